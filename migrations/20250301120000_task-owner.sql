@@ -0,0 +1,7 @@
+---- Task ownership
+
+-- Tasks created before this migration have no owner and are only reachable with the root context.
+ALTER TABLE task
+  ADD COLUMN owner_id BIGINT REFERENCES "user"(id) ON DELETE CASCADE;
+
+CREATE INDEX task_owner_id_idx ON task (owner_id);
@@ -0,0 +1,18 @@
+---- Audit columns (creator id, creation time, modifier id, modification time)
+
+-- The defaults backfill the existing rows and the rows inserted outside of the model layer,
+-- `base::create` and `base::update` always set the values.
+
+-- User
+ALTER TABLE "user"
+  ADD COLUMN cid BIGINT NOT NULL DEFAULT 0,
+  ADD COLUMN ctime timestamp with time zone NOT NULL DEFAULT now(),
+  ADD COLUMN mid BIGINT NOT NULL DEFAULT 0,
+  ADD COLUMN mtime timestamp with time zone NOT NULL DEFAULT now();
+
+-- Task
+ALTER TABLE task
+  ADD COLUMN cid BIGINT NOT NULL DEFAULT 0,
+  ADD COLUMN ctime timestamp with time zone NOT NULL DEFAULT now(),
+  ADD COLUMN mid BIGINT NOT NULL DEFAULT 0,
+  ADD COLUMN mtime timestamp with time zone NOT NULL DEFAULT now();
@@ -0,0 +1,18 @@
+---- Optimistic locking
+
+-- Increment the version of the row on each update, `base::update_if_version` only updates the
+-- row when the version provided by the client is still the current one.
+CREATE FUNCTION increment_version() RETURNS trigger AS $$
+BEGIN
+  NEW.version := OLD.version + 1;
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Task
+ALTER TABLE task
+  ADD COLUMN version BIGINT NOT NULL DEFAULT 0;
+
+CREATE TRIGGER task_increment_version
+  BEFORE UPDATE ON task
+  FOR EACH ROW EXECUTE FUNCTION increment_version();
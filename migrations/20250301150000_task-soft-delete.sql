@@ -0,0 +1,9 @@
+---- Soft delete
+
+-- `is_deleted` is derived from `deleted_at` so it can be compared with a bound value.
+ALTER TABLE task
+  ADD COLUMN deleted_at timestamp with time zone,
+  ADD COLUMN is_deleted BOOLEAN GENERATED ALWAYS AS (deleted_at IS NOT NULL) STORED;
+
+-- Used by the purge job.
+CREATE INDEX task_deleted_at_idx ON task (deleted_at) WHERE deleted_at IS NOT NULL;
@@ -0,0 +1,2 @@
+-- Mail address of the user, used to deliver the password reset tokens.
+ALTER TABLE "user" ADD COLUMN email varchar(256) UNIQUE;
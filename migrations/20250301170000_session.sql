@@ -0,0 +1,27 @@
+---- Sessions, one per logged in device, holding the refresh token
+
+CREATE TABLE session (
+  id BIGINT GENERATED BY DEFAULT AS IDENTITY (START WITH 1000) PRIMARY KEY,
+  user_id BIGINT NOT NULL REFERENCES "user"(id) ON DELETE CASCADE,
+
+  -- Refresh token, only the hash of its secret is stored and it changes on each refresh.
+  -- The previous hash is kept to detect the reuse of a rotated token.
+  refresh_hash varchar(64) NOT NULL,
+  prev_refresh_hash varchar(64),
+
+  -- Client
+  device varchar(128),
+  ip varchar(64),
+  user_agent varchar(512),
+
+  expires_at timestamp with time zone NOT NULL,
+  last_seen_at timestamp with time zone NOT NULL DEFAULT now(),
+
+  -- Timestamps
+  cid BIGINT NOT NULL DEFAULT 0,
+  ctime timestamp with time zone NOT NULL DEFAULT now(),
+  mid BIGINT NOT NULL DEFAULT 0,
+  mtime timestamp with time zone NOT NULL DEFAULT now()
+);
+
+CREATE INDEX session_user_id_idx ON session (user_id);
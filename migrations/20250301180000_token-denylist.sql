@@ -0,0 +1,14 @@
+---- Denylist of the web tokens revoked before their expiration, by the logoff
+
+CREATE TABLE token_denylist (
+  -- Jwt `jti`, or hash of the custom token signature.
+  token_id varchar(64) PRIMARY KEY,
+  user_id BIGINT NOT NULL REFERENCES "user"(id) ON DELETE CASCADE,
+
+  -- Expiration of the token, the entry is useless after it.
+  expires_at timestamp with time zone NOT NULL,
+
+  ctime timestamp with time zone NOT NULL DEFAULT now()
+);
+
+CREATE INDEX token_denylist_expires_at_idx ON token_denylist (expires_at);
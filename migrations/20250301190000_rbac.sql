@@ -0,0 +1,40 @@
+---- Roles and their permissions, the permissions of the user roles are loaded in the ctx
+
+CREATE TABLE role (
+  id BIGINT GENERATED BY DEFAULT AS IDENTITY (START WITH 1000) PRIMARY KEY,
+  name varchar(64) NOT NULL UNIQUE
+);
+
+CREATE TABLE role_permission (
+  role_id BIGINT NOT NULL REFERENCES role(id) ON DELETE CASCADE,
+  -- `resource:action`, `task:write` for example.
+  permission varchar(64) NOT NULL,
+
+  PRIMARY KEY (role_id, permission)
+);
+
+CREATE TABLE user_role (
+  user_id BIGINT NOT NULL REFERENCES "user"(id) ON DELETE CASCADE,
+  role_id BIGINT NOT NULL REFERENCES role(id) ON DELETE CASCADE,
+
+  PRIMARY KEY (user_id, role_id)
+);
+
+-- Default roles, `user` is given to every registered user.
+INSERT INTO role (name) VALUES ('user'), ('reader');
+
+INSERT INTO role_permission (role_id, permission)
+SELECT role.id, permission.name
+FROM role, unnest(ARRAY['task:read', 'task:write']) AS permission(name)
+WHERE role.name = 'user';
+
+INSERT INTO role_permission (role_id, permission)
+SELECT role.id, 'task:read'
+FROM role
+WHERE role.name = 'reader';
+
+-- The existing users keep their access.
+INSERT INTO user_role (user_id, role_id)
+SELECT "user".id, role.id
+FROM "user", role
+WHERE role.name = 'user';
@@ -0,0 +1,22 @@
+---- Api keys of the machine clients, sent in the `X-API-Key` header
+
+CREATE TABLE api_key (
+  id BIGINT GENERATED BY DEFAULT AS IDENTITY (START WITH 1000) PRIMARY KEY,
+  user_id BIGINT NOT NULL REFERENCES "user"(id) ON DELETE CASCADE,
+
+  name varchar(128) NOT NULL,
+  -- Only the hash of the key secret is stored, the key is shown once at creation.
+  key_hash varchar(64) NOT NULL,
+  -- Permissions the key is limited to, among the ones of the user.
+  scopes varchar(64)[] NOT NULL,
+  -- No expiration when null.
+  expires_at timestamp with time zone,
+
+  -- Timestamps
+  cid BIGINT NOT NULL DEFAULT 0,
+  ctime timestamp with time zone NOT NULL DEFAULT now(),
+  mid BIGINT NOT NULL DEFAULT 0,
+  mtime timestamp with time zone NOT NULL DEFAULT now()
+);
+
+CREATE INDEX api_key_user_id_idx ON api_key (user_id);
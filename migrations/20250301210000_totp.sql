@@ -0,0 +1,24 @@
+---- Two-factor authentication with a totp (RFC 6238) authenticator app
+
+ALTER TABLE "user"
+  -- Base32 secret shared with the authenticator app, set by the setup.
+  ADD COLUMN totp_secret varchar(64),
+  -- Only required on login once a first code has been verified.
+  ADD COLUMN totp_enabled BOOLEAN NOT NULL DEFAULT false,
+  -- Last time step used, a code can not be replayed.
+  ADD COLUMN totp_last_step BIGINT;
+
+-- One-time codes to log in when the authenticator app is lost.
+CREATE TABLE recovery_code (
+  id BIGINT GENERATED BY DEFAULT AS IDENTITY (START WITH 1000) PRIMARY KEY,
+  user_id BIGINT NOT NULL REFERENCES "user"(id) ON DELETE CASCADE,
+
+  -- Only the hash of the code is stored, the codes are shown once when 2fa is enabled.
+  -- Hashed with the password scheme, `#scheme#kid#hashed`.
+  code_hash varchar(256) NOT NULL,
+  used_at timestamp with time zone,
+
+  ctime timestamp with time zone NOT NULL DEFAULT now()
+);
+
+CREATE INDEX recovery_code_user_id_idx ON recovery_code (user_id);
@@ -0,0 +1,11 @@
+---- Failed logins, per username and per ip, to lock them out after too many failures
+
+CREATE TABLE login_throttle (
+  -- `username:<username>` or `ip:<ip>`
+  key text PRIMARY KEY,
+
+  -- Consecutive failures, reset once the window since the last one has passed.
+  failures INTEGER NOT NULL,
+  last_failure_at timestamp with time zone NOT NULL,
+  locked_until timestamp with time zone
+);
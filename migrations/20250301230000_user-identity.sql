@@ -0,0 +1,19 @@
+---- Identities of the users at the OpenID Connect providers (SSO)
+
+CREATE TABLE user_identity (
+  id BIGINT GENERATED BY DEFAULT AS IDENTITY (START WITH 1000) PRIMARY KEY,
+  user_id BIGINT NOT NULL REFERENCES "user"(id) ON DELETE CASCADE,
+
+  -- Name of the provider in the config.
+  provider varchar(64) NOT NULL,
+  -- `sub` claim of the id token, the id of the user at the provider.
+  subject varchar(255) NOT NULL,
+  -- Mail address given by the provider when linked, for information only.
+  email varchar(256),
+
+  ctime timestamp with time zone NOT NULL DEFAULT now(),
+
+  UNIQUE (provider, subject)
+);
+
+CREATE INDEX user_identity_user_id_idx ON user_identity (user_id);
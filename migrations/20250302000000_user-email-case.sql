@@ -0,0 +1,3 @@
+-- The emails are compared case insensitively, `Jane@Corp.com` and `jane@corp.com` are one address.
+ALTER TABLE "user" DROP CONSTRAINT user_email_key;
+CREATE UNIQUE INDEX user_email_key ON "user" (lower(email));
@@ -0,0 +1,75 @@
+//! Commands for the administrators, run with `axum-demo <command>` instead of starting the server.
+
+use crate::config::config;
+use crate::error::{Error, Result};
+use crate::model::ModelManager;
+use crate::model::user::{PwdKeyUsage, UserBmc};
+
+pub const COMMAND_PWD_KEY_USAGE: &str = "pwd-key-usage";
+
+/// Name and description of the admin commands.
+pub const COMMANDS: &[(&str, &str)] = &[(
+    COMMAND_PWD_KEY_USAGE,
+    "Print the number of password hashes per pwd key",
+)];
+
+pub fn is_command(command: &str) -> bool {
+    COMMANDS.iter().any(|(name, _)| *name == command)
+}
+
+pub fn usage() -> String {
+    let mut usage =
+        "Usage: axum-demo [COMMAND]\n\nStart the server when no command is given.\n\nCommands:\n"
+            .to_string();
+    for (name, description) in COMMANDS {
+        usage.push_str(&format!("  {name:<16}{description}\n"));
+    }
+    usage
+}
+
+pub async fn run(command: &str) -> Result<()> {
+    match command {
+        COMMAND_PWD_KEY_USAGE => pwd_key_usage().await,
+        _ => Err(Error::AdminUnknownCommand(command.to_string())),
+    }
+}
+
+/// Print the number of password hashes per pwd key.
+///
+/// There is no migrate command: the hashes can not be rewrapped without the clear password,
+/// each one is rewrapped with the active key on the next login of its user.
+/// A retired key can be removed from `SERVICE_PWD_KEYS` once it is not used anymore,
+/// the users still on it then have to reset their password.
+async fn pwd_key_usage() -> Result<()> {
+    let mm = ModelManager::new(&config().postgres).await?;
+    let active_kid = &config().crypt.pwd_keys.active().kid;
+
+    println!("{:<12} {:>10}  STATUS", "KID", "COUNT");
+    for PwdKeyUsage { kid, count } in UserBmc::pwd_key_usage(&mm).await? {
+        let status = match &kid {
+            Some(kid) if kid == active_kid => "active",
+            _ => "rewrapped on next login",
+        };
+        println!(
+            "{:<12} {count:>10}  {status}",
+            kid.as_deref().unwrap_or("-")
+        );
+    }
+
+    Ok(())
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_command_ok() {
+        // -- Exec & Check
+        assert!(is_command(COMMAND_PWD_KEY_USAGE));
+        assert!(!is_command("--port"));
+        assert!(usage().contains(COMMAND_PWD_KEY_USAGE));
+    }
+}
+// endregion: --- Tests
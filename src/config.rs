@@ -1,6 +1,8 @@
+use crate::crypt::{Key, Keyring};
 use crate::error::{Error, Result};
 use dotenvy::dotenv;
 use secrecy::SecretBox;
+use serde::Deserialize;
 use std::env;
 use std::str::FromStr;
 use std::sync::OnceLock;
@@ -27,6 +29,11 @@ pub struct Config {
     pub postgres: Postgres,
     pub tracing: Tracing,
     pub crypt: Crypt,
+    pub auth: AuthSettings,
+    pub rpc: Rpc,
+    pub trash: Trash,
+    pub mailer: MailerSettings,
+    pub oidc: OidcSettings,
 }
 
 pub enum Env {
@@ -61,9 +68,154 @@ pub struct Postgres {
 }
 
 pub struct Crypt {
-    pub pwd_key: Vec<u8>,
-    pub token_key: Vec<u8>,
+    pub pwd_keys: Keyring,
+    /// Argon2id memory cost, in KiB.
+    pub pwd_argon2_m_cost: u32,
+    /// Argon2id number of iterations.
+    pub pwd_argon2_t_cost: u32,
+    pub token_keys: Keyring,
+    /// Lifetime of the access tokens, kept short as they can not be revoked.
     pub token_duration_sec: f64,
+    /// Lifetime of the refresh tokens, and so of the sessions.
+    pub refresh_duration_sec: f64,
+    pub pwd_reset_duration_sec: f64,
+    /// Lifetime of the token returned by the login of a user with 2fa, until its code is verified.
+    pub mfa_pending_duration_sec: f64,
+    /// Issuer shown by the authenticator apps next to the account.
+    pub totp_issuer: String,
+    /// Format of the new web tokens, both formats are always accepted.
+    pub token_format: TokenFormat,
+    pub token_jwt: JwtSettings,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TokenFormat {
+    /// `kid.ident.exp.sign` token, only verifiable by this service.
+    Custom,
+    /// Standard JWS token, verifiable by any service knowing the key.
+    Jwt,
+}
+
+impl FromStr for TokenFormat {
+    type Err = ();
+
+    fn from_str(value: &str) -> core::result::Result<Self, Self::Err> {
+        match value.to_lowercase().trim() {
+            "custom" => Ok(TokenFormat::Custom),
+            "jwt" => Ok(TokenFormat::Jwt),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JwtAlg {
+    /// HMAC signature with the `token_keys`.
+    Hs512,
+    /// Ed25519 signature with the `ed25519_keys`, the public keys are published in the jwks.
+    EdDsa,
+}
+
+impl FromStr for JwtAlg {
+    type Err = ();
+
+    fn from_str(value: &str) -> core::result::Result<Self, Self::Err> {
+        match value.trim() {
+            "HS512" => Ok(JwtAlg::Hs512),
+            "EdDSA" => Ok(JwtAlg::EdDsa),
+            _ => Err(()),
+        }
+    }
+}
+
+pub struct JwtSettings {
+    pub alg: JwtAlg,
+    /// Keyring of PKCS#8 DER Ed25519 private keys, required with [JwtAlg::EdDsa].
+    pub ed25519_keys: Option<Keyring>,
+    pub iss: String,
+    pub aud: String,
+}
+
+#[derive(Debug)]
+pub struct AuthSettings {
+    /// Source read first when a request carries both a cookie and an `Authorization` header.
+    pub token_precedence: TokenSource,
+    /// Interval at which the revoked tokens are reloaded from the db and the expired ones purged.
+    pub denylist_sync_interval_sec: u64,
+    /// Failed logins of a username before it is locked.
+    pub lockout_threshold: i32,
+    /// Failed logins from an ip before it is locked, higher as an ip can be shared.
+    pub lockout_ip_threshold: i32,
+    /// First lockout duration, doubled on each further failure.
+    pub lockout_base_sec: i64,
+    pub lockout_max_sec: i64,
+    /// Time without failure after which the failures are forgotten.
+    pub lockout_window_sec: i64,
+    /// Interval at which the forgotten failures, with no lockout left, are purged.
+    pub lockout_purge_interval_sec: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TokenSource {
+    /// The `auth-token` cookie, set by the login for the browsers.
+    Cookie,
+    /// The `Authorization: Bearer <token>` header, for the other clients.
+    Bearer,
+}
+
+impl FromStr for TokenSource {
+    type Err = ();
+
+    fn from_str(value: &str) -> core::result::Result<Self, Self::Err> {
+        match value.to_lowercase().trim() {
+            "cookie" => Ok(TokenSource::Cookie),
+            "bearer" => Ok(TokenSource::Bearer),
+            _ => Err(()),
+        }
+    }
+}
+
+pub struct Rpc {
+    pub max_batch_size: usize,
+}
+
+pub struct Trash {
+    /// How long a soft deleted row is kept before being purged.
+    pub retention_sec: u64,
+    pub purge_interval_sec: u64,
+}
+
+pub struct MailerSettings {
+    pub from: String,
+    /// Directory where the local mailer writes the mails, they are only logged when not set.
+    pub out_dir: Option<String>,
+}
+
+pub struct OidcSettings {
+    /// Public url of the service, the callback given to the providers is
+    /// `{redirect_base_url}/account/oidc/{provider}/callback`.
+    /// Required when a provider is configured, empty otherwise.
+    pub redirect_base_url: String,
+    /// Lifetime of the login flow, between the redirection to the provider and its callback.
+    pub flow_duration_sec: f64,
+    pub providers: Vec<OidcProvider>,
+}
+
+/// An OpenID Connect provider, its endpoints are discovered from its issuer.
+#[derive(Debug, Deserialize)]
+pub struct OidcProvider {
+    /// Name in the login and callback routes.
+    pub name: String,
+    /// Url of the issuer, `/.well-known/openid-configuration` is appended for the discovery.
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: SecretBox<String>,
+    #[serde(default = "default_oidc_scopes")]
+    pub scopes: Vec<String>,
+}
+
+fn default_oidc_scopes() -> Vec<String> {
+    ["openid", "email", "profile"].map(String::from).to_vec()
 }
 
 pub struct Tracing {
@@ -95,10 +247,55 @@ impl Config {
                 otel_enabled: get_env_parse("OTEL_ENABLED")?,
             },
             crypt: Crypt {
-                pwd_key: get_env_b64u_as_u8s("SERVICE_PWD_KEY")?,
-                token_key: get_env_b64u_as_u8s("SERVICE_TOKEN_KEY")?,
+                pwd_keys: get_env_keyring("SERVICE_PWD_KEYS", "SERVICE_PWD_KEY")?,
+                pwd_argon2_m_cost: get_env_parse_or("SERVICE_PWD_ARGON2_M_COST", 19 * 1024)?,
+                pwd_argon2_t_cost: get_env_parse_or("SERVICE_PWD_ARGON2_T_COST", 2)?,
+                token_keys: get_env_keyring("SERVICE_TOKEN_KEYS", "SERVICE_TOKEN_KEY")?,
                 token_duration_sec: get_env_parse("SERVICE_TOKEN_DURATION_SEC")?,
+                refresh_duration_sec: get_env_parse_or(
+                    "SERVICE_REFRESH_DURATION_SEC",
+                    30. * 24. * 3600.,
+                )?,
+                pwd_reset_duration_sec: get_env_parse_or("SERVICE_PWD_RESET_DURATION_SEC", 900.)?,
+                mfa_pending_duration_sec: get_env_parse_or(
+                    "SERVICE_MFA_PENDING_DURATION_SEC",
+                    300.,
+                )?,
+                totp_issuer: get_env_parse_or("SERVICE_TOTP_ISSUER", "axum-demo".to_string())?,
+                token_format: get_env_parse_or("SERVICE_TOKEN_FORMAT", TokenFormat::Custom)?,
+                token_jwt: get_jwt_settings()?,
+            },
+            auth: AuthSettings {
+                token_precedence: get_env_parse_or(
+                    "SERVICE_AUTH_TOKEN_PRECEDENCE",
+                    TokenSource::Bearer,
+                )?,
+                denylist_sync_interval_sec: get_env_parse_or(
+                    "SERVICE_AUTH_DENYLIST_SYNC_INTERVAL_SEC",
+                    60,
+                )?,
+                lockout_threshold: get_env_parse_or("SERVICE_AUTH_LOCKOUT_THRESHOLD", 5)?,
+                lockout_ip_threshold: get_env_parse_or("SERVICE_AUTH_LOCKOUT_IP_THRESHOLD", 20)?,
+                lockout_base_sec: get_env_parse_or("SERVICE_AUTH_LOCKOUT_BASE_SEC", 30)?,
+                lockout_max_sec: get_env_parse_or("SERVICE_AUTH_LOCKOUT_MAX_SEC", 3600)?,
+                lockout_window_sec: get_env_parse_or("SERVICE_AUTH_LOCKOUT_WINDOW_SEC", 3600)?,
+                lockout_purge_interval_sec: get_env_parse_or(
+                    "SERVICE_AUTH_LOCKOUT_PURGE_INTERVAL_SEC",
+                    3600,
+                )?,
             },
+            rpc: Rpc {
+                max_batch_size: get_env_parse_or("SERVICE_RPC_MAX_BATCH_SIZE", 20)?,
+            },
+            trash: Trash {
+                retention_sec: get_env_parse_or("SERVICE_TRASH_RETENTION_SEC", 30 * 24 * 3600)?,
+                purge_interval_sec: get_env_parse_or("SERVICE_TRASH_PURGE_INTERVAL_SEC", 3600)?,
+            },
+            mailer: MailerSettings {
+                from: get_env_parse_or("SERVICE_MAILER_FROM", "no-reply@localhost".to_string())?,
+                out_dir: env::var("SERVICE_MAILER_OUT_DIR").ok(),
+            },
+            oidc: get_oidc_settings()?,
         })
     }
 }
@@ -112,10 +309,96 @@ fn get_env_parse<T: FromStr>(name: &'static str) -> Result<T> {
     val.parse::<T>().map_err(|_| Error::ConfigWrongFormat(name))
 }
 
+/// Same as [get_env_parse] but fallback to the default value when the variable is not set.
+fn get_env_parse_or<T: FromStr>(name: &'static str, default: T) -> Result<T> {
+    parse_or(name, env::var(name).ok(), default)
+}
+
+/// Parse the value of the variable `name`, the default is used when it is not set.
+fn parse_or<T: FromStr>(name: &'static str, val: Option<String>, default: T) -> Result<T> {
+    match val {
+        Some(val) => val.parse::<T>().map_err(|_| Error::ConfigWrongFormat(name)),
+        None => Ok(default),
+    }
+}
+
 fn get_env_b64u_as_u8s(name: &'static str) -> Result<Vec<u8>> {
     base64_url::decode(&get_env(name)?).map_err(|_| Error::ConfigWrongFormat(name))
 }
 
+/// Keys listed as `kid:key_b64u,...` in `name`, the first one being the active key.
+///
+/// Fallback to the single key of `legacy_name` (without kid), which gets the kid `00`.
+fn get_env_keyring(name: &'static str, legacy_name: &'static str) -> Result<Keyring> {
+    match env::var(name) {
+        Ok(val) => val.parse().map_err(|_| Error::ConfigWrongFormat(name)),
+        Err(_) => Keyring::new(vec![Key {
+            kid: "00".to_string(),
+            secret: get_env_b64u_as_u8s(legacy_name)?,
+        }])
+        .map_err(|_| Error::ConfigWrongFormat(legacy_name)),
+    }
+}
+
+fn get_jwt_settings() -> Result<JwtSettings> {
+    let alg = get_env_parse_or("SERVICE_TOKEN_JWT_ALG", JwtAlg::Hs512)?;
+    let ed25519_keys = match env::var("SERVICE_TOKEN_ED25519_KEYS") {
+        Ok(val) => Some(
+            val.parse()
+                .map_err(|_| Error::ConfigWrongFormat("SERVICE_TOKEN_ED25519_KEYS"))?,
+        ),
+        Err(_) if alg == JwtAlg::EdDsa => {
+            return Err(Error::ConfigMissingEnv("SERVICE_TOKEN_ED25519_KEYS"));
+        }
+        Err(_) => None,
+    };
+
+    Ok(JwtSettings {
+        alg,
+        ed25519_keys,
+        iss: get_env_parse_or("SERVICE_TOKEN_JWT_ISS", "axum-demo".to_string())?,
+        aud: get_env_parse_or("SERVICE_TOKEN_JWT_AUD", "axum-demo".to_string())?,
+    })
+}
+
+fn get_oidc_settings() -> Result<OidcSettings> {
+    let providers = get_oidc_providers()?;
+    // The callback must not be built from the request, its `Host` is chosen by the client.
+    let redirect_base_url = match env::var("SERVICE_OIDC_REDIRECT_BASE_URL") {
+        Ok(val) => parse_redirect_base_url(&val)
+            .ok_or(Error::ConfigWrongFormat("SERVICE_OIDC_REDIRECT_BASE_URL"))?,
+        Err(_) if !providers.is_empty() => {
+            return Err(Error::ConfigMissingEnv("SERVICE_OIDC_REDIRECT_BASE_URL"));
+        }
+        Err(_) => String::new(),
+    };
+
+    Ok(OidcSettings {
+        redirect_base_url,
+        flow_duration_sec: get_env_parse_or("SERVICE_OIDC_FLOW_DURATION_SEC", 600.)?,
+        providers,
+    })
+}
+
+/// Json array of [OidcProvider], no provider when not set.
+fn get_oidc_providers() -> Result<Vec<OidcProvider>> {
+    match env::var("SERVICE_OIDC_PROVIDERS") {
+        Ok(val) => serde_json::from_str(&val)
+            .map_err(|_| Error::ConfigWrongFormat("SERVICE_OIDC_PROVIDERS")),
+        Err(_) => Ok(Vec::new()),
+    }
+}
+
+/// Absolute http(s) url, without its trailing slash.
+fn parse_redirect_base_url(val: &str) -> Option<String> {
+    let url = reqwest::Url::parse(val).ok()?;
+    if !matches!(url.scheme(), "http" | "https") || !url.has_host() {
+        return None;
+    }
+
+    Some(val.trim_end_matches('/').to_string())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -144,6 +427,35 @@ mod tests {
         }
     }
 
+    // The value is given to `parse_or`, removing the variable from the process env
+    // would race with the tests loading the config.
+    #[test]
+    fn test_parse_or() {
+        let result = parse_or::<usize>("SERVICE_RPC_MAX_BATCH_SIZE", None, 20)
+            .expect("Failed to get the default value");
+        assert_eq!(result, 20);
+
+        let result = parse_or::<usize>("SERVICE_RPC_MAX_BATCH_SIZE", Some("5".to_string()), 20)
+            .expect("Failed to parse the value");
+        assert_eq!(result, 5);
+
+        let result = parse_or::<usize>("SERVICE_RPC_MAX_BATCH_SIZE", Some("five".to_string()), 20);
+        assert!(matches!(
+            result,
+            Err(Error::ConfigWrongFormat("SERVICE_RPC_MAX_BATCH_SIZE"))
+        ));
+    }
+
+    #[test]
+    fn test_parse_redirect_base_url() {
+        assert_eq!(
+            parse_redirect_base_url("https://demo.example.com/"),
+            Some("https://demo.example.com".to_string())
+        );
+        assert_eq!(parse_redirect_base_url("demo.example.com"), None);
+        assert_eq!(parse_redirect_base_url("ftp://demo.example.com"), None);
+    }
+
     #[test]
     fn test_get_env_b64u_as_u8s() {
         unsafe {
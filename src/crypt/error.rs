@@ -8,10 +8,22 @@ pub enum Error {
     // -- Key
     #[error("Key fail")]
     KeyFailHmac,
+    #[error("The keyring is not valid : {0}")]
+    KeyringInvalid(String),
+    #[error("No key with the kid {0:?} in the keyring")]
+    KeyNotFound(String),
 
     // -- Pwd
     #[error("The passwords didn't match")]
     PwdNotMatching,
+    #[error("The password does not start with a `#scheme#` prefix")]
+    PwdWithSchemeFailedParse,
+    #[error("The password scheme {0:?} is not supported")]
+    PwdSchemeNotFound(String),
+    #[error("Failed to hash the password : {0}")]
+    PwdFailHash(String),
+    #[error("Failed to run the password hashing on the blocking thread pool")]
+    PwdFailSpawnBlock,
 
     // -- Token
     #[error("The token has an invalid format")]
@@ -26,4 +38,18 @@ pub enum Error {
     TokenExpNotIso,
     #[error("The token has expired")]
     TokenExpired,
+    #[error("Failed to encode the jwt : {0}")]
+    TokenJwtFailEncode(String),
+    #[error("The jwt is not valid : {0}")]
+    TokenJwtInvalid(String),
+
+    // -- Totp
+    #[error("The totp secret is not valid base32")]
+    TotpSecretInvalid,
+    #[error("The totp code is not matching")]
+    TotpCodeNotMatching,
+    #[error("The totp code has already been used")]
+    TotpCodeAlreadyUsed,
+    #[error("The number of digits of the totp codes does not fit in a usize")]
+    TotpDigitsInvalid,
 }
@@ -0,0 +1,350 @@
+use crate::config::{JwtAlg, config};
+use crate::crypt::{Error, Keyring, Result, ct_eq, now_utc, sha256_into_b64u};
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+    OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse,
+};
+use jsonwebtoken::{
+    Algorithm, DecodingKey, EncodingKey, Header, Validation, decode, decode_header, encode,
+};
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+use time::Duration;
+use uuid::Uuid;
+
+// region:    --- Claims
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JwtClaims {
+    pub sub: String, // Identifier (username for example).
+    pub exp: i64,    // Expiration, unix timestamp in seconds.
+    pub iat: i64,    // Issued at, unix timestamp in seconds.
+    pub jti: String, // Unique id of the token.
+    pub iss: String,
+    pub aud: String,
+    /// Fingerprint of the user token salt, so rotating the salt revokes the token,
+    /// as for the custom token. The salt itself is never published.
+    pub sfp: String,
+}
+
+impl JwtClaims {
+    pub fn validate_salt(&self, salt: &str) -> Result<()> {
+        if !ct_eq(&self.sfp, &sha256_into_b64u(salt)) {
+            return Err(Error::TokenSignatureNotMatching);
+        }
+
+        Ok(())
+    }
+}
+
+// endregion: --- Claims
+
+// region:    --- Web Jwt Gen and Validation
+
+pub fn generate_web_jwt(user: &str, salt: &str) -> Result<String> {
+    _generate_jwt(
+        user,
+        config().crypt.token_duration_sec,
+        salt,
+        jwt_keys().active(),
+    )
+}
+
+/// Validate the signature, expiration, issuer and audience of the jwt.
+///
+/// The salt is validated afterward with [JwtClaims::validate_salt],
+/// once the user of the `sub` claim is loaded.
+pub fn validate_web_jwt(token: &str) -> Result<JwtClaims> {
+    _validate_jwt(token, jwt_keys())
+}
+
+/// Public keys of the asymmetric algorithms, empty with HS512 as the keys are secret.
+pub fn jwks() -> JwkSet {
+    JwkSet {
+        keys: jwt_keys()
+            .keys
+            .iter()
+            .filter_map(|key| key.jwk.clone())
+            .collect(),
+    }
+}
+
+// endregion: --- Web Jwt Gen and Validation
+
+// region:    --- Jwt Keys
+
+/// Signing and verifying keys of one kid.
+struct JwtKey {
+    kid: String,
+    alg: Algorithm,
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+    /// Public key to publish, `None` for the symmetric algorithms.
+    jwk: Option<Jwk>,
+}
+
+/// Same order as the keyring, the first key is the active one.
+struct JwtKeys {
+    keys: Vec<JwtKey>,
+}
+
+impl JwtKeys {
+    fn new(alg: JwtAlg, keyring: &Keyring) -> Result<Self> {
+        let keys = keyring
+            .keys()
+            .map(|key| match alg {
+                JwtAlg::Hs512 => Ok(JwtKey {
+                    kid: key.kid.clone(),
+                    alg: Algorithm::HS512,
+                    encoding: EncodingKey::from_secret(&key.secret),
+                    decoding: DecodingKey::from_secret(&key.secret),
+                    jwk: None,
+                }),
+                JwtAlg::EdDsa => {
+                    let key_pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(&key.secret)
+                        .map_err(|ex| {
+                            Error::KeyringInvalid(format!(
+                                "key {:?} is not an Ed25519 PKCS#8 key: {ex}",
+                                key.kid
+                            ))
+                        })?;
+                    let public_key = key_pair.public_key().as_ref();
+
+                    Ok(JwtKey {
+                        kid: key.kid.clone(),
+                        alg: Algorithm::EdDSA,
+                        encoding: EncodingKey::from_ed_der(&key.secret),
+                        // Despite its name, expects the raw public key.
+                        decoding: DecodingKey::from_ed_der(public_key),
+                        jwk: Some(Jwk {
+                            common: CommonParameters {
+                                public_key_use: Some(PublicKeyUse::Signature),
+                                key_algorithm: Some(KeyAlgorithm::EdDSA),
+                                key_id: Some(key.kid.clone()),
+                                ..Default::default()
+                            },
+                            algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                                key_type: OctetKeyPairType::OctetKeyPair,
+                                curve: EllipticCurve::Ed25519,
+                                x: base64_url::encode(public_key),
+                            }),
+                        }),
+                    })
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { keys })
+    }
+
+    fn active(&self) -> &JwtKey {
+        &self.keys[0]
+    }
+
+    fn get(&self, kid: &str) -> Result<&JwtKey> {
+        self.keys
+            .iter()
+            .find(|key| key.kid == kid)
+            .ok_or_else(|| Error::KeyNotFound(kid.to_string()))
+    }
+}
+
+/// Keys of the configured algorithm, built once.
+fn jwt_keys() -> &'static JwtKeys {
+    static INSTANCE: OnceLock<JwtKeys> = OnceLock::new();
+
+    INSTANCE.get_or_init(|| {
+        let crypt = &config().crypt;
+        let keyring = match crypt.token_jwt.alg {
+            JwtAlg::Hs512 => &crypt.token_keys,
+            JwtAlg::EdDsa => crypt
+                .token_jwt
+                .ed25519_keys
+                .as_ref()
+                .expect("ed25519_keys are checked when loading the config"),
+        };
+
+        JwtKeys::new(crypt.token_jwt.alg, keyring)
+            .unwrap_or_else(|ex| panic!("FATAL - WHILE BUILDING JWT KEYS - Cause: {ex:?}"))
+    })
+}
+
+// endregion: --- Jwt Keys
+
+// region:    --- (private) Jwt Gen and Validation
+
+fn _generate_jwt(ident: &str, duration_sec: f64, salt: &str, key: &JwtKey) -> Result<String> {
+    let jwt_config = &config().crypt.token_jwt;
+    let now = now_utc();
+    let claims = JwtClaims {
+        sub: ident.to_string(),
+        exp: (now + Duration::seconds_f64(duration_sec)).unix_timestamp(),
+        iat: now.unix_timestamp(),
+        jti: Uuid::new_v4().to_string(),
+        iss: jwt_config.iss.clone(),
+        aud: jwt_config.aud.clone(),
+        sfp: sha256_into_b64u(salt),
+    };
+
+    let mut header = Header::new(key.alg);
+    header.kid = Some(key.kid.clone());
+
+    encode(&header, &claims, &key.encoding).map_err(|ex| Error::TokenJwtFailEncode(ex.to_string()))
+}
+
+/// The algorithm comes from the key and never from the token header,
+/// a token can not downgrade the algorithm used to verify it.
+fn _validate_jwt(token: &str, keys: &JwtKeys) -> Result<JwtClaims> {
+    let header = decode_header(token).map_err(|_| Error::TokenInvalidFormat)?;
+    let kid = header.kid.ok_or(Error::TokenInvalidFormat)?;
+    let key = keys.get(&kid)?;
+
+    let jwt_config = &config().crypt.token_jwt;
+    let mut validation = Validation::new(key.alg);
+    validation.leeway = 0;
+    validation.set_required_spec_claims(&["exp", "sub", "iss", "aud"]);
+    validation.set_issuer(&[&jwt_config.iss]);
+    validation.set_audience(&[&jwt_config.aud]);
+
+    let token_data =
+        decode::<JwtClaims>(token, &key.decoding, &validation).map_err(|ex| match ex.kind() {
+            ErrorKind::ExpiredSignature => Error::TokenExpired,
+            ErrorKind::InvalidSignature => Error::TokenSignatureNotMatching,
+            _ => Error::TokenJwtInvalid(ex.to_string()),
+        })?;
+
+    Ok(token_data.claims)
+}
+
+// endregion: --- (private) Jwt Gen and Validation
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    const FX_ED25519_KEYS: &str = "02:MC4CAQAwBQYDK2VwBCIEIMT3qD0TzDNCRWvIbKEcWNx_-yrSGWSCD0yX5wuUiMZ6,\
+        01:MC4CAQAwBQYDK2VwBCIEID6ImOsZsMr_uiNnzWTpd4Sk1Nhb5Wwg4OwRFMjm7XAZ";
+
+    #[test]
+    fn test_validate_jwt_ok_hs512() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_salt = "pepper";
+        let fx_keys = JwtKeys::new(JwtAlg::Hs512, &"01:SGVsbG8gV29ybGQ".parse()?)?;
+        let fx_token = _generate_jwt("user_one", 60., fx_salt, fx_keys.active())?;
+
+        // -- Exec
+        let claims = _validate_jwt(&fx_token, &fx_keys)?;
+
+        // -- Check
+        assert_eq!(claims.sub, "user_one");
+        assert_eq!(claims.iss, config().crypt.token_jwt.iss);
+        claims.validate_salt(fx_salt)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_jwt_ok_eddsa_retired_key() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_keys = JwtKeys::new(JwtAlg::EdDsa, &FX_ED25519_KEYS.parse()?)?;
+        let fx_token = _generate_jwt("user_one", 60., "pepper", fx_keys.get("01")?)?;
+
+        // -- Exec
+        let claims = _validate_jwt(&fx_token, &fx_keys)?;
+
+        // -- Check
+        assert_eq!(claims.sub, "user_one");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_jwt_err_expired() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_keys = JwtKeys::new(JwtAlg::Hs512, &"01:SGVsbG8gV29ybGQ".parse()?)?;
+        let fx_token = _generate_jwt("user_one", -10., "pepper", fx_keys.active())?;
+
+        // -- Exec
+        let res = _validate_jwt(&fx_token, &fx_keys);
+
+        // -- Check
+        assert!(
+            matches!(res, Err(Error::TokenExpired)),
+            "Should have matched `Err(Error::TokenExpired)` but was `{res:?}`"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_jwt_err_other_alg() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_ed_keys = JwtKeys::new(JwtAlg::EdDsa, &FX_ED25519_KEYS.parse()?)?;
+        let fx_hs_keys = JwtKeys::new(JwtAlg::Hs512, &"02:SGVsbG8gV29ybGQ".parse()?)?;
+        let fx_token = _generate_jwt("user_one", 60., "pepper", fx_hs_keys.active())?;
+
+        // -- Exec
+        let res = _validate_jwt(&fx_token, &fx_ed_keys);
+
+        // -- Check
+        assert!(
+            matches!(res, Err(Error::TokenJwtInvalid(_))),
+            "Should have matched `Err(Error::TokenJwtInvalid)` but was `{res:?}`"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_salt_err_rotated() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_keys = JwtKeys::new(JwtAlg::Hs512, &"01:SGVsbG8gV29ybGQ".parse()?)?;
+        let fx_token = _generate_jwt("user_one", 60., "pepper", fx_keys.active())?;
+        let claims = _validate_jwt(&fx_token, &fx_keys)?;
+
+        // -- Exec
+        let res = claims.validate_salt("new-pepper");
+
+        // -- Check
+        assert!(
+            matches!(res, Err(Error::TokenSignatureNotMatching)),
+            "Should have matched `Err(Error::TokenSignatureNotMatching)` but was `{res:?}`"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwt_keys_new_ok_eddsa_jwk() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_keys = JwtKeys::new(JwtAlg::EdDsa, &FX_ED25519_KEYS.parse()?)?;
+
+        // -- Exec
+        let jwk = fx_keys
+            .active()
+            .jwk
+            .clone()
+            .ok_or(Error::KeyNotFound("02".into()))?;
+        let decoding = match &jwk.algorithm {
+            AlgorithmParameters::OctetKeyPair(params) => {
+                DecodingKey::from_ed_components(&params.x)?
+            }
+            _ => panic!("Should have been an OctetKeyPair jwk"),
+        };
+        let fx_token = _generate_jwt("user_one", 60., "pepper", fx_keys.active())?;
+
+        // -- Check
+        // A third party must be able to verify the token with the published key only.
+        assert_eq!(jwk.common.key_id.as_deref(), Some("02"));
+        let mut validation = Validation::new(Algorithm::EdDSA);
+        validation.set_audience(&[&config().crypt.token_jwt.aud]);
+        decode::<JwtClaims>(&fx_token, &decoding, &validation)?;
+
+        Ok(())
+    }
+}
+// endregion: --- Tests
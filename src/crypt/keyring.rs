@@ -0,0 +1,140 @@
+use crate::crypt::{Error, Result};
+use std::str::FromStr;
+
+/// A secret key and its id, the id is carried by the tokens and password hashes it signed.
+#[derive(Clone)]
+pub struct Key {
+    pub kid: String,
+    pub secret: Vec<u8>,
+}
+
+/// All the keys of one purpose (token or password).
+///
+/// String format: `kid:key_b64u,kid:key_b64u,...`
+/// - The first key is the active key, used to sign the new tokens and hashes.
+/// - The following ones are retired keys, only used to validate, from the newest to the oldest.
+pub struct Keyring {
+    keys: Vec<Key>,
+}
+
+impl Keyring {
+    pub fn new(keys: Vec<Key>) -> Result<Self> {
+        if keys.is_empty() {
+            return Err(Error::KeyringInvalid("no key".to_string()));
+        }
+        for (i, key) in keys.iter().enumerate() {
+            if !is_valid_kid(&key.kid) {
+                return Err(Error::KeyringInvalid(format!("invalid kid {:?}", key.kid)));
+            }
+            if keys[..i].iter().any(|other| other.kid == key.kid) {
+                return Err(Error::KeyringInvalid(format!(
+                    "duplicate kid {:?}",
+                    key.kid
+                )));
+            }
+        }
+
+        Ok(Self { keys })
+    }
+
+    pub fn active(&self) -> &Key {
+        &self.keys[0]
+    }
+
+    /// The oldest key, used for the data signed before the key ids were introduced.
+    pub fn oldest(&self) -> &Key {
+        &self.keys[self.keys.len() - 1]
+    }
+
+    pub fn get(&self, kid: &str) -> Result<&Key> {
+        self.keys
+            .iter()
+            .find(|key| key.kid == kid)
+            .ok_or_else(|| Error::KeyNotFound(kid.to_string()))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &Key> {
+        self.keys.iter()
+    }
+}
+
+impl FromStr for Keyring {
+    type Err = Error;
+
+    fn from_str(keyring_str: &str) -> Result<Self> {
+        let keys = keyring_str
+            .split(',')
+            .map(|key_str| {
+                let (kid, secret_b64u) = key_str
+                    .trim()
+                    .split_once(':')
+                    .ok_or_else(|| Error::KeyringInvalid("missing `kid:` prefix".to_string()))?;
+                let secret = base64_url::decode(secret_b64u)
+                    .map_err(|_| Error::KeyringInvalid(format!("key {kid:?} is not base64url")))?;
+
+                Ok(Key {
+                    kid: kid.to_string(),
+                    secret,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Self::new(keys)
+    }
+}
+
+/// Kids are written as is in the tokens and password hashes, so they can not contain separators.
+fn is_valid_kid(kid: &str) -> bool {
+    !kid.is_empty()
+        && kid
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-'))
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn test_keyring_from_str_ok() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_keyring_str = "02:SGVsbG8gV29ybGQ, 01:SGVsbG8";
+
+        // -- Exec
+        let keyring: Keyring = fx_keyring_str.parse()?;
+
+        // -- Check
+        assert_eq!(keyring.active().kid, "02");
+        assert_eq!(keyring.oldest().kid, "01");
+        assert_eq!(keyring.get("01")?.secret, b"Hello");
+        assert!(matches!(keyring.get("03"), Err(Error::KeyNotFound(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_keyring_from_str_err_invalid() {
+        // -- Setup & Fixtures
+        let fx_keyring_strs = [
+            "",
+            "SGVsbG8",
+            "01:SGVsbG8,01:SGVsbG8gV29ybGQ",
+            "0.1:SGVsbG8",
+            "01:not base64",
+        ];
+
+        // -- Exec & Check
+        for fx_keyring_str in fx_keyring_strs {
+            assert!(
+                matches!(
+                    fx_keyring_str.parse::<Keyring>(),
+                    Err(Error::KeyringInvalid(_))
+                ),
+                "Should have failed to parse {fx_keyring_str:?}"
+            );
+        }
+    }
+}
+// endregion: --- Tests
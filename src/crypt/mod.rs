@@ -1,16 +1,22 @@
 // region:    --- Modules
 mod error;
+pub mod jwt;
+mod keyring;
 pub mod pwd;
 pub mod token;
+pub mod totp;
 mod utils;
 
 pub use self::error::{Error, Result};
+pub use self::keyring::{Key, Keyring};
 pub use self::utils::*;
 
 use hmac::{Hmac, Mac};
-use sha2::Sha512;
+use sha2::{Digest, Sha256, Sha512};
+use subtle::ConstantTimeEq;
 // endregion: --- Modules
 
+#[derive(Clone)]
 pub struct EncryptContent {
     pub content: String, // Clear content.
     pub salt: String,    // Clear salt.
@@ -35,6 +41,19 @@ pub fn encrypt_into_b64u(key: &[u8], enc_content: &EncryptContent) -> Result<Str
     Ok(result)
 }
 
+/// SHA-256 of a random secret (refresh token), base64url encoded.
+///
+/// Not suited to passwords, which need a slow hash (see [pwd]).
+pub fn sha256_into_b64u(content: &str) -> String {
+    base64_url::encode(&Sha256::digest(content.as_bytes()))
+}
+
+/// Compare two secrets (signatures, hashes) in constant time,
+/// so the response time does not tell how many leading bytes are matching.
+pub fn ct_eq(a: &str, b: &str) -> bool {
+    a.as_bytes().ct_eq(b.as_bytes()).into()
+}
+
 // region:    --- Tests
 #[cfg(test)]
 mod tests {
@@ -62,5 +81,14 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_ct_eq_ok() {
+        // -- Exec & Check
+        assert!(ct_eq("some-sign-b64u", "some-sign-b64u"));
+        assert!(!ct_eq("some-sign-b64u", "some-sign-b64v"));
+        assert!(!ct_eq("some-sign-b64u", "some-sign"));
+        assert!(!ct_eq("", "some-sign"));
+    }
 }
 // endregion: --- Tests
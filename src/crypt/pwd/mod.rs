@@ -0,0 +1,276 @@
+//! Multi-scheme password hashing.
+//!
+//! String format: `#scheme_name#kid#hashed`, the scheme and key are read back from the prefix
+//! on validation, so hashes made with an older scheme or a retired key keep working
+//! and are upgraded on the next login.
+//!
+//! Hashes made before the key ids were introduced (`#scheme_name#hashed`)
+//! are validated with the oldest key of the keyring.
+
+// region:    --- Modules
+mod scheme;
+
+pub use self::scheme::SchemeStatus;
+
+use self::scheme::{DEFAULT_SCHEME, get_scheme};
+use crate::config::config;
+use crate::crypt::{EncryptContent, Error, Result};
+use tokio::sync::OnceCell;
+use uuid::Uuid;
+// endregion: --- Modules
+
+/// Hash the password with the default scheme.
+///
+/// Hashing is cpu bound, it runs on the blocking thread pool.
+pub async fn hash_pwd(to_hash: EncryptContent) -> Result<String> {
+    tokio::task::spawn_blocking(move || hash_for_scheme(DEFAULT_SCHEME, &to_hash))
+        .await
+        .map_err(|_| Error::PwdFailSpawnBlock)?
+}
+
+/// Validate if an EncryptContent matches the reference password hash.
+///
+/// [SchemeStatus::Outdated] is returned when the hash was made with another scheme
+/// than the default one or with a retired key, the caller should then hash the password again.
+pub async fn validate_pwd(to_hash: EncryptContent, pwd_ref: String) -> Result<SchemeStatus> {
+    let PwdParts {
+        scheme_name,
+        kid,
+        hashed,
+    } = pwd_ref.parse()?;
+
+    let pwd_keys = &config().crypt.pwd_keys;
+    let key = match &kid {
+        Some(kid) => pwd_keys.get(kid)?,
+        None => pwd_keys.oldest(),
+    };
+
+    let scheme_status = if scheme_name == DEFAULT_SCHEME && key.kid == pwd_keys.active().kid {
+        SchemeStatus::Ok
+    } else {
+        SchemeStatus::Outdated
+    };
+
+    tokio::task::spawn_blocking(move || get_scheme(&scheme_name)?.validate(&to_hash, &hashed, key))
+        .await
+        .map_err(|_| Error::PwdFailSpawnBlock)??;
+
+    Ok(scheme_status)
+}
+
+/// Do the same work as [validate_pwd] against a dummy hash, always fails with `PwdNotMatching`.
+///
+/// Used when the user does not exist or has no password,
+/// so the response time does not disclose which usernames exist.
+pub async fn validate_pwd_dummy(content: String) -> Result<()> {
+    let pwd_ref = dummy_pwd().await?;
+    let to_hash = EncryptContent {
+        content,
+        salt: Uuid::nil().to_string(),
+    };
+
+    validate_pwd(to_hash, pwd_ref).await?;
+
+    // Can only happen if the dummy password has been guessed.
+    Err(Error::PwdNotMatching)
+}
+
+/// Hash of a random password with the default scheme, computed once.
+async fn dummy_pwd() -> Result<String> {
+    static INSTANCE: OnceCell<String> = OnceCell::const_new();
+
+    INSTANCE
+        .get_or_try_init(|| {
+            hash_pwd(EncryptContent {
+                content: Uuid::new_v4().to_string(),
+                salt: Uuid::nil().to_string(),
+            })
+        })
+        .await
+        .cloned()
+}
+
+/// Hashes are always made with the active key.
+fn hash_for_scheme(scheme_name: &str, to_hash: &EncryptContent) -> Result<String> {
+    let key = config().crypt.pwd_keys.active();
+    let hashed = get_scheme(scheme_name)?.hash(to_hash, key)?;
+
+    Ok(format!("#{scheme_name}#{}#{hashed}", key.kid))
+}
+
+// region:    --- PwdParts
+
+/// A stored password split in its scheme name, key id and hashed part.
+struct PwdParts {
+    scheme_name: String,
+    /// `None` for the hashes made before the key ids were introduced.
+    kid: Option<String>,
+    hashed: String,
+}
+
+impl std::str::FromStr for PwdParts {
+    type Err = Error;
+
+    fn from_str(pwd_with_scheme: &str) -> Result<Self> {
+        let (scheme_name, rest) = pwd_with_scheme
+            .strip_prefix('#')
+            .and_then(|rest| rest.split_once('#'))
+            .ok_or(Error::PwdWithSchemeFailedParse)?;
+
+        // The hashed parts of all the schemes never contain a `#`.
+        let (kid, hashed) = match rest.split_once('#') {
+            Some((kid, hashed)) => (Some(kid.to_string()), hashed),
+            None => (None, rest),
+        };
+
+        Ok(Self {
+            scheme_name: scheme_name.to_string(),
+            kid,
+            hashed: hashed.to_string(),
+        })
+    }
+}
+
+// endregion: --- PwdParts
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[tokio::test]
+    async fn test_validate_pwd_ok() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_salt = "a1b2c3d4-e5f6-4a1b-8c2d-3e4f5a6b7c8d";
+        let fx_to_hash = EncryptContent {
+            content: "hello world".to_string(),
+            salt: fx_salt.to_string(),
+        };
+
+        // -- Exec
+        let pwd_hashed = hash_pwd(fx_to_hash.clone()).await?;
+        let scheme_status = validate_pwd(fx_to_hash, pwd_hashed.clone()).await?;
+
+        // -- Check
+        assert!(pwd_hashed.starts_with(&format!("#{DEFAULT_SCHEME}#")));
+        assert!(matches!(scheme_status, SchemeStatus::Ok));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_validate_pwd_ok_outdated() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_to_hash = EncryptContent {
+            content: "hello world".to_string(),
+            salt: "some salt".to_string(),
+        };
+        let fx_pwd_hashed = hash_for_scheme("01", &fx_to_hash)?;
+
+        // -- Exec
+        let scheme_status = validate_pwd(fx_to_hash, fx_pwd_hashed).await?;
+
+        // -- Check
+        assert!(matches!(scheme_status, SchemeStatus::Outdated));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_validate_pwd_err_not_matching() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_salt = "a1b2c3d4-e5f6-4a1b-8c2d-3e4f5a6b7c8d";
+        let fx_pwd_hashed = hash_pwd(EncryptContent {
+            content: "hello world".to_string(),
+            salt: fx_salt.to_string(),
+        })
+        .await?;
+
+        // -- Exec
+        let res = validate_pwd(
+            EncryptContent {
+                content: "hello moon".to_string(),
+                salt: fx_salt.to_string(),
+            },
+            fx_pwd_hashed,
+        )
+        .await;
+
+        // -- Check
+        assert!(
+            matches!(res, Err(Error::PwdNotMatching)),
+            "Should have matched `Err(Error::PwdNotMatching)` but was `{res:?}`"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_validate_pwd_dummy_err_not_matching() -> Result<()> {
+        // -- Exec
+        let res = validate_pwd_dummy("hello world".to_string()).await;
+
+        // -- Check
+        assert!(
+            matches!(res, Err(Error::PwdNotMatching)),
+            "Should have matched `Err(Error::PwdNotMatching)` but was `{res:?}`"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_validate_pwd_ok_without_kid() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_to_hash = EncryptContent {
+            content: "hello world".to_string(),
+            salt: "some salt".to_string(),
+        };
+        let fx_key = config().crypt.pwd_keys.oldest();
+        let fx_pwd_hashed = format!("#01#{}", get_scheme("01")?.hash(&fx_to_hash, fx_key)?);
+
+        // -- Exec
+        let scheme_status = validate_pwd(fx_to_hash, fx_pwd_hashed).await?;
+
+        // -- Check
+        assert!(matches!(scheme_status, SchemeStatus::Outdated));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pwd_parts_from_str_ok() -> Result<()> {
+        // -- Exec
+        let with_kid: PwdParts = "#02#k1#$argon2id$v=19$m=19456,t=2,p=1$c2FsdA$aGFzaA".parse()?;
+        let without_kid: PwdParts = "#01#aGFzaA".parse()?;
+
+        // -- Check
+        assert_eq!(with_kid.scheme_name, "02");
+        assert_eq!(with_kid.kid.as_deref(), Some("k1"));
+        assert_eq!(
+            with_kid.hashed,
+            "$argon2id$v=19$m=19456,t=2,p=1$c2FsdA$aGFzaA"
+        );
+        assert_eq!(without_kid.scheme_name, "01");
+        assert_eq!(without_kid.kid, None);
+        assert_eq!(without_kid.hashed, "aGFzaA");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pwd_parts_from_str_err_no_scheme() {
+        // -- Exec & Check
+        for fx_pwd in ["hashed", "#01hashed", "01#hashed"] {
+            assert!(
+                matches!(
+                    fx_pwd.parse::<PwdParts>(),
+                    Err(Error::PwdWithSchemeFailedParse)
+                ),
+                "Should have failed to parse {fx_pwd}"
+            );
+        }
+    }
+}
+// endregion: --- Tests
@@ -0,0 +1,32 @@
+// region:    --- Modules
+mod scheme_01;
+mod scheme_02;
+
+use crate::crypt::{EncryptContent, Error, Key, Result};
+// endregion: --- Modules
+
+/// Scheme used to hash the new passwords.
+pub const DEFAULT_SCHEME: &str = "02";
+
+#[derive(Debug)]
+pub enum SchemeStatus {
+    /// The password hash uses the default scheme.
+    Ok,
+    /// The password hash uses an older scheme or a retired key, and should be hashed again.
+    Outdated,
+}
+
+/// A password hashing scheme, keyed with one of the `pwd_keys`.
+pub trait Scheme: Send + Sync {
+    fn hash(&self, to_hash: &EncryptContent, key: &Key) -> Result<String>;
+
+    fn validate(&self, to_hash: &EncryptContent, pwd_ref: &str, key: &Key) -> Result<()>;
+}
+
+pub fn get_scheme(scheme_name: &str) -> Result<&'static dyn Scheme> {
+    match scheme_name {
+        "01" => Ok(&scheme_01::Scheme01),
+        "02" => Ok(&scheme_02::Scheme02),
+        _ => Err(Error::PwdSchemeNotFound(scheme_name.to_string())),
+    }
+}
@@ -0,0 +1,23 @@
+use crate::crypt::pwd::scheme::Scheme;
+use crate::crypt::{EncryptContent, Error, Key, Result, ct_eq, encrypt_into_b64u};
+
+/// HMAC-SHA512 of the password and salt with the server pwd key.
+///
+/// Only kept to validate the existing passwords, they are upgraded on the next login.
+pub struct Scheme01;
+
+impl Scheme for Scheme01 {
+    fn hash(&self, to_hash: &EncryptContent, key: &Key) -> Result<String> {
+        encrypt_into_b64u(&key.secret, to_hash)
+    }
+
+    fn validate(&self, to_hash: &EncryptContent, pwd_ref: &str, key: &Key) -> Result<()> {
+        let pwd = self.hash(to_hash, key)?;
+
+        if ct_eq(&pwd, pwd_ref) {
+            Ok(())
+        } else {
+            Err(Error::PwdNotMatching)
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use crate::config::config;
+use crate::crypt::pwd::scheme::Scheme;
+use crate::crypt::{EncryptContent, Error, Key, Result};
+use argon2::password_hash::SaltString;
+use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Argon2id with the server pwd key as secret, the hash is stored as a PHC string.
+///
+/// The memory and time costs come from the config, and are stored in the PHC string,
+/// so changing them does not break the existing passwords.
+pub struct Scheme02;
+
+impl Scheme for Scheme02 {
+    fn hash(&self, to_hash: &EncryptContent, key: &Key) -> Result<String> {
+        let salt = SaltString::encode_b64(to_hash.salt.as_bytes())
+            .map_err(|ex| Error::PwdFailHash(ex.to_string()))?;
+
+        let pwd = argon2(key)?
+            .hash_password(to_hash.content.as_bytes(), &salt)
+            .map_err(|ex| Error::PwdFailHash(ex.to_string()))?;
+
+        Ok(pwd.to_string())
+    }
+
+    fn validate(&self, to_hash: &EncryptContent, pwd_ref: &str, key: &Key) -> Result<()> {
+        let pwd_ref = PasswordHash::new(pwd_ref).map_err(|_| Error::PwdWithSchemeFailedParse)?;
+
+        argon2(key)?
+            .verify_password(to_hash.content.as_bytes(), &pwd_ref)
+            .map_err(|_| Error::PwdNotMatching)
+    }
+}
+
+/// One Argon2 instance per key of the `pwd_keys` keyring, built once.
+fn argon2(key: &Key) -> Result<&'static Argon2<'static>> {
+    static INSTANCES: OnceLock<HashMap<String, Argon2<'static>>> = OnceLock::new();
+
+    let instances = INSTANCES.get_or_init(|| {
+        let crypt = &config().crypt;
+        crypt
+            .pwd_keys
+            .keys()
+            .map(|key| {
+                let argon2 = Params::new(
+                    crypt.pwd_argon2_m_cost,
+                    crypt.pwd_argon2_t_cost,
+                    Params::DEFAULT_P_COST,
+                    None,
+                )
+                .and_then(|params| {
+                    Argon2::new_with_secret(
+                        &key.secret,
+                        Algorithm::Argon2id,
+                        Version::V0x13,
+                        params,
+                    )
+                })
+                .unwrap_or_else(|ex| panic!("FATAL - WHILE BUILDING ARGON2 - Cause: {ex:?}"));
+
+                (key.kid.clone(), argon2)
+            })
+            .collect()
+    });
+
+    instances
+        .get(&key.kid)
+        .ok_or_else(|| Error::KeyNotFound(key.kid.clone()))
+}
@@ -1,16 +1,20 @@
-use crate::config::config;
+use crate::config::{TokenFormat, config};
+use crate::crypt::jwt::{JwtClaims, generate_web_jwt, validate_web_jwt};
 use crate::crypt::{
-    EncryptContent, Error, Result, b64u_decode, b64u_encode, encrypt_into_b64u, now_utc,
-    now_utc_plus_sec_str, parse_utc,
+    EncryptContent, Error, Key, Keyring, Result, b64u_decode, b64u_encode, ct_eq,
+    encrypt_into_b64u, now_utc, now_utc_plus_sec_str, parse_utc, sha256_into_b64u,
 };
+use rand::RngCore;
 use std::fmt::Display;
 use std::str::FromStr;
+use time::OffsetDateTime;
 
 // region:    --- Token Type
 
-/// String format: `ident_b64u.exp_b64u.sign_b64u`
+/// String format: `kid.ident_b64u.exp_b64u.sign_b64u`
 #[derive(Debug)]
 pub struct Token {
+    pub kid: String,       // Id of the key which signed the token.
     pub ident: String,     // Identifier (username for example).
     pub exp: String,       // Expiration date in Rfc3339.
     pub sign_b64u: String, // Signature, base64url encoded.
@@ -21,12 +25,14 @@ impl FromStr for Token {
 
     fn from_str(token_str: &str) -> std::result::Result<Self, Self::Err> {
         let splits: Vec<&str> = token_str.split('.').collect();
-        if splits.len() != 3 {
+        if splits.len() != 4 {
             return Err(Error::TokenInvalidFormat);
         }
-        let (ident_b64u, exp_b64u, sign_b64u) = (splits[0], splits[1], splits[2]);
+        let (kid, ident_b64u, exp_b64u, sign_b64u) = (splits[0], splits[1], splits[2], splits[3]);
 
         Ok(Self {
+            kid: kid.to_string(),
+
             ident: b64u_decode(ident_b64u).map_err(|_| Error::TokenCannotDecodeIdent)?,
 
             exp: b64u_decode(exp_b64u).map_err(|_| Error::TokenCannotDecodeExp)?,
@@ -40,7 +46,8 @@ impl Display for Token {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "{}.{}.{}",
+            "{}.{}.{}.{}",
+            self.kid,
             b64u_encode(&self.ident),
             b64u_encode(&self.exp),
             self.sign_b64u
@@ -50,6 +57,192 @@ impl Display for Token {
 
 // endregion: --- Token Type
 
+// region:    --- Web Token
+
+/// A web token in any of the supported formats,
+/// so changing the configured format does not log out the users.
+#[derive(Debug)]
+pub enum WebToken {
+    Custom(Token),
+    /// Claims of a jwt, whose signature, expiration, issuer and audience are already validated.
+    Jwt(JwtClaims),
+}
+
+impl WebToken {
+    pub fn ident(&self) -> &str {
+        match self {
+            WebToken::Custom(token) => &token.ident,
+            WebToken::Jwt(claims) => &claims.sub,
+        }
+    }
+
+    /// Unique id of the token, to revoke it: the jwt `jti` or the hash of the custom token signature.
+    pub fn id(&self) -> String {
+        match self {
+            WebToken::Custom(token) => sha256_into_b64u(&token.sign_b64u),
+            WebToken::Jwt(claims) => claims.jti.clone(),
+        }
+    }
+
+    pub fn exp(&self) -> Result<OffsetDateTime> {
+        match self {
+            WebToken::Custom(token) => parse_utc(&token.exp).map_err(|_| Error::TokenExpNotIso),
+            WebToken::Jwt(claims) => {
+                OffsetDateTime::from_unix_timestamp(claims.exp).map_err(|_| Error::TokenExpNotIso)
+            }
+        }
+    }
+
+    /// Validate what depends on the user token salt.
+    pub fn validate(&self, salt: &str) -> Result<()> {
+        match self {
+            WebToken::Custom(token) => validate_web_token(token, salt),
+            WebToken::Jwt(claims) => claims.validate_salt(salt),
+        }
+    }
+}
+
+impl FromStr for WebToken {
+    type Err = Error;
+
+    /// A jwt has three parts, the custom token four.
+    fn from_str(token_str: &str) -> std::result::Result<Self, Self::Err> {
+        if token_str.split('.').count() == 3 {
+            validate_web_jwt(token_str).map(WebToken::Jwt)
+        } else {
+            token_str.parse().map(WebToken::Custom)
+        }
+    }
+}
+
+/// New web token, in the format selected by the config.
+pub fn generate_web_token_str(user: &str, salt: &str) -> Result<String> {
+    match config().crypt.token_format {
+        TokenFormat::Custom => generate_web_token(user, salt).map(|token| token.to_string()),
+        TokenFormat::Jwt => generate_web_jwt(user, salt),
+    }
+}
+
+// endregion: --- Web Token
+
+// region:    --- Refresh Token
+
+/// String format: `session_id.secret_b64u`
+///
+/// The secret is random, only its hash is stored with the session.
+#[derive(Debug)]
+pub struct RefreshToken {
+    pub session_id: i64,
+    pub secret_b64u: String,
+}
+
+impl RefreshToken {
+    /// New random secret, to be hashed into the session before its id is known.
+    pub fn new_secret() -> String {
+        new_random_secret()
+    }
+
+    pub fn hash(&self) -> String {
+        Self::hash_secret(&self.secret_b64u)
+    }
+
+    /// Hash stored with the session.
+    pub fn hash_secret(secret_b64u: &str) -> String {
+        sha256_into_b64u(secret_b64u)
+    }
+}
+
+impl FromStr for RefreshToken {
+    type Err = Error;
+
+    fn from_str(token_str: &str) -> std::result::Result<Self, Self::Err> {
+        let (session_id, secret_b64u) =
+            token_str.split_once('.').ok_or(Error::TokenInvalidFormat)?;
+        let session_id = session_id.parse().map_err(|_| Error::TokenInvalidFormat)?;
+        if secret_b64u.is_empty() {
+            return Err(Error::TokenInvalidFormat);
+        }
+
+        Ok(Self {
+            session_id,
+            secret_b64u: secret_b64u.to_string(),
+        })
+    }
+}
+
+impl Display for RefreshToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.session_id, self.secret_b64u)
+    }
+}
+
+// endregion: --- Refresh Token
+
+// region:    --- Api Key Token
+
+const API_KEY_PREFIX: &str = "ak_";
+
+/// String format: `ak_key_id.secret_b64u`
+///
+/// The prefix makes the keys easy to spot, in a leaked file for example.
+#[derive(Debug)]
+pub struct ApiKeyToken {
+    pub key_id: i64,
+    pub secret_b64u: String,
+}
+
+impl ApiKeyToken {
+    /// New random secret, to be hashed into the api key before its id is known.
+    pub fn new_secret() -> String {
+        new_random_secret()
+    }
+
+    pub fn hash(&self) -> String {
+        Self::hash_secret(&self.secret_b64u)
+    }
+
+    /// Hash stored with the api key.
+    pub fn hash_secret(secret_b64u: &str) -> String {
+        sha256_into_b64u(secret_b64u)
+    }
+}
+
+impl FromStr for ApiKeyToken {
+    type Err = Error;
+
+    fn from_str(token_str: &str) -> std::result::Result<Self, Self::Err> {
+        let (key_id, secret_b64u) = token_str
+            .strip_prefix(API_KEY_PREFIX)
+            .and_then(|token_str| token_str.split_once('.'))
+            .ok_or(Error::TokenInvalidFormat)?;
+        let key_id = key_id.parse().map_err(|_| Error::TokenInvalidFormat)?;
+        if secret_b64u.is_empty() {
+            return Err(Error::TokenInvalidFormat);
+        }
+
+        Ok(Self {
+            key_id,
+            secret_b64u: secret_b64u.to_string(),
+        })
+    }
+}
+
+impl Display for ApiKeyToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{API_KEY_PREFIX}{}.{}", self.key_id, self.secret_b64u)
+    }
+}
+
+// endregion: --- Api Key Token
+
+/// 32 random bytes, base64url encoded.
+fn new_random_secret() -> String {
+    let mut secret = [0u8; 32];
+    rand::rng().fill_bytes(&mut secret);
+
+    base64_url::encode(&secret)
+}
+
 // region:    --- Web Token Gen and Validation
 
 pub fn generate_web_token(user: &str, salt: &str) -> Result<Token> {
@@ -58,41 +251,138 @@ pub fn generate_web_token(user: &str, salt: &str) -> Result<Token> {
         user,
         config.crypt.token_duration_sec,
         salt,
-        &config.crypt.token_key,
+        config.crypt.token_keys.active(),
     )
 }
 
 pub fn validate_web_token(origin_token: &Token, salt: &str) -> Result<()> {
     let config = &config();
-    _validate_token_sign_and_exp(origin_token, salt, &config.crypt.token_key)?;
+    _validate_token_sign_and_exp(origin_token, salt, &config.crypt.token_keys)?;
 
     Ok(())
 }
 
 // endregion: --- Web Token Gen and Validation
 
+// region:    --- Pwd Reset Token Gen and Validation
+
+/// Prefix of the reset token salt, so a web token can never be accepted as a reset token.
+const PWD_RESET_SALT_PREFIX: &str = "pwd-reset.";
+
+/// The caller must pass a salt that changes once the reset is done
+/// (the user token salt and current password), which makes the token single-use.
+pub fn generate_pwd_reset_token(user: &str, salt: &str) -> Result<Token> {
+    let config = &config();
+    _generate_token(
+        user,
+        config.crypt.pwd_reset_duration_sec,
+        &format!("{PWD_RESET_SALT_PREFIX}{salt}"),
+        config.crypt.token_keys.active(),
+    )
+}
+
+pub fn validate_pwd_reset_token(origin_token: &Token, salt: &str) -> Result<()> {
+    let config = &config();
+    _validate_token_sign_and_exp(
+        origin_token,
+        &format!("{PWD_RESET_SALT_PREFIX}{salt}"),
+        &config.crypt.token_keys,
+    )?;
+
+    Ok(())
+}
+
+// endregion: --- Pwd Reset Token Gen and Validation
+
+// region:    --- Mfa Pending Token Gen and Validation
+
+/// Prefix of the mfa pending token salt, so it can never be accepted as a web token.
+const MFA_PENDING_SALT_PREFIX: &str = "mfa-pending.";
+
+/// Token of a login whose password is validated, until the second factor is verified.
+pub fn generate_mfa_pending_token(ident: &str, salt: &str) -> Result<Token> {
+    let config = &config();
+    _generate_token(
+        ident,
+        config.crypt.mfa_pending_duration_sec,
+        &format!("{MFA_PENDING_SALT_PREFIX}{salt}"),
+        config.crypt.token_keys.active(),
+    )
+}
+
+pub fn validate_mfa_pending_token(origin_token: &Token, salt: &str) -> Result<()> {
+    let config = &config();
+    _validate_token_sign_and_exp(
+        origin_token,
+        &format!("{MFA_PENDING_SALT_PREFIX}{salt}"),
+        &config.crypt.token_keys,
+    )?;
+
+    Ok(())
+}
+
+// endregion: --- Mfa Pending Token Gen and Validation
+
+// region:    --- Oidc Flow Token Gen and Validation
+
+/// Salt of the oidc flow token, there is no user yet to salt it with.
+const OIDC_FLOW_SALT: &str = "oidc-flow.";
+
+/// Token of an oidc login, from the redirection to the provider until its callback.
+///
+/// The ident is readable by the client, which holds it in an http only cookie.
+pub fn generate_oidc_flow_token(ident: &str) -> Result<Token> {
+    let config = &config();
+    _generate_token(
+        ident,
+        config.oidc.flow_duration_sec,
+        OIDC_FLOW_SALT,
+        config.crypt.token_keys.active(),
+    )
+}
+
+pub fn validate_oidc_flow_token(origin_token: &Token) -> Result<()> {
+    let config = &config();
+    _validate_token_sign_and_exp(origin_token, OIDC_FLOW_SALT, &config.crypt.token_keys)?;
+
+    Ok(())
+}
+
+// endregion: --- Oidc Flow Token Gen and Validation
+
 // region:    --- (private) Token Gen and Validation
 
-fn _generate_token(ident: &str, duration_sec: f64, salt: &str, key: &[u8]) -> Result<Token> {
-    // -- Compute the two first components.
+/// New tokens are always signed with the active key of the keyring.
+fn _generate_token(ident: &str, duration_sec: f64, salt: &str, key: &Key) -> Result<Token> {
+    // -- Compute the three first components.
+    let kid = key.kid.clone();
     let ident = ident.to_string();
     let exp = now_utc_plus_sec_str(duration_sec);
 
-    // -- Sign the two first components.
-    let sign_b64u = _token_sign_into_b64u(&ident, &exp, salt, key)?;
+    // -- Sign the three first components.
+    let sign_b64u = _token_sign_into_b64u(&kid, &ident, &exp, salt, &key.secret)?;
 
     Ok(Token {
+        kid,
         ident,
         exp,
         sign_b64u,
     })
 }
 
-fn _validate_token_sign_and_exp(origin_token: &Token, salt: &str, key: &[u8]) -> Result<()> {
+/// Tokens signed with a retired key stay valid as long as the key is in the keyring.
+fn _validate_token_sign_and_exp(origin_token: &Token, salt: &str, keyring: &Keyring) -> Result<()> {
     // -- Validate signature.
-    let new_sign_b64u = _token_sign_into_b64u(&origin_token.ident, &origin_token.exp, salt, key)?;
+    let key = keyring.get(&origin_token.kid)?;
+    let new_sign_b64u = _token_sign_into_b64u(
+        &origin_token.kid,
+        &origin_token.ident,
+        &origin_token.exp,
+        salt,
+        &key.secret,
+    )?;
 
-    if new_sign_b64u != origin_token.sign_b64u {
+    if !ct_eq(&new_sign_b64u, &origin_token.sign_b64u) {
         return Err(Error::TokenSignatureNotMatching);
     }
 
@@ -109,8 +399,14 @@ fn _validate_token_sign_and_exp(origin_token: &Token, salt: &str, key: &[u8]) ->
 
 /// Create token signature from token parts
 /// and salt.
-fn _token_sign_into_b64u(ident: &str, exp: &str, salt: &str, key: &[u8]) -> Result<String> {
-    let content = format!("{}.{}", b64u_encode(ident), b64u_encode(exp));
+fn _token_sign_into_b64u(
+    kid: &str,
+    ident: &str,
+    exp: &str,
+    salt: &str,
+    key: &[u8],
+) -> Result<String> {
+    let content = format!("{kid}.{}.{}", b64u_encode(ident), b64u_encode(exp));
     let signature = encrypt_into_b64u(
         key,
         &EncryptContent {
@@ -135,8 +431,9 @@ mod tests {
     #[test]
     fn test_token_display_ok() -> Result<()> {
         // -- Fixtures
-        let fx_token_str = "ZngtaWRlbnQtMDE.MjAyMy0wNS0xN1QxNTozMDowMFo.some-sign-b64u-encoded";
+        let fx_token_str = "01.ZngtaWRlbnQtMDE.MjAyMy0wNS0xN1QxNTozMDowMFo.some-sign-b64u-encoded";
         let fx_token = Token {
+            kid: "01".to_string(),
             ident: "fx-ident-01".to_string(),
             exp: "2023-05-17T15:30:00Z".to_string(),
             sign_b64u: "some-sign-b64u-encoded".to_string(),
@@ -151,8 +448,9 @@ mod tests {
     #[test]
     fn test_token_from_str_ok() -> Result<()> {
         // -- Fixtures
-        let fx_token_str = "ZngtaWRlbnQtMDE.MjAyMy0wNS0xN1QxNTozMDowMFo.some-sign-b64u-encoded";
+        let fx_token_str = "01.ZngtaWRlbnQtMDE.MjAyMy0wNS0xN1QxNTozMDowMFo.some-sign-b64u-encoded";
         let fx_token = Token {
+            kid: "01".to_string(),
             ident: "fx-ident-01".to_string(),
             exp: "2023-05-17T15:30:00Z".to_string(),
             sign_b64u: "some-sign-b64u-encoded".to_string(),
@@ -167,13 +465,71 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_refresh_token_from_str_ok() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_token = RefreshToken {
+            session_id: 1000,
+            secret_b64u: RefreshToken::new_secret(),
+        };
+
+        // -- Exec
+        let token: RefreshToken = fx_token.to_string().parse()?;
+
+        // -- Check
+        assert_eq!(token.session_id, 1000);
+        assert_eq!(token.hash(), fx_token.hash());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_refresh_token_from_str_err_invalid() {
+        // -- Setup & Fixtures
+        let fx_token_strs = ["", "1000", "1000.", "abc.some-secret"];
+
+        // -- Exec & Check
+        for fx_token_str in fx_token_strs {
+            assert!(
+                matches!(
+                    fx_token_str.parse::<RefreshToken>(),
+                    Err(Error::TokenInvalidFormat)
+                ),
+                "Should have failed to parse {fx_token_str:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_api_key_token_from_str_err_invalid() {
+        // -- Setup & Fixtures
+        let fx_token_strs = [
+            "",
+            "1000.some-secret",
+            "ak_1000",
+            "ak_1000.",
+            "ak_abc.some-secret",
+        ];
+
+        // -- Exec & Check
+        for fx_token_str in fx_token_strs {
+            assert!(
+                matches!(
+                    fx_token_str.parse::<ApiKeyToken>(),
+                    Err(Error::TokenInvalidFormat)
+                ),
+                "Should have failed to parse {fx_token_str:?}"
+            );
+        }
+    }
+
     #[test]
     fn test_validate_web_token_ok() -> Result<()> {
         // -- Setup & Fixtures
         let fx_user = "user_one";
         let fx_salt = "pepper";
         let fx_duration_sec = 0.02; // 20ms
-        let token_key = &config().crypt.token_key;
+        let token_key = config().crypt.token_keys.active();
         let fx_token = _generate_token(fx_user, fx_duration_sec, fx_salt, token_key)?;
 
         // -- Exec
@@ -192,7 +548,7 @@ mod tests {
         let fx_user = "user_one";
         let fx_salt = "pepper";
         let fx_duration_sec = 0.01; // 10ms
-        let token_key = &config().crypt.token_key;
+        let token_key = config().crypt.token_keys.active();
         let fx_token = _generate_token(fx_user, fx_duration_sec, fx_salt, token_key)?;
 
         // -- Exec
@@ -207,5 +563,158 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_validate_pwd_reset_token_ok() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_user = "user_one";
+        let fx_salt = "pepper";
+        let fx_token = generate_pwd_reset_token(fx_user, fx_salt)?;
+
+        // -- Exec
+        let res = validate_pwd_reset_token(&fx_token, fx_salt);
+
+        // -- Check
+        res?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_pwd_reset_token_err_web_token() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_user = "user_one";
+        let fx_salt = "pepper";
+        let fx_token = generate_web_token(fx_user, fx_salt)?;
+
+        // -- Exec
+        let res = validate_pwd_reset_token(&fx_token, fx_salt);
+
+        // -- Check
+        assert!(
+            matches!(res, Err(Error::TokenSignatureNotMatching)),
+            "Should have matched `Err(Error::TokenSignatureNotMatching)` but was `{res:?}`"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_web_token_err_mfa_pending_token() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_user = "user_one";
+        let fx_salt = "pepper";
+        let fx_token = generate_mfa_pending_token(fx_user, fx_salt)?;
+
+        // -- Exec
+        let res = validate_web_token(&fx_token, fx_salt);
+
+        // -- Check
+        assert!(
+            matches!(res, Err(Error::TokenSignatureNotMatching)),
+            "Should have matched `Err(Error::TokenSignatureNotMatching)` but was `{res:?}`"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_oidc_flow_token_ok() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_ident = r#"{"provider":"fx-provider","state":"fx.state"}"#;
+        let fx_token = generate_oidc_flow_token(fx_ident)?;
+
+        // -- Exec
+        let token: Token = fx_token.to_string().parse()?;
+        let res = validate_oidc_flow_token(&token);
+
+        // -- Check
+        res?;
+        assert_eq!(token.ident, fx_ident);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_token_ok_retired_key() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_salt = "pepper";
+        let fx_retired_key = Key {
+            kid: "01".to_string(),
+            secret: b"retired-key".to_vec(),
+        };
+        let fx_keyring = Keyring::new(vec![
+            Key {
+                kid: "02".to_string(),
+                secret: b"active-key".to_vec(),
+            },
+            fx_retired_key.clone(),
+        ])?;
+        let fx_token = _generate_token("user_one", 60., fx_salt, &fx_retired_key)?;
+
+        // -- Exec
+        let res = _validate_token_sign_and_exp(&fx_token, fx_salt, &fx_keyring);
+
+        // -- Check
+        res?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_token_err_removed_key() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_salt = "pepper";
+        let fx_removed_key = Key {
+            kid: "01".to_string(),
+            secret: b"removed-key".to_vec(),
+        };
+        let fx_keyring = Keyring::new(vec![Key {
+            kid: "02".to_string(),
+            secret: b"active-key".to_vec(),
+        }])?;
+        let fx_token = _generate_token("user_one", 60., fx_salt, &fx_removed_key)?;
+
+        // -- Exec
+        let res = _validate_token_sign_and_exp(&fx_token, fx_salt, &fx_keyring);
+
+        // -- Check
+        assert!(
+            matches!(res, Err(Error::KeyNotFound(_))),
+            "Should have matched `Err(Error::KeyNotFound)` but was `{res:?}`"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_token_err_kid_swapped() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_salt = "pepper";
+        let fx_key = Key {
+            kid: "01".to_string(),
+            secret: b"same-key".to_vec(),
+        };
+        let fx_keyring = Keyring::new(vec![
+            Key {
+                kid: "02".to_string(),
+                secret: b"same-key".to_vec(),
+            },
+            fx_key.clone(),
+        ])?;
+        let mut fx_token = _generate_token("user_one", 60., fx_salt, &fx_key)?;
+        fx_token.kid = "02".to_string();
+
+        // -- Exec
+        let res = _validate_token_sign_and_exp(&fx_token, fx_salt, &fx_keyring);
+
+        // -- Check
+        assert!(
+            matches!(res, Err(Error::TokenSignatureNotMatching)),
+            "Should have matched `Err(Error::TokenSignatureNotMatching)` but was `{res:?}`"
+        );
+
+        Ok(())
+    }
 }
 // endregion: --- Tests
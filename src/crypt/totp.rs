@@ -0,0 +1,284 @@
+//! Time-based one-time passwords (RFC 6238), as generated by the authenticator apps,
+//! and the recovery codes to use when the authenticator is lost.
+
+use crate::config::config;
+use crate::crypt::{EncryptContent, Error, Result, ct_eq, pwd};
+use base32::Alphabet;
+use rand::RngCore;
+use ring::hmac;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+/// The defaults of the authenticator apps, some of them ignore other values.
+const TOTP_DIGITS: u32 = 6;
+const TOTP_MODULO: u32 = 10u32.pow(TOTP_DIGITS);
+const TOTP_PERIOD_SEC: i64 = 30;
+/// Steps accepted before and after the current one, for the clocks drifting a bit.
+const TOTP_SKEW_STEPS: i64 = 1;
+const TOTP_SECRET_LEN: usize = 20; // 160 bits, as advised by RFC 4226.
+
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_LEN: usize = 10; // 80 bits, 16 base32 characters.
+
+const BASE32: Alphabet = Alphabet::Rfc4648 { padding: false };
+
+// region:    --- Secret
+
+/// New random secret, base32 encoded as expected by the authenticator apps.
+pub fn new_secret() -> String {
+    let mut secret = [0u8; TOTP_SECRET_LEN];
+    rand::rng().fill_bytes(&mut secret);
+
+    base32::encode(BASE32, &secret)
+}
+
+/// `otpauth://` uri to show as a qr code, for the authenticator app to register the secret.
+pub fn otpauth_uri(secret: &str, account: &str) -> String {
+    let issuer = uri_encode(&config().crypt.totp_issuer);
+    format!(
+        "otpauth://totp/{issuer}:{}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={TOTP_DIGITS}&period={TOTP_PERIOD_SEC}",
+        uri_encode(account)
+    )
+}
+
+/// Percent-encode all but the unreserved characters of RFC 3986.
+fn uri_encode(content: &str) -> String {
+    content
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                char::from(b).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+// endregion: --- Secret
+
+// region:    --- Code Gen and Validation
+
+/// Code of the authenticator app at the given time.
+pub fn generate_code(secret: &str, time: OffsetDateTime) -> Result<String> {
+    let key = decode_secret(secret)?;
+
+    hotp(&key, time_step(time))
+}
+
+/// Validate the code against the steps around the current one,
+/// and return the matching step.
+///
+/// A step must be greater than `last_step`, the last one used by the user,
+/// so an intercepted code can not be replayed.
+pub fn validate_code(
+    secret: &str,
+    code: &str,
+    now: OffsetDateTime,
+    last_step: Option<i64>,
+) -> Result<i64> {
+    let key = decode_secret(secret)?;
+    let code = code.trim();
+    let current_step = time_step(now);
+
+    let mut matching_step = None;
+    for step in current_step - TOTP_SKEW_STEPS..=current_step + TOTP_SKEW_STEPS {
+        if ct_eq(&hotp(&key, step)?, code) {
+            matching_step = Some(step);
+            break;
+        }
+    }
+    let step = matching_step.ok_or(Error::TotpCodeNotMatching)?;
+
+    if last_step.is_some_and(|last_step| step <= last_step) {
+        return Err(Error::TotpCodeAlreadyUsed);
+    }
+
+    Ok(step)
+}
+
+fn decode_secret(secret: &str) -> Result<Vec<u8>> {
+    base32::decode(BASE32, secret).ok_or(Error::TotpSecretInvalid)
+}
+
+fn time_step(time: OffsetDateTime) -> i64 {
+    time.unix_timestamp().div_euclid(TOTP_PERIOD_SEC)
+}
+
+/// HOTP (RFC 4226) code of the counter, HMAC-SHA1 with dynamic truncation.
+fn hotp(key: &[u8], counter: i64) -> Result<String> {
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, key);
+    let tag = hmac::sign(&key, &counter.to_be_bytes());
+    let hash = tag.as_ref();
+
+    let offset = usize::from(hash[hash.len() - 1] & 0x0f);
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+
+    Ok(format!(
+        "{:0width$}",
+        binary % TOTP_MODULO,
+        width = usize::try_from(TOTP_DIGITS).map_err(|_| Error::TotpDigitsInvalid)?
+    ))
+}
+
+// endregion: --- Code Gen and Validation
+
+// region:    --- Recovery Codes
+
+/// New recovery codes, formatted as `xxxx-xxxx-xxxx-xxxx` to be easy to copy by hand.
+pub fn new_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut bytes = [0u8; RECOVERY_CODE_LEN];
+            rand::rng().fill_bytes(&mut bytes);
+            let code = base32::encode(BASE32, &bytes).to_lowercase();
+            format!(
+                "{}-{}-{}-{}",
+                &code[..4],
+                &code[4..8],
+                &code[8..12],
+                &code[12..]
+            )
+        })
+        .collect()
+}
+
+/// Hash stored for a recovery code, made with the pwd scheme and a random salt.
+pub async fn hash_recovery_code(code: &str) -> Result<String> {
+    pwd::hash_pwd(EncryptContent {
+        content: normalize_recovery_code(code),
+        salt: Uuid::new_v4().to_string(),
+    })
+    .await
+}
+
+/// Whether the code matches the hash, whatever its case and separators.
+pub async fn validate_recovery_code(code: &str, code_hash: String) -> bool {
+    // The salt is read back from the hash.
+    let to_hash = EncryptContent {
+        content: normalize_recovery_code(code),
+        salt: Uuid::nil().to_string(),
+    };
+
+    pwd::validate_pwd(to_hash, code_hash).await.is_ok()
+}
+
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+// endregion: --- Recovery Codes
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    // Secret of the RFC 6238 SHA1 test vectors.
+    const FX_RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn test_generate_code_ok_rfc_vectors() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_secret = base32::encode(BASE32, FX_RFC_SECRET);
+        // The RFC lists 8 digits codes, only the last 6 are kept.
+        let fx_vectors = [
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1111111111, "050471"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+        ];
+
+        // -- Exec & Check
+        for (fx_time, fx_code) in fx_vectors {
+            let time = OffsetDateTime::from_unix_timestamp(fx_time)?;
+            assert_eq!(generate_code(&fx_secret, time)?, fx_code, "time {fx_time}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_code_ok_skew() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_secret = new_secret();
+        let fx_now = OffsetDateTime::from_unix_timestamp(1_700_000_000)?;
+        let fx_code = generate_code(&fx_secret, fx_now - time::Duration::seconds(30))?;
+
+        // -- Exec
+        let step = validate_code(&fx_secret, &fx_code, fx_now, None)?;
+
+        // -- Check
+        assert_eq!(step, time_step(fx_now) - 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_code_err_not_matching() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_secret = new_secret();
+        let fx_now = OffsetDateTime::from_unix_timestamp(1_700_000_000)?;
+        let fx_code = generate_code(&fx_secret, fx_now - time::Duration::seconds(90))?;
+
+        // -- Exec
+        let res = validate_code(&fx_secret, &fx_code, fx_now, None);
+
+        // -- Check
+        assert!(
+            matches!(res, Err(Error::TotpCodeNotMatching)),
+            "Should have matched `Err(Error::TotpCodeNotMatching)` but was `{res:?}`"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_code_err_already_used() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_secret = new_secret();
+        let fx_now = OffsetDateTime::from_unix_timestamp(1_700_000_000)?;
+        let fx_code = generate_code(&fx_secret, fx_now)?;
+        let fx_last_step = validate_code(&fx_secret, &fx_code, fx_now, None)?;
+
+        // -- Exec
+        let res = validate_code(&fx_secret, &fx_code, fx_now, Some(fx_last_step));
+
+        // -- Check
+        assert!(
+            matches!(res, Err(Error::TotpCodeAlreadyUsed)),
+            "Should have matched `Err(Error::TotpCodeAlreadyUsed)` but was `{res:?}`"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_validate_recovery_code_ok_normalized() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_codes = new_recovery_codes();
+        let fx_code_hash = hash_recovery_code(&fx_codes[0]).await?;
+
+        // -- Exec & Check
+        assert!(
+            validate_recovery_code(
+                &fx_codes[0].replace('-', " ").to_uppercase(),
+                fx_code_hash.clone()
+            )
+            .await
+        );
+        assert!(!validate_recovery_code(&fx_codes[1], fx_code_hash).await);
+
+        Ok(())
+    }
+}
+// endregion: --- Tests
@@ -1,24 +1,48 @@
 use self::error::{Error, Result};
+use std::sync::Arc;
 mod error;
 
 #[derive(Clone, Debug)]
 pub struct Ctx {
     user_id: i64,
+    /// Permissions granted by the user roles, `task:write` for example.
+    permissions: Arc<[String]>,
+    /// Scopes of the api key authenticating the request, `None` for a user token.
+    api_key_scopes: Option<Arc<[String]>>,
 }
 
 // Constructor.
 impl Ctx {
     pub fn root_ctx() -> Self {
-        Ctx { user_id: 0 }
+        Ctx {
+            user_id: 0,
+            permissions: Arc::from([]),
+            api_key_scopes: None,
+        }
     }
 
     pub fn new(user_id: i64) -> Result<Self> {
         if user_id == 0 {
             Err(Error::CtxCannotNewRootCtx)
         } else {
-            Ok(Self { user_id })
+            Ok(Self {
+                user_id,
+                permissions: Arc::from([]),
+                api_key_scopes: None,
+            })
         }
     }
+
+    pub fn with_permissions(mut self, permissions: Vec<String>) -> Self {
+        self.permissions = permissions.into();
+        self
+    }
+
+    /// Limit the permissions to the scopes of an api key.
+    pub fn with_api_key_scopes(mut self, scopes: Vec<String>) -> Self {
+        self.api_key_scopes = Some(scopes.into());
+        self
+    }
 }
 
 // Property Accessors.
@@ -26,4 +50,66 @@ impl Ctx {
     pub fn user_id(&self) -> i64 {
         self.user_id
     }
+
+    pub fn is_root(&self) -> bool {
+        self.user_id == 0
+    }
+
+    pub fn permissions(&self) -> &[String] {
+        &self.permissions
+    }
+
+    pub fn api_key_scopes(&self) -> Option<&[String]> {
+        self.api_key_scopes.as_deref()
+    }
+
+    /// The root ctx holds every permission.
+    /// With an api key, the permission must also be one of its scopes.
+    pub fn has_permission(&self, permission: &str) -> bool {
+        if self.is_root() {
+            return true;
+        }
+
+        let in_scopes = self
+            .api_key_scopes
+            .as_deref()
+            .is_none_or(|scopes| scopes.iter().any(|s| s == permission));
+
+        in_scopes && self.permissions.iter().any(|p| p == permission)
+    }
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_has_permission_ok() -> Result<()> {
+        // -- Setup & Fixtures
+        let ctx = Ctx::new(1000)?.with_permissions(vec!["task:read".to_string()]);
+
+        // -- Check
+        assert!(ctx.has_permission("task:read"));
+        assert!(!ctx.has_permission("task:write"));
+        assert!(Ctx::root_ctx().has_permission("task:write"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_has_permission_ok_api_key_scopes() -> Result<()> {
+        // -- Setup & Fixtures
+        let ctx = Ctx::new(1000)?
+            .with_permissions(vec!["task:read".to_string(), "task:write".to_string()])
+            .with_api_key_scopes(vec!["task:read".to_string(), "admin".to_string()]);
+
+        // -- Check
+        assert!(ctx.has_permission("task:read"));
+        assert!(!ctx.has_permission("task:write"), "Not in the key scopes");
+        assert!(!ctx.has_permission("admin"), "Not held by the user");
+
+        Ok(())
+    }
 }
+// endregion: --- Tests
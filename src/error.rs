@@ -11,6 +11,10 @@ pub enum Error {
     #[error("Failed to load the environnement variable file, wrong format : `{0}`")]
     ConfigWrongFormat(&'static str),
 
+    // -- Admin
+    #[error("Unknown admin command : `{0}`")]
+    AdminUnknownCommand(String),
+
     // -- Modules
     #[error("Model layer error")]
     Model(#[from] model::Error),
@@ -14,6 +14,8 @@ This section gives a brief overview of the primary types in this crate:
 
 /// For dev only, shoud be remove in a future release
 mod _dev_utils;
+/// Commands for the administrators (key rotation ...)
+pub mod admin;
 /// Load the config for the app, can come from differents locations
 pub mod config;
 /// Handle all the JWT related operations
@@ -22,10 +24,14 @@ pub mod crypt;
 mod ctx;
 /// All possible errors that can occurs
 mod error;
+/// Send the mails of the application (password reset ...)
+pub mod mailer;
 /// All the model layer related functionnality : modele, controller ...
 mod model;
 /// Centralize the observability capabilities of the application : tracing and metrics
 pub mod observability;
+/// Login with the OpenID Connect providers (SSO)
+pub mod oidc;
 /// All of the functions needed to start the application
 pub mod startup;
 /// All the routing and controllers logic
@@ -0,0 +1,9 @@
+use thiserror::Error;
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Failed to write the mail to `{path}` : {cause}")]
+    FailWriteMail { path: String, cause: String },
+}
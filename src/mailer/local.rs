@@ -0,0 +1,101 @@
+use crate::config::MailerSettings;
+use crate::crypt::{format_time, now_utc};
+use crate::mailer::{Error, Mail, Mailer, Result};
+use futures::future::BoxFuture;
+use std::path::PathBuf;
+use tracing::info;
+use uuid::Uuid;
+
+/// Mailer for local development and tests, nothing leaves the machine.
+///
+/// Every mail is logged, and also written as an `.eml` file when an output directory is set.
+#[derive(Debug, Clone)]
+pub struct LocalMailer {
+    from: String,
+    out_dir: Option<PathBuf>,
+}
+
+impl LocalMailer {
+    pub fn new(settings: &MailerSettings) -> Self {
+        Self {
+            from: settings.from.clone(),
+            out_dir: settings.out_dir.as_ref().map(PathBuf::from),
+        }
+    }
+
+    fn to_eml(&self, mail: &Mail) -> String {
+        format!(
+            "From: {}\r\nTo: {}\r\nDate: {}\r\nSubject: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n{}\r\n",
+            self.from,
+            mail.to,
+            format_time(now_utc()),
+            mail.subject,
+            mail.body
+        )
+    }
+}
+
+impl Mailer for LocalMailer {
+    fn send(&self, mail: Mail) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            info!(
+                "{:<12} - send - to: {} - {}",
+                "MAILER", mail.to, mail.subject
+            );
+
+            let Some(out_dir) = &self.out_dir else {
+                return Ok(());
+            };
+
+            let path = out_dir.join(format!("{}.eml", Uuid::new_v4()));
+            let fail_write = |ex: std::io::Error| Error::FailWriteMail {
+                path: path.display().to_string(),
+                cause: ex.to_string(),
+            };
+            tokio::fs::create_dir_all(out_dir)
+                .await
+                .map_err(fail_write)?;
+            tokio::fs::write(&path, self.to_eml(&mail))
+                .await
+                .map_err(fail_write)?;
+
+            Ok(())
+        })
+    }
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_local_mailer_send_ok_file() -> anyhow::Result<()> {
+        // -- Setup & Fixtures
+        let fx_out_dir = std::env::temp_dir().join(format!("mailer-{}", Uuid::new_v4()));
+        let mailer = LocalMailer::new(&MailerSettings {
+            from: "no-reply@localhost".to_string(),
+            out_dir: Some(fx_out_dir.display().to_string()),
+        });
+        let fx_mail = Mail {
+            to: "demo@localhost".to_string(),
+            subject: "Hello".to_string(),
+            body: "Hello world".to_string(),
+        };
+
+        // -- Exec
+        mailer.send(fx_mail).await?;
+
+        // -- Check
+        let mut entries = std::fs::read_dir(&fx_out_dir)?;
+        let eml = std::fs::read_to_string(entries.next().expect("Should have a mail")?.path())?;
+        assert!(eml.contains("To: demo@localhost\r\n"));
+        assert!(eml.ends_with("Hello world\r\n"));
+
+        // -- Clean
+        std::fs::remove_dir_all(&fx_out_dir)?;
+
+        Ok(())
+    }
+}
+// endregion: --- Tests
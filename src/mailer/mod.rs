@@ -0,0 +1,25 @@
+// region:    --- Modules
+mod error;
+mod local;
+
+pub use self::error::{Error, Result};
+pub use self::local::LocalMailer;
+
+use futures::future::BoxFuture;
+use std::fmt::Debug;
+// endregion: --- Modules
+
+/// A mail ready to be sent, the body is plain text.
+#[derive(Debug, Clone)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Delivers the mails sent by the application.
+///
+/// Shared as `Arc<dyn Mailer>`, which is why `send` returns a boxed future.
+pub trait Mailer: Debug + Send + Sync {
+    fn send(&self, mail: Mail) -> BoxFuture<'_, Result<()>>;
+}
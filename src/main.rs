@@ -1,3 +1,4 @@
+use axum_demo::admin;
 use axum_demo::config::get_configuration;
 use axum_demo::observability::init_observability;
 use axum_demo::startup::Application;
@@ -11,6 +12,25 @@ fn main() -> std::io::Result<()> {
         .block_on(async {
             let config = get_configuration().expect("Failed to read configuration");
 
+            // -- Run the admin command instead of the server, ex: `axum-demo pwd-key-usage`
+            match std::env::args().nth(1).as_deref() {
+                None => {}
+                Some(command) if admin::is_command(command) => {
+                    admin::run(command)
+                        .await
+                        .expect("Failed to run the admin command");
+                    return Ok(());
+                }
+                Some("-h" | "--help" | "help") => {
+                    print!("{}", admin::usage());
+                    return Ok(());
+                }
+                Some(_) => {
+                    eprint!("{}", admin::usage());
+                    std::process::exit(2);
+                }
+            }
+
             let observability_guard = init_observability(&config);
 
             let application = Application::build(config, observability_guard.meter.clone())
@@ -0,0 +1,156 @@
+use crate::crypt::now_utc;
+use crate::ctx::Ctx;
+use crate::model::base::{self, DbBmc};
+use crate::model::list::quote_ident;
+use crate::model::{Error, ModelManager, Result};
+use serde::Serialize;
+use sqlx::{FromRow, QueryBuilder};
+use time::OffsetDateTime;
+use tracing::instrument;
+use utoipa::ToSchema;
+
+// region:    --- ApiKey Types
+
+/// An api key, as listed to its user. The key itself is never returned after its creation.
+#[derive(Debug, Clone, FromRow, Serialize, ToSchema)]
+pub struct ApiKey {
+    pub id: i64,
+    pub name: String,
+    /// Permissions the key is limited to.
+    pub scopes: Vec<String>,
+    /// No expiration when absent.
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub expires_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub ctime: OffsetDateTime,
+}
+
+pub struct ApiKeyForCreate {
+    pub name: String,
+    pub key_hash: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct ApiKeyForAuth {
+    pub id: i64,
+    pub user_id: i64,
+    pub key_hash: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<OffsetDateTime>,
+}
+
+// endregion: --- ApiKey Types
+
+// The scopes array can not be bound through sqlb, the queries are built with sqlx.
+const API_KEY_COLUMNS: &str = "\"id\", \"name\", \"scopes\", \"expires_at\", \"ctime\"";
+const API_KEY_FOR_AUTH_COLUMNS: &str =
+    "\"id\", \"user_id\", \"key_hash\", \"scopes\", \"expires_at\"";
+
+pub struct ApiKeyBmc;
+
+impl DbBmc for ApiKeyBmc {
+    const TABLE: &'static str = "api_key";
+    const OWNER_COLUMN: Option<&'static str> = Some("user_id");
+}
+
+impl ApiKeyBmc {
+    /// Create a key for the ctx user.
+    #[instrument(skip(api_key_c))]
+    pub async fn create(ctx: &Ctx, mm: &ModelManager, api_key_c: ApiKeyForCreate) -> Result<i64> {
+        let db = mm.db();
+
+        let ApiKeyForCreate {
+            name,
+            key_hash,
+            scopes,
+            expires_at,
+        } = api_key_c;
+        let now = now_utc();
+
+        let mut qb = QueryBuilder::new(format!(
+            "INSERT INTO {} (\"user_id\", \"name\", \"key_hash\", \"scopes\", \"expires_at\", \
+             \"cid\", \"ctime\", \"mid\", \"mtime\") VALUES (",
+            quote_ident(Self::TABLE)
+        ));
+        let mut values = qb.separated(", ");
+        values.push_bind(ctx.user_id());
+        values.push_bind(name);
+        values.push_bind(key_hash);
+        values.push_bind(scopes);
+        values.push_bind(expires_at);
+        values.push_bind(ctx.user_id());
+        values.push_bind(now);
+        values.push_bind(ctx.user_id());
+        values.push_bind(now);
+        qb.push(") RETURNING \"id\"");
+
+        let id = qb.build_query_scalar::<i64>().fetch_one(db).await?;
+
+        Ok(id)
+    }
+
+    /// Key looked up by the auth middleware, whatever its owner.
+    #[instrument(name = "DB get_for_auth", skip(mm))]
+    pub async fn get_for_auth(mm: &ModelManager, id: i64) -> Result<ApiKeyForAuth> {
+        let db = mm.db();
+
+        let mut qb = QueryBuilder::new(format!(
+            "SELECT {API_KEY_FOR_AUTH_COLUMNS} FROM {} WHERE \"id\" = ",
+            quote_ident(Self::TABLE)
+        ));
+        qb.push_bind(id);
+
+        qb.build_query_as::<ApiKeyForAuth>()
+            .fetch_optional(db)
+            .await?
+            .ok_or(Error::EntityNotFound {
+                entity: Self::TABLE,
+                id,
+            })
+    }
+
+    #[instrument]
+    pub async fn get(ctx: &Ctx, mm: &ModelManager, id: i64) -> Result<ApiKey> {
+        let db = mm.db();
+
+        let mut qb = QueryBuilder::new(format!(
+            "SELECT {API_KEY_COLUMNS} FROM {} WHERE \"id\" = ",
+            quote_ident(Self::TABLE)
+        ));
+        qb.push_bind(id);
+        qb.push(" AND \"user_id\" = ").push_bind(ctx.user_id());
+
+        qb.build_query_as::<ApiKey>()
+            .fetch_optional(db)
+            .await?
+            .ok_or(Error::EntityNotFound {
+                entity: Self::TABLE,
+                id,
+            })
+    }
+
+    /// Keys of the ctx user, the expired ones included, the most recent first.
+    #[instrument]
+    pub async fn list(ctx: &Ctx, mm: &ModelManager) -> Result<Vec<ApiKey>> {
+        let db = mm.db();
+
+        let mut qb = QueryBuilder::new(format!(
+            "SELECT {API_KEY_COLUMNS} FROM {} WHERE \"user_id\" = ",
+            quote_ident(Self::TABLE)
+        ));
+        qb.push_bind(ctx.user_id());
+        qb.push(" ORDER BY \"id\" DESC");
+
+        let api_keys = qb.build_query_as::<ApiKey>().fetch_all(db).await?;
+
+        Ok(api_keys)
+    }
+
+    /// Revoke the key, it can no longer be used.
+    #[instrument]
+    pub async fn delete(ctx: &Ctx, mm: &ModelManager, id: i64) -> Result<()> {
+        base::delete::<Self>(ctx, mm, id).await
+    }
+}
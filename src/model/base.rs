@@ -1,33 +1,114 @@
 use super::{Error, Result};
+use crate::crypt::now_utc;
 use crate::ctx::Ctx;
 use crate::model::ModelManager;
+use crate::model::list::{
+    Cursor, FilterNode, FilterOp, FilterVal, LIST_LIMIT_MAX, ListFilter, ListOptions, ListPage,
+    OrderBy, ensure_column, push_cursor, push_filter, push_order_bys, quote_ident,
+};
 use sqlb::HasFields;
-use sqlx::FromRow;
 use sqlx::postgres::PgRow;
+use sqlx::{FromRow, QueryBuilder, Row};
+use time::OffsetDateTime;
 
 pub trait DbBmc {
     const TABLE: &'static str;
+
+    /// Column holding the id of the user owning the row.
+    /// When set, all the queries are scoped to the rows owned by the ctx user.
+    const OWNER_COLUMN: Option<&'static str> = None;
+
+    /// When set, [delete] only fills the `deleted_at` column and the deleted rows are hidden
+    /// from [get], [list] and [update]. The table must also have the generated `is_deleted` column.
+    const SOFT_DELETE: bool = false;
+}
+
+// region:    --- Timestamps
+
+/// [OffsetDateTime] wrapper to be able to bind it with sqlb.
+#[derive(Debug, Clone, sqlx::Type)]
+#[sqlx(transparent)]
+pub struct UtcTime(pub OffsetDateTime);
+
+sqlb::bindable!(UtcTime);
+
+// endregion: --- Timestamps
+
+/// Column and user id to scope the queries on.
+/// `None` when the entity has no owner or for the root ctx, which can access every row.
+fn owner_scope<MC>(ctx: &Ctx) -> Option<(&'static str, i64)>
+where
+    MC: DbBmc,
+{
+    match MC::OWNER_COLUMN {
+        Some(column) if !ctx.is_root() => Some((column, ctx.user_id())),
+        _ => None,
+    }
 }
 
-pub async fn create<MC, E>(_ctx: &Ctx, mm: &ModelManager, data: E) -> Result<i64>
+pub async fn create<MC, E>(ctx: &Ctx, mm: &ModelManager, data: E) -> Result<i64>
 where
     MC: DbBmc,
     E: HasFields,
 {
     let db = mm.db();
 
-    let fields = data.not_none_fields();
+    let mut fields = data.not_none_fields();
+    if let Some((column, user_id)) = owner_scope::<MC>(ctx) {
+        fields.push((column, user_id).into());
+    }
+
+    // -- Audit columns, the creator is also the first modifier.
+    let now = UtcTime(now_utc());
+    fields.push(("cid", ctx.user_id()).into());
+    fields.push(("ctime", now.clone()).into());
+    fields.push(("mid", ctx.user_id()).into());
+    fields.push(("mtime", now).into());
+
     let (id,) = sqlb::insert()
         .table(MC::TABLE)
         .data(fields)
         .returning(&["id"])
         .fetch_one::<_, (i64,)>(db)
-        .await?;
+        .await
+        .map_err(|ex| insert_error(MC::TABLE, ex))?;
 
     Ok(id)
 }
 
-pub async fn get<MC, E>(_ctx: &Ctx, mm: &ModelManager, id: i64) -> Result<E>
+/// `UniqueViolation` when the insert failed on a unique constraint.
+pub fn insert_error(table: &'static str, ex: sqlx::Error) -> Error {
+    match ex {
+        sqlx::Error::Database(db_error) if db_error.is_unique_violation() => {
+            Error::UniqueViolation {
+                table,
+                constraint: db_error.constraint().unwrap_or_default().to_string(),
+            }
+        }
+        ex => Error::Sqlx(ex),
+    }
+}
+
+pub async fn get<MC, E>(ctx: &Ctx, mm: &ModelManager, id: i64) -> Result<E>
+where
+    MC: DbBmc,
+    E: for<'r> FromRow<'r, PgRow> + Unpin + Send,
+    E: HasFields,
+{
+    _get::<MC, E>(ctx, mm, id, false).await
+}
+
+/// Same as [get] for a soft deleted row.
+pub async fn get_deleted<MC, E>(ctx: &Ctx, mm: &ModelManager, id: i64) -> Result<E>
+where
+    MC: DbBmc,
+    E: for<'r> FromRow<'r, PgRow> + Unpin + Send,
+    E: HasFields,
+{
+    _get::<MC, E>(ctx, mm, id, true).await
+}
+
+async fn _get<MC, E>(ctx: &Ctx, mm: &ModelManager, id: i64, deleted: bool) -> Result<E>
 where
     MC: DbBmc,
     E: for<'r> FromRow<'r, PgRow> + Unpin + Send,
@@ -35,52 +116,323 @@ where
 {
     let db = mm.db();
 
-    let entity: E = sqlb::select()
+    let mut sb = sqlb::select()
         .table(MC::TABLE)
         .columns(E::field_names())
-        .and_where("id", "=", id)
-        .fetch_optional(db)
-        .await?
-        .ok_or(Error::EntityNotFound {
-            entity: MC::TABLE,
-            id,
-        })?;
+        .and_where("id", "=", id);
+    if let Some((column, user_id)) = owner_scope::<MC>(ctx) {
+        sb = sb.and_where(column, "=", user_id);
+    }
+    if MC::SOFT_DELETE {
+        sb = sb.and_where("is_deleted", "=", deleted);
+    }
+
+    // A row owned by another user is reported as not found, to not leak its existence.
+    let entity: E = sb.fetch_optional(db).await?.ok_or(Error::EntityNotFound {
+        entity: MC::TABLE,
+        id,
+    })?;
 
     Ok(entity)
 }
 
-pub async fn list<MC, E>(_ctx: &Ctx, mm: &ModelManager) -> Result<Vec<E>>
+/// Same as [list_page] without the cursor of the next page.
+pub async fn list<MC, E, F>(
+    ctx: &Ctx,
+    mm: &ModelManager,
+    filter: Option<F>,
+    list_options: Option<ListOptions>,
+) -> Result<Vec<E>>
 where
     MC: DbBmc,
     E: for<'r> FromRow<'r, PgRow> + Unpin + Send,
     E: HasFields,
+    F: ListFilter,
+{
+    let page = _list_page::<MC, E, F>(ctx, mm, filter, list_options, false).await?;
+
+    Ok(page.items)
+}
+
+/// List the entities matching the filter.
+///
+/// The page starts after `list_options.cursor` when provided (keyset pagination), then skips `offset` rows.
+/// A `next_cursor` is only returned when ordering on a single column.
+pub async fn list_page<MC, E, F>(
+    ctx: &Ctx,
+    mm: &ModelManager,
+    filter: Option<F>,
+    list_options: Option<ListOptions>,
+) -> Result<ListPage<E>>
+where
+    MC: DbBmc,
+    E: for<'r> FromRow<'r, PgRow> + Unpin + Send,
+    E: HasFields,
+    F: ListFilter,
+{
+    _list_page::<MC, E, F>(ctx, mm, filter, list_options, false).await
+}
+
+/// Same as [list_page] but only on the soft deleted rows (trash view).
+pub async fn list_deleted_page<MC, E, F>(
+    ctx: &Ctx,
+    mm: &ModelManager,
+    filter: Option<F>,
+    list_options: Option<ListOptions>,
+) -> Result<ListPage<E>>
+where
+    MC: DbBmc,
+    E: for<'r> FromRow<'r, PgRow> + Unpin + Send,
+    E: HasFields,
+    F: ListFilter,
+{
+    _list_page::<MC, E, F>(ctx, mm, filter, list_options, true).await
+}
+
+/// Alias of the order column as text, selected to put its value in the next cursor.
+const CURSOR_VALUE_COLUMN: &str = "cursor_value";
+
+async fn _list_page<MC, E, F>(
+    ctx: &Ctx,
+    mm: &ModelManager,
+    filter: Option<F>,
+    list_options: Option<ListOptions>,
+    deleted: bool,
+) -> Result<ListPage<E>>
+where
+    MC: DbBmc,
+    E: for<'r> FromRow<'r, PgRow> + Unpin + Send,
+    E: HasFields,
+    F: ListFilter,
 {
     let db = mm.db();
+    let columns = E::field_names();
 
-    let entities: Vec<E> = sqlb::select()
-        .table(MC::TABLE)
-        .columns(E::field_names())
-        .order_by("id")
-        .fetch_all(db)
-        .await?;
+    // -- Filter, only the columns of the entity can be used.
+    let mut nodes = filter.map(ListFilter::into_nodes).unwrap_or_default();
+    for node in &nodes {
+        ensure_column(columns, node.column)?;
+    }
+    if let Some((column, user_id)) = owner_scope::<MC>(ctx) {
+        nodes.push(FilterNode::new(
+            column,
+            FilterOp::Eq(FilterVal::Int64(user_id)),
+        ));
+    }
+    if MC::SOFT_DELETE {
+        nodes.push(FilterNode::new(
+            "is_deleted",
+            FilterOp::Eq(FilterVal::Bool(deleted)),
+        ));
+    }
 
-    Ok(entities)
+    // -- List options
+    let list_options = list_options.unwrap_or_default();
+    let limit = list_options.limit()?;
+    let offset = list_options.offset.unwrap_or_default();
+    let order_bys = list_options.order_bys(columns)?;
+
+    // -- Cursor, only valid for the ordering it has been created with.
+    let cursor_salt = format!("{}:{}", MC::TABLE, ctx.user_id());
+    let keyset_order_by = match order_bys.as_slice() {
+        [] => Some(None),
+        [order_by] => Some(Some(order_by.clone())),
+        _ => None,
+    };
+    let cursor = match &list_options.cursor {
+        Some(cursor) => {
+            let cursor = Cursor::decode(cursor, &cursor_salt)?;
+            if keyset_order_by.as_ref() != Some(&cursor.order_by) {
+                return Err(Error::ListCursorInvalid);
+            }
+            Some(cursor)
+        }
+        None => None,
+    };
+
+    // -- Build & Exec the query, one more row is fetched to know if there is a next page.
+    let mut select_columns = columns
+        .iter()
+        .map(|column| quote_ident(column))
+        .collect::<Vec<_>>()
+        .join(", ");
+    if let Some(Some(OrderBy { column, .. })) = &keyset_order_by {
+        select_columns.push_str(&format!(
+            ", {}::text AS {}",
+            quote_ident(column),
+            quote_ident(CURSOR_VALUE_COLUMN)
+        ));
+    }
+    let mut qb = QueryBuilder::new(format!(
+        "SELECT {select_columns} FROM {}",
+        quote_ident(MC::TABLE)
+    ));
+    let has_filter = !nodes.is_empty();
+    push_filter(&mut qb, nodes);
+    if let Some(cursor) = &cursor {
+        qb.push(if has_filter { " AND " } else { " WHERE " });
+        push_cursor(&mut qb, MC::TABLE, cursor);
+    }
+    push_order_bys(&mut qb, &order_bys);
+    qb.push(" LIMIT ").push_bind(i64::from(limit) + 1);
+    qb.push(" OFFSET ").push_bind(i64::from(offset));
+
+    let mut rows = qb.build().fetch_all(db).await?;
+    let page_len = usize::try_from(limit).map_err(|_| Error::ListLimitOverMax {
+        max: LIST_LIMIT_MAX,
+        actual: limit,
+    })?;
+    let has_next = rows.len() > page_len;
+    rows.truncate(page_len);
+
+    // -- Next cursor from the last row of the page.
+    let next_cursor = match (has_next, keyset_order_by, rows.last()) {
+        (true, Some(order_by), Some(last_row)) => Some(
+            Cursor {
+                id: last_row.try_get("id")?,
+                value: match order_by {
+                    Some(_) => last_row.try_get(CURSOR_VALUE_COLUMN)?,
+                    None => None,
+                },
+                order_by,
+            }
+            .encode(&cursor_salt)?,
+        ),
+        _ => None,
+    };
+
+    let items = rows
+        .iter()
+        .map(E::from_row)
+        .collect::<core::result::Result<Vec<E>, _>>()?;
+
+    Ok(ListPage { items, next_cursor })
 }
 
-pub async fn update<MC, E>(_ctx: &Ctx, mm: &ModelManager, id: i64, data: E) -> Result<()>
+pub async fn update<MC, E>(ctx: &Ctx, mm: &ModelManager, id: i64, data: E) -> Result<()>
+where
+    MC: DbBmc,
+    E: HasFields,
+{
+    _update::<MC, E>(ctx, mm, id, None, data).await
+}
+
+/// Update the row only if it is still at `version` (optimistic locking).
+///
+/// The table must have a `version` column incremented on each update (`increment_version` trigger).
+pub async fn update_if_version<MC, E>(
+    ctx: &Ctx,
+    mm: &ModelManager,
+    id: i64,
+    version: i64,
+    data: E,
+) -> Result<()>
+where
+    MC: DbBmc,
+    E: HasFields,
+{
+    _update::<MC, E>(ctx, mm, id, Some(version), data).await
+}
+
+async fn _update<MC, E>(
+    ctx: &Ctx,
+    mm: &ModelManager,
+    id: i64,
+    version: Option<i64>,
+    data: E,
+) -> Result<()>
 where
     MC: DbBmc,
     E: HasFields,
 {
     let db = mm.db();
 
-    let fields = data.not_none_fields();
-    let count = sqlb::update()
+    let mut fields = data.not_none_fields();
+    fields.push(("mid", ctx.user_id()).into());
+    fields.push(("mtime", UtcTime(now_utc())).into());
+
+    let mut sb = sqlb::update()
         .table(MC::TABLE)
         .and_where("id", "=", id)
-        .data(fields)
-        .exec(db)
-        .await?;
+        .data(fields);
+    if let Some((column, user_id)) = owner_scope::<MC>(ctx) {
+        sb = sb.and_where(column, "=", user_id);
+    }
+    if MC::SOFT_DELETE {
+        sb = sb.and_where("is_deleted", "=", false);
+    }
+    if let Some(version) = version {
+        sb = sb.and_where("version", "=", version);
+    }
+
+    let count = sb.exec(db).await?;
+
+    if count > 0 {
+        Ok(())
+    } else if version.is_some() && exists::<MC>(ctx, mm, id).await? {
+        Err(Error::ConcurrentModification {
+            entity: MC::TABLE,
+            id,
+        })
+    } else {
+        Err(Error::EntityNotFound {
+            entity: MC::TABLE,
+            id,
+        })
+    }
+}
+
+/// Whether the row exists and is visible from the ctx.
+async fn exists<MC>(ctx: &Ctx, mm: &ModelManager, id: i64) -> Result<bool>
+where
+    MC: DbBmc,
+{
+    let db = mm.db();
+
+    let mut sb = sqlb::select()
+        .table(MC::TABLE)
+        .columns(&["id"])
+        .and_where("id", "=", id);
+    if let Some((column, user_id)) = owner_scope::<MC>(ctx) {
+        sb = sb.and_where(column, "=", user_id);
+    }
+    if MC::SOFT_DELETE {
+        sb = sb.and_where("is_deleted", "=", false);
+    }
+
+    let row = sb.fetch_optional::<_, (i64,)>(db).await?;
+
+    Ok(row.is_some())
+}
+
+pub async fn delete<MC>(ctx: &Ctx, mm: &ModelManager, id: i64) -> Result<()>
+where
+    MC: DbBmc,
+{
+    let db = mm.db();
+
+    let count = if MC::SOFT_DELETE {
+        let now = UtcTime(now_utc());
+        let mut sb = sqlb::update()
+            .table(MC::TABLE)
+            .and_where("id", "=", id)
+            .and_where("is_deleted", "=", false)
+            .data(vec![
+                ("deleted_at", now.clone()).into(),
+                ("mid", ctx.user_id()).into(),
+                ("mtime", now).into(),
+            ]);
+        if let Some((column, user_id)) = owner_scope::<MC>(ctx) {
+            sb = sb.and_where(column, "=", user_id);
+        }
+        sb.exec(db).await?
+    } else {
+        let mut sb = sqlb::delete().table(MC::TABLE).and_where("id", "=", id);
+        if let Some((column, user_id)) = owner_scope::<MC>(ctx) {
+            sb = sb.and_where(column, "=", user_id);
+        }
+        sb.exec(db).await?
+    };
 
     if count == 0 {
         Err(Error::EntityNotFound {
@@ -92,17 +444,28 @@ where
     }
 }
 
-pub async fn delete<MC>(_ctx: &Ctx, mm: &ModelManager, id: i64) -> Result<()>
+/// Restore a soft deleted row.
+pub async fn restore<MC>(ctx: &Ctx, mm: &ModelManager, id: i64) -> Result<()>
 where
     MC: DbBmc,
 {
     let db = mm.db();
 
-    let count = sqlb::delete()
-        .table(MC::TABLE)
-        .and_where("id", "=", id)
-        .exec(db)
-        .await?;
+    // `deleted_at` is set back to NULL, which can not be bound through sqlb.
+    let mut qb = QueryBuilder::new(format!(
+        "UPDATE {} SET \"deleted_at\" = NULL, \"mid\" = ",
+        quote_ident(MC::TABLE)
+    ));
+    qb.push_bind(ctx.user_id());
+    qb.push(", \"mtime\" = ").push_bind(now_utc());
+    qb.push(" WHERE \"id\" = ").push_bind(id);
+    qb.push(" AND \"is_deleted\" = TRUE");
+    if let Some((column, user_id)) = owner_scope::<MC>(ctx) {
+        qb.push(format!(" AND {} = ", quote_ident(column)))
+            .push_bind(user_id);
+    }
+
+    let count = qb.build().execute(db).await?.rows_affected();
 
     if count == 0 {
         Err(Error::EntityNotFound {
@@ -114,6 +477,60 @@ where
     }
 }
 
+/// Hard delete the rows soft deleted before `deleted_before`, returns the number of purged rows.
+pub async fn purge_deleted<MC>(mm: &ModelManager, deleted_before: OffsetDateTime) -> Result<u64>
+where
+    MC: DbBmc,
+{
+    let db = mm.db();
+
+    let count = sqlb::delete()
+        .table(MC::TABLE)
+        .and_where("deleted_at", "<", UtcTime(deleted_before))
+        .exec(db)
+        .await?;
+
+    Ok(count)
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct OwnedBmc;
+
+    impl DbBmc for OwnedBmc {
+        const TABLE: &'static str = "owned";
+        const OWNER_COLUMN: Option<&'static str> = Some("owner_id");
+    }
+
+    struct SharedBmc;
+
+    impl DbBmc for SharedBmc {
+        const TABLE: &'static str = "shared";
+    }
+
+    #[test]
+    fn test_owner_scope_ok_user() -> anyhow::Result<()> {
+        // -- Setup & Fixtures
+        let ctx = Ctx::new(1000)?;
+
+        // -- Exec & Check
+        assert_eq!(owner_scope::<OwnedBmc>(&ctx), Some(("owner_id", 1000)));
+        assert_eq!(owner_scope::<SharedBmc>(&ctx), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_owner_scope_ok_root() {
+        // -- Exec & Check
+        assert_eq!(owner_scope::<OwnedBmc>(&Ctx::root_ctx()), None);
+    }
+}
+// endregion: --- Tests
+
 // #[cfg(test)]
 // mod tests {
 //     use std::env;
@@ -11,6 +11,21 @@ pub type Result<T> = core::result::Result<T, Error>;
 pub enum Error {
     #[error("The entity {entity:?} with the id {id:?} has not been found")]
     EntityNotFound { entity: &'static str, id: i64 },
+    #[error("A row of {table:?} already exists with the same values, constraint: {constraint:?}")]
+    UniqueViolation {
+        table: &'static str,
+        constraint: String,
+    },
+    #[error("The entity {entity:?} with the id {id:?} has been modified by someone else")]
+    ConcurrentModification { entity: &'static str, id: i64 },
+
+    // -- List
+    #[error("The limit {actual} is over the max of {max}")]
+    ListLimitOverMax { max: u32, actual: u32 },
+    #[error("The column {column:?} can not be used to filter or order")]
+    ListColumnNotAllowed { column: String },
+    #[error("The list cursor is not valid for this list")]
+    ListCursorInvalid,
 
     // -- Modules
     #[error("Error at the store level")]
@@ -0,0 +1,588 @@
+use crate::config::config;
+use crate::crypt::{EncryptContent, b64u_decode, b64u_encode, ct_eq, encrypt_into_b64u};
+use crate::model::{Error, Result};
+use serde::{Deserialize, Serialize};
+use sqlx::{Postgres, QueryBuilder};
+use utoipa::ToSchema;
+
+/// Number of rows returned when no limit is provided.
+pub const LIST_LIMIT_DEFAULT: u32 = 100;
+/// Max number of rows a caller can ask for.
+pub const LIST_LIMIT_MAX: u32 = 1000;
+
+// region:    --- ListOptions
+
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ListOptions {
+    #[schema(maximum = 1000)]
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+    /// Columns to order by, prefixed with `!` for a descending order (ex: `["!ctime", "title"]`).
+    pub order_bys: Option<Vec<String>>,
+    /// `next_cursor` of the previous page, to resume the list after its last row.
+    pub cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderBy {
+    pub column: String,
+    pub desc: bool,
+}
+
+impl std::fmt::Display for OrderBy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.desc {
+            write!(f, "!{}", self.column)
+        } else {
+            write!(f, "{}", self.column)
+        }
+    }
+}
+
+/// One page of a list, `next_cursor` is `None` on the last page.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ListPage<E> {
+    pub items: Vec<E>,
+    pub next_cursor: Option<String>,
+}
+
+impl ListOptions {
+    /// Limit to apply, the default one if none has been provided.
+    pub fn limit(&self) -> Result<u32> {
+        match self.limit {
+            Some(limit) if limit > LIST_LIMIT_MAX => Err(Error::ListLimitOverMax {
+                max: LIST_LIMIT_MAX,
+                actual: limit,
+            }),
+            Some(limit) => Ok(limit),
+            None => Ok(LIST_LIMIT_DEFAULT),
+        }
+    }
+
+    /// Parse the order bys, only the `allowed_columns` can be used.
+    pub fn order_bys(&self, allowed_columns: &[&str]) -> Result<Vec<OrderBy>> {
+        self.order_bys
+            .iter()
+            .flatten()
+            .map(|order_by| {
+                let (column, desc) = match order_by.strip_prefix('!') {
+                    Some(column) => (column, true),
+                    None => (order_by.as_str(), false),
+                };
+                ensure_column(allowed_columns, column)?;
+
+                Ok(OrderBy {
+                    column: column.to_string(),
+                    desc,
+                })
+            })
+            .collect()
+    }
+}
+
+// endregion: --- ListOptions
+
+// region:    --- Cursor
+
+/// Position after the last row of a page, keyed on the order column and the id.
+///
+/// String format: `content_b64u.sign_b64u`, with content being `id.order_by[.value]`.
+/// The signature prevents the clients from forging arbitrary positions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor {
+    pub id: i64,
+    pub order_by: Option<OrderBy>,
+    /// Text of the order column in the last row, `None` when null or without order by.
+    /// Kept in the cursor, so the position survives the deletion of the row.
+    pub value: Option<String>,
+}
+
+impl Cursor {
+    /// `salt` should be unique per table and user, so a cursor can not be reused elsewhere.
+    ///
+    /// Cursors are short lived, they are only signed and validated with the active token key.
+    pub fn encode(&self, salt: &str) -> Result<String> {
+        self._encode(salt, &config().crypt.token_keys.active().secret)
+    }
+
+    pub fn decode(cursor: &str, salt: &str) -> Result<Self> {
+        Self::_decode(cursor, salt, &config().crypt.token_keys.active().secret)
+    }
+
+    fn _encode(&self, salt: &str, key: &[u8]) -> Result<String> {
+        let content = self.content();
+        let sign_b64u = sign_cursor(&content, salt, key)?;
+
+        Ok(format!("{}.{sign_b64u}", b64u_encode(&content)))
+    }
+
+    fn _decode(cursor: &str, salt: &str, key: &[u8]) -> Result<Self> {
+        let (content_b64u, sign_b64u) = cursor.split_once('.').ok_or(Error::ListCursorInvalid)?;
+        let content = b64u_decode(content_b64u).map_err(|_| Error::ListCursorInvalid)?;
+
+        if !ct_eq(&sign_cursor(&content, salt, key)?, sign_b64u) {
+            return Err(Error::ListCursorInvalid);
+        }
+
+        let mut parts = content.splitn(3, '.');
+        let (Some(id), Some(order_by)) = (parts.next(), parts.next()) else {
+            return Err(Error::ListCursorInvalid);
+        };
+        let value = parts.next().map(String::from);
+        let id = id.parse::<i64>().map_err(|_| Error::ListCursorInvalid)?;
+        let order_by = match order_by {
+            "" => None,
+            order_by => Some(match order_by.strip_prefix('!') {
+                Some(column) => OrderBy {
+                    column: column.to_string(),
+                    desc: true,
+                },
+                None => OrderBy {
+                    column: order_by.to_string(),
+                    desc: false,
+                },
+            }),
+        };
+
+        Ok(Self {
+            id,
+            order_by,
+            value,
+        })
+    }
+
+    fn content(&self) -> String {
+        let order_by = self
+            .order_by
+            .as_ref()
+            .map(ToString::to_string)
+            .unwrap_or_default();
+        match &self.value {
+            Some(value) => format!("{}.{order_by}.{value}", self.id),
+            None => format!("{}.{order_by}", self.id),
+        }
+    }
+}
+
+fn sign_cursor(content: &str, salt: &str, key: &[u8]) -> Result<String> {
+    let sign_b64u = encrypt_into_b64u(
+        key,
+        &EncryptContent {
+            content: content.to_string(),
+            salt: salt.to_string(),
+        },
+    )?;
+
+    Ok(sign_b64u)
+}
+
+// endregion: --- Cursor
+
+// region:    --- Filter
+
+/// Value compared to a column.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterVal {
+    Bool(bool),
+    Int64(i64),
+    String(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterOp {
+    Eq(FilterVal),
+    In(Vec<FilterVal>),
+    Lt(FilterVal),
+    Gt(FilterVal),
+    Contains(String),
+    StartsWith(String),
+}
+
+/// One condition of a filter, all the nodes of a filter are combined with `AND`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterNode {
+    pub column: &'static str,
+    pub op: FilterOp,
+}
+
+impl FilterNode {
+    pub fn new(column: &'static str, op: FilterOp) -> Self {
+        Self { column, op }
+    }
+}
+
+/// Implemented by the filter of each entity, one optional field per filterable column.
+pub trait ListFilter {
+    fn into_nodes(self) -> Vec<FilterNode>;
+}
+
+/// Operators available on a text column.
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct OpValsString {
+    #[serde(rename = "$eq")]
+    pub eq: Option<String>,
+    #[serde(rename = "$in")]
+    pub in_: Option<Vec<String>>,
+    #[serde(rename = "$lt")]
+    pub lt: Option<String>,
+    #[serde(rename = "$gt")]
+    pub gt: Option<String>,
+    #[serde(rename = "$contains")]
+    pub contains: Option<String>,
+    #[serde(rename = "$startsWith")]
+    pub starts_with: Option<String>,
+}
+
+impl OpValsString {
+    pub fn into_nodes(self, column: &'static str) -> Vec<FilterNode> {
+        let ops = [
+            self.eq.map(|v| FilterOp::Eq(FilterVal::String(v))),
+            self.in_
+                .map(|vs| FilterOp::In(vs.into_iter().map(FilterVal::String).collect())),
+            self.lt.map(|v| FilterOp::Lt(FilterVal::String(v))),
+            self.gt.map(|v| FilterOp::Gt(FilterVal::String(v))),
+            self.contains.map(FilterOp::Contains),
+            self.starts_with.map(FilterOp::StartsWith),
+        ];
+
+        ops.into_iter()
+            .flatten()
+            .map(|op| FilterNode::new(column, op))
+            .collect()
+    }
+}
+
+/// Operators available on an integer column.
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct OpValsInt64 {
+    #[serde(rename = "$eq")]
+    pub eq: Option<i64>,
+    #[serde(rename = "$in")]
+    pub in_: Option<Vec<i64>>,
+    #[serde(rename = "$lt")]
+    pub lt: Option<i64>,
+    #[serde(rename = "$gt")]
+    pub gt: Option<i64>,
+}
+
+impl OpValsInt64 {
+    pub fn into_nodes(self, column: &'static str) -> Vec<FilterNode> {
+        let ops = [
+            self.eq.map(|v| FilterOp::Eq(FilterVal::Int64(v))),
+            self.in_
+                .map(|vs| FilterOp::In(vs.into_iter().map(FilterVal::Int64).collect())),
+            self.lt.map(|v| FilterOp::Lt(FilterVal::Int64(v))),
+            self.gt.map(|v| FilterOp::Gt(FilterVal::Int64(v))),
+        ];
+
+        ops.into_iter()
+            .flatten()
+            .map(|op| FilterNode::new(column, op))
+            .collect()
+    }
+}
+
+// endregion: --- Filter
+
+// region:    --- Sql
+
+pub fn ensure_column(allowed_columns: &[&str], column: &str) -> Result<()> {
+    if allowed_columns.contains(&column) {
+        Ok(())
+    } else {
+        Err(Error::ListColumnNotAllowed {
+            column: column.to_string(),
+        })
+    }
+}
+
+pub fn quote_ident(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
+
+/// Escape the `LIKE` wildcards, so the value is matched literally.
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+fn push_bind_val(qb: &mut QueryBuilder<'_, Postgres>, val: FilterVal) {
+    match val {
+        FilterVal::Bool(v) => qb.push_bind(v),
+        FilterVal::Int64(v) => qb.push_bind(v),
+        FilterVal::String(v) => qb.push_bind(v),
+    };
+}
+
+/// Push the `WHERE` clause of the nodes, nothing is pushed when there is no node.
+pub fn push_filter(qb: &mut QueryBuilder<'_, Postgres>, nodes: Vec<FilterNode>) {
+    for (idx, FilterNode { column, op }) in nodes.into_iter().enumerate() {
+        qb.push(if idx == 0 { " WHERE " } else { " AND " });
+        qb.push(quote_ident(column));
+
+        match op {
+            FilterOp::Eq(val) => {
+                qb.push(" = ");
+                push_bind_val(qb, val);
+            }
+            // An empty list matches nothing.
+            FilterOp::In(vals) if vals.is_empty() => {
+                qb.push(" IN (NULL)");
+            }
+            FilterOp::In(vals) => {
+                qb.push(" IN (");
+                for (idx, val) in vals.into_iter().enumerate() {
+                    if idx > 0 {
+                        qb.push(", ");
+                    }
+                    push_bind_val(qb, val);
+                }
+                qb.push(")");
+            }
+            FilterOp::Lt(val) => {
+                qb.push(" < ");
+                push_bind_val(qb, val);
+            }
+            FilterOp::Gt(val) => {
+                qb.push(" > ");
+                push_bind_val(qb, val);
+            }
+            FilterOp::Contains(val) => {
+                qb.push(" LIKE ");
+                qb.push_bind(format!("%{}%", escape_like(&val)));
+            }
+            FilterOp::StartsWith(val) => {
+                qb.push(" LIKE ");
+                qb.push_bind(format!("{}%", escape_like(&val)));
+            }
+        }
+    }
+}
+
+/// Push the `ORDER BY` clause, `id` is always added last to keep the pagination stable.
+/// It follows the direction of the last order by, so `(column, id)` can be used as a keyset.
+pub fn push_order_bys(qb: &mut QueryBuilder<'_, Postgres>, order_bys: &[OrderBy]) {
+    qb.push(" ORDER BY ");
+    for OrderBy { column, desc } in order_bys {
+        qb.push(quote_ident(column));
+        qb.push(if *desc { " DESC, " } else { " ASC, " });
+    }
+    match order_bys.last() {
+        Some(OrderBy { desc: true, .. }) => qb.push("\"id\" DESC"),
+        _ => qb.push("\"id\" ASC"),
+    };
+}
+
+/// Push the keyset condition to only keep the rows after the cursor.
+///
+/// The order value is cast back from its text through the row type of the table,
+/// and the nulls are placed as Postgres orders them (last when ascending, first when descending).
+pub fn push_cursor(qb: &mut QueryBuilder<'_, Postgres>, table: &str, cursor: &Cursor) {
+    let Some(OrderBy { column, desc }) = &cursor.order_by else {
+        qb.push("\"id\" > ").push_bind(cursor.id);
+        return;
+    };
+    let quoted_column = quote_ident(column);
+    let op = if *desc { "<" } else { ">" };
+
+    qb.push("(");
+    match &cursor.value {
+        Some(value) => {
+            qb.push(format!(
+                "({quoted_column}, \"id\") {op} ((SELECT {quoted_column} FROM json_populate_record(NULL::{}, json_build_object(",
+                quote_ident(table)
+            ));
+            qb.push_bind(column.clone());
+            qb.push(", ");
+            qb.push_bind(value.clone());
+            qb.push("))), ");
+            qb.push_bind(cursor.id);
+            qb.push(")");
+            if !desc {
+                qb.push(format!(" OR {quoted_column} IS NULL"));
+            }
+        }
+        None => {
+            qb.push(format!("{quoted_column} IS NULL AND \"id\" {op} "));
+            qb.push_bind(cursor.id);
+            if *desc {
+                qb.push(format!(" OR {quoted_column} IS NOT NULL"));
+            }
+        }
+    }
+    qb.push(")");
+}
+
+// endregion: --- Sql
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list_options_limit_err_over_max() {
+        // -- Setup & Fixtures
+        let list_options = ListOptions {
+            limit: Some(LIST_LIMIT_MAX + 1),
+            ..Default::default()
+        };
+
+        // -- Exec
+        let res = list_options.limit();
+
+        // -- Check
+        assert!(
+            matches!(res, Err(Error::ListLimitOverMax { .. })),
+            "Should have matched `Err(Error::ListLimitOverMax)`"
+        );
+    }
+
+    #[test]
+    fn test_list_options_order_bys_ok() -> Result<()> {
+        // -- Setup & Fixtures
+        let list_options = ListOptions {
+            order_bys: Some(vec!["!ctime".to_string(), "title".to_string()]),
+            ..Default::default()
+        };
+
+        // -- Exec
+        let order_bys = list_options.order_bys(&["id", "title", "ctime"])?;
+
+        // -- Check
+        assert_eq!(
+            order_bys,
+            vec![
+                OrderBy {
+                    column: "ctime".to_string(),
+                    desc: true
+                },
+                OrderBy {
+                    column: "title".to_string(),
+                    desc: false
+                },
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_options_order_bys_err_column() {
+        // -- Setup & Fixtures
+        let list_options = ListOptions {
+            order_bys: Some(vec!["pwd".to_string()]),
+            ..Default::default()
+        };
+
+        // -- Exec
+        let res = list_options.order_bys(&["id", "title"]);
+
+        // -- Check
+        assert!(
+            matches!(res, Err(Error::ListColumnNotAllowed { .. })),
+            "Should have matched `Err(Error::ListColumnNotAllowed)`"
+        );
+    }
+
+    #[test]
+    fn test_cursor_decode_ok() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_key = b"some-cursor-key";
+        let fx_cursor = Cursor {
+            id: 1042,
+            order_by: Some(OrderBy {
+                column: "title".to_string(),
+                desc: true,
+            }),
+            value: Some("Task 1.2".to_string()),
+        };
+        let encoded = fx_cursor._encode("task:1000", fx_key)?;
+
+        // -- Exec
+        let cursor = Cursor::_decode(&encoded, "task:1000", fx_key)?;
+
+        // -- Check
+        assert_eq!(cursor, fx_cursor);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cursor_decode_err_forged() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_key = b"some-cursor-key";
+        let encoded = Cursor {
+            id: 1042,
+            order_by: None,
+            value: None,
+        }
+        ._encode("task:1000", fx_key)?;
+        let (_, sign_b64u) = encoded.split_once('.').unwrap();
+        let forged = format!("{}.{sign_b64u}", b64u_encode("1.")); // other position, same sign
+
+        // -- Exec & Check
+        for (cursor, salt) in [(forged.as_str(), "task:1000"), (&encoded, "task:1001")] {
+            assert!(
+                matches!(
+                    Cursor::_decode(cursor, salt, fx_key),
+                    Err(Error::ListCursorInvalid)
+                ),
+                "Should have matched `Err(Error::ListCursorInvalid)`"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_push_filter_ok() {
+        // -- Setup & Fixtures
+        let nodes = OpValsString {
+            contains: Some("50%".to_string()),
+            in_: Some(vec!["a".to_string(), "b".to_string()]),
+            ..Default::default()
+        }
+        .into_nodes("title");
+        let mut qb = QueryBuilder::<Postgres>::new("SELECT \"id\" FROM \"task\"");
+
+        // -- Exec
+        push_filter(&mut qb, nodes);
+        push_order_bys(&mut qb, &[]);
+
+        // -- Check
+        assert_eq!(
+            qb.sql(),
+            "SELECT \"id\" FROM \"task\" WHERE \"title\" IN ($1, $2) AND \"title\" LIKE $3 ORDER BY \"id\" ASC"
+        );
+    }
+
+    #[test]
+    fn test_push_cursor_ok_order_value() {
+        // -- Setup & Fixtures
+        let fx_cursor = Cursor {
+            id: 1042,
+            order_by: Some(OrderBy {
+                column: "title".to_string(),
+                desc: false,
+            }),
+            value: Some("Task 12".to_string()),
+        };
+        let mut qb = QueryBuilder::<Postgres>::new("SELECT \"id\" FROM \"task\" WHERE ");
+
+        // -- Exec
+        push_cursor(&mut qb, "task", &fx_cursor);
+
+        // -- Check
+        assert_eq!(
+            qb.sql(),
+            "SELECT \"id\" FROM \"task\" WHERE ((\"title\", \"id\") > ((SELECT \"title\" FROM json_populate_record(NULL::\"task\", json_build_object($1, $2))), $3) OR \"title\" IS NULL)"
+        );
+    }
+}
+// endregion: --- Tests
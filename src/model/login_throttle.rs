@@ -0,0 +1,121 @@
+use crate::model::base::DbBmc;
+use crate::model::list::quote_ident;
+use crate::model::{ModelManager, Result};
+use sqlx::QueryBuilder;
+use time::OffsetDateTime;
+use tracing::instrument;
+
+pub struct LoginThrottleBmc;
+
+impl DbBmc for LoginThrottleBmc {
+    const TABLE: &'static str = "login_throttle";
+}
+
+impl LoginThrottleBmc {
+    /// The latest lockout end among the keys, if one of them is still locked at `now`.
+    #[instrument(skip(mm))]
+    pub async fn locked_until(
+        mm: &ModelManager,
+        keys: &[String],
+        now: OffsetDateTime,
+    ) -> Result<Option<OffsetDateTime>> {
+        let db = mm.db();
+
+        let mut qb = QueryBuilder::new(format!(
+            "SELECT max(\"locked_until\") FROM {} WHERE \"key\" = ANY(",
+            quote_ident(Self::TABLE)
+        ));
+        qb.push_bind(keys.to_vec());
+        qb.push(") AND \"locked_until\" > ").push_bind(now);
+
+        let locked_until = qb
+            .build_query_scalar::<Option<OffsetDateTime>>()
+            .fetch_one(db)
+            .await?;
+
+        Ok(locked_until)
+    }
+
+    /// Count a failure for the key and return its number of consecutive failures.
+    /// The count restarts when the previous failure is older than `window_start`.
+    #[instrument(skip(mm))]
+    pub async fn record_failure(
+        mm: &ModelManager,
+        key: &str,
+        now: OffsetDateTime,
+        window_start: OffsetDateTime,
+    ) -> Result<i32> {
+        let db = mm.db();
+        let table = quote_ident(Self::TABLE);
+
+        let mut qb = QueryBuilder::new(format!(
+            "INSERT INTO {table} (\"key\", \"failures\", \"last_failure_at\") VALUES ("
+        ));
+        qb.push_bind(key);
+        qb.push(", 1, ").push_bind(now);
+        qb.push(format!(
+            ") ON CONFLICT (\"key\") DO UPDATE SET \"failures\" = CASE WHEN {table}.\"last_failure_at\" < "
+        ));
+        qb.push_bind(window_start);
+        qb.push(format!(
+            " THEN 1 ELSE {table}.\"failures\" + 1 END, \"last_failure_at\" = EXCLUDED.\"last_failure_at\" RETURNING \"failures\""
+        ));
+
+        let failures = qb.build_query_scalar::<i32>().fetch_one(db).await?;
+
+        Ok(failures)
+    }
+
+    #[instrument(skip(mm))]
+    pub async fn lock(mm: &ModelManager, key: &str, locked_until: OffsetDateTime) -> Result<()> {
+        let db = mm.db();
+
+        let mut qb = QueryBuilder::new(format!(
+            "UPDATE {} SET \"locked_until\" = ",
+            quote_ident(Self::TABLE)
+        ));
+        qb.push_bind(locked_until);
+        qb.push(" WHERE \"key\" = ").push_bind(key);
+        qb.build().execute(db).await?;
+
+        Ok(())
+    }
+
+    /// Delete the keys whose failures are forgotten and which are no longer locked,
+    /// returns the number of deleted keys.
+    #[instrument(skip(mm))]
+    pub async fn purge_expired(
+        mm: &ModelManager,
+        now: OffsetDateTime,
+        window_start: OffsetDateTime,
+    ) -> Result<u64> {
+        let db = mm.db();
+
+        let mut qb = QueryBuilder::new(format!(
+            "DELETE FROM {} WHERE \"last_failure_at\" < ",
+            quote_ident(Self::TABLE)
+        ));
+        qb.push_bind(window_start);
+        qb.push(" AND (\"locked_until\" IS NULL OR \"locked_until\" <= ")
+            .push_bind(now);
+        qb.push(")");
+        let count = qb.build().execute(db).await?.rows_affected();
+
+        Ok(count)
+    }
+
+    /// Forget the failures of the key, after a successful login.
+    #[instrument(skip(mm))]
+    pub async fn clear(mm: &ModelManager, key: &str) -> Result<()> {
+        let db = mm.db();
+
+        let mut qb = QueryBuilder::new(format!(
+            "DELETE FROM {} WHERE \"key\" = ",
+            quote_ident(Self::TABLE)
+        ));
+        qb.push_bind(key);
+        qb.build().execute(db).await?;
+
+        Ok(())
+    }
+}
@@ -1,8 +1,19 @@
+pub mod api_key;
+pub mod list;
+pub mod login_throttle;
+pub mod recovery_code;
+pub mod role;
+pub mod session;
 pub mod task;
+pub mod token_denylist;
 pub mod user;
+pub mod user_identity;
 pub use self::error::{Error, Result};
 use self::store::{Db, new_db_pool};
+use self::token_denylist::DenylistCache;
+use crate::config::Postgres;
 use axum_macros::FromRef;
+use std::sync::Arc;
 
 mod base;
 mod error;
@@ -11,13 +22,18 @@ mod store;
 #[derive(Debug, Clone, FromRef)]
 pub struct ModelManager {
     db: Db,
+    #[from_ref(skip)]
+    denylist: Arc<DenylistCache>,
 }
 
 impl ModelManager {
     /// Setup the connection to the db
-    pub async fn new() -> Result<Self> {
-        let db = new_db_pool().await?;
-        Ok(ModelManager { db })
+    pub async fn new(postgres: &Postgres) -> Result<Self> {
+        let db = new_db_pool(postgres).await?;
+        Ok(ModelManager {
+            db,
+            denylist: Arc::default(),
+        })
     }
 
     // Create the db and setup the connection to the db
@@ -56,4 +72,9 @@ impl ModelManager {
     pub(in crate::model) fn db(&self) -> &Db {
         &self.db
     }
+
+    /// Returns the cache of the revoked tokens, shared by the clones of the model manager.
+    pub(in crate::model) fn denylist(&self) -> &DenylistCache {
+        &self.denylist
+    }
 }
@@ -0,0 +1,83 @@
+use crate::crypt::now_utc;
+use crate::model::base::DbBmc;
+use crate::model::list::quote_ident;
+use crate::model::{ModelManager, Result};
+use sqlx::QueryBuilder;
+use tracing::instrument;
+
+pub struct RecoveryCodeBmc;
+
+impl DbBmc for RecoveryCodeBmc {
+    const TABLE: &'static str = "recovery_code";
+    const OWNER_COLUMN: Option<&'static str> = Some("user_id");
+}
+
+impl RecoveryCodeBmc {
+    /// Replace all the recovery codes of the user, the previous ones can no longer be used.
+    #[instrument(skip(mm, code_hashes))]
+    pub async fn replace_all(
+        mm: &ModelManager,
+        user_id: i64,
+        code_hashes: &[String],
+    ) -> Result<()> {
+        let mut tx = mm.db().begin().await?;
+
+        let mut qb = QueryBuilder::new(format!(
+            "DELETE FROM {} WHERE \"user_id\" = ",
+            quote_ident(Self::TABLE)
+        ));
+        qb.push_bind(user_id);
+        qb.build().execute(&mut *tx).await?;
+
+        if !code_hashes.is_empty() {
+            let mut qb = QueryBuilder::new(format!(
+                "INSERT INTO {} (\"user_id\", \"code_hash\") ",
+                quote_ident(Self::TABLE)
+            ));
+            qb.push_values(code_hashes, |mut values, code_hash| {
+                values.push_bind(user_id).push_bind(code_hash);
+            });
+            qb.build().execute(&mut *tx).await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Id and hash of the codes of the user which have not been used yet.
+    #[instrument(skip(mm))]
+    pub async fn list_unused(mm: &ModelManager, user_id: i64) -> Result<Vec<(i64, String)>> {
+        let db = mm.db();
+
+        let mut qb = QueryBuilder::new(format!(
+            "SELECT \"id\", \"code_hash\" FROM {} WHERE \"user_id\" = ",
+            quote_ident(Self::TABLE)
+        ));
+        qb.push_bind(user_id);
+        qb.push(" AND \"used_at\" IS NULL ORDER BY \"id\"");
+
+        let codes = qb.build_query_as::<(i64, String)>().fetch_all(db).await?;
+
+        Ok(codes)
+    }
+
+    /// Mark the code as used, returns `false` if it has already been used.
+    #[instrument(skip(mm))]
+    pub async fn use_code(mm: &ModelManager, user_id: i64, id: i64) -> Result<bool> {
+        let db = mm.db();
+
+        let mut qb = QueryBuilder::new(format!(
+            "UPDATE {} SET \"used_at\" = ",
+            quote_ident(Self::TABLE)
+        ));
+        qb.push_bind(now_utc());
+        qb.push(" WHERE \"id\" = ").push_bind(id);
+        qb.push(" AND \"user_id\" = ").push_bind(user_id);
+        qb.push(" AND \"used_at\" IS NULL");
+
+        let count = qb.build().execute(db).await?.rows_affected();
+
+        Ok(count > 0)
+    }
+}
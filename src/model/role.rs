@@ -0,0 +1,51 @@
+use crate::model::base::DbBmc;
+use crate::model::list::quote_ident;
+use crate::model::{ModelManager, Result};
+use sqlx::{PgExecutor, QueryBuilder};
+use tracing::instrument;
+
+/// Role given to every registered user.
+pub const DEFAULT_ROLE: &str = "user";
+
+pub struct RoleBmc;
+
+impl DbBmc for RoleBmc {
+    const TABLE: &'static str = "role";
+}
+
+impl RoleBmc {
+    /// Give a role to a user, nothing is done if the user already has it.
+    /// Takes the executor, so it can run in the transaction creating the user.
+    #[instrument(skip(db))]
+    pub async fn assign(db: impl PgExecutor<'_>, user_id: i64, role: &str) -> Result<()> {
+        let mut qb =
+            QueryBuilder::new("INSERT INTO \"user_role\" (\"user_id\", \"role_id\") SELECT ");
+        qb.push_bind(user_id);
+        qb.push(format!(
+            ", \"id\" FROM {} WHERE \"name\" = ",
+            quote_ident(Self::TABLE)
+        ));
+        qb.push_bind(role);
+        qb.push(" ON CONFLICT DO NOTHING");
+        qb.build().execute(db).await?;
+
+        Ok(())
+    }
+
+    /// Permissions granted by all the roles of the user, sorted and without duplicates.
+    #[instrument(name = "DB list_permissions", skip(mm))]
+    pub async fn list_permissions(mm: &ModelManager, user_id: i64) -> Result<Vec<String>> {
+        let db = mm.db();
+
+        let mut qb = QueryBuilder::new(
+            "SELECT DISTINCT rp.\"permission\" FROM \"role_permission\" rp \
+             JOIN \"user_role\" ur ON ur.\"role_id\" = rp.\"role_id\" WHERE ur.\"user_id\" = ",
+        );
+        qb.push_bind(user_id);
+        qb.push(" ORDER BY rp.\"permission\"");
+
+        let permissions = qb.build_query_scalar::<String>().fetch_all(db).await?;
+
+        Ok(permissions)
+    }
+}
@@ -0,0 +1,177 @@
+use crate::crypt::now_utc;
+use crate::ctx::Ctx;
+use crate::model::base::{self, DbBmc, UtcTime};
+use crate::model::list::quote_ident;
+use crate::model::{ModelManager, Result};
+use serde::Serialize;
+use sqlb::{Fields, HasFields};
+use sqlx::{FromRow, QueryBuilder};
+use time::OffsetDateTime;
+use tracing::instrument;
+use utoipa::ToSchema;
+
+// region:    --- Session Types
+
+/// A logged in device, as listed to its user.
+#[derive(Debug, Clone, Fields, FromRow, Serialize, ToSchema)]
+pub struct Session {
+    pub id: i64,
+    pub device: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    /// Time of the login.
+    #[serde(with = "time::serde::rfc3339")]
+    pub ctime: OffsetDateTime,
+    /// Time of the last refresh.
+    #[serde(with = "time::serde::rfc3339")]
+    pub last_seen_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub expires_at: OffsetDateTime,
+}
+
+pub struct SessionForCreate {
+    pub refresh_hash: String,
+    pub device: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub expires_at: OffsetDateTime,
+}
+
+#[derive(Fields)]
+struct SessionForInsert {
+    refresh_hash: String,
+    device: Option<String>,
+    ip: Option<String>,
+    user_agent: Option<String>,
+    expires_at: UtcTime,
+}
+
+#[derive(Debug, Clone, Fields, FromRow)]
+pub struct SessionForRefresh {
+    pub id: i64,
+    pub user_id: i64,
+    pub refresh_hash: String,
+    pub prev_refresh_hash: Option<String>,
+    pub expires_at: OffsetDateTime,
+}
+
+// endregion: --- Session Types
+
+pub struct SessionBmc;
+
+impl DbBmc for SessionBmc {
+    const TABLE: &'static str = "session";
+    const OWNER_COLUMN: Option<&'static str> = Some("user_id");
+}
+
+impl SessionBmc {
+    /// Open a session for the ctx user, its expired sessions are purged at the same time.
+    #[instrument(skip(session_c))]
+    pub async fn create(ctx: &Ctx, mm: &ModelManager, session_c: SessionForCreate) -> Result<i64> {
+        let SessionForCreate {
+            refresh_hash,
+            device,
+            ip,
+            user_agent,
+            expires_at,
+        } = session_c;
+
+        sqlb::delete()
+            .table(Self::TABLE)
+            .and_where("user_id", "=", ctx.user_id())
+            .and_where("expires_at", "<", UtcTime(now_utc()))
+            .exec(mm.db())
+            .await?;
+
+        let session_i = SessionForInsert {
+            refresh_hash,
+            device,
+            ip,
+            user_agent,
+            expires_at: UtcTime(expires_at),
+        };
+
+        base::create::<Self, _>(ctx, mm, session_i).await
+    }
+
+    #[instrument]
+    pub async fn get_for_refresh(
+        ctx: &Ctx,
+        mm: &ModelManager,
+        id: i64,
+    ) -> Result<SessionForRefresh> {
+        base::get::<Self, _>(ctx, mm, id).await
+    }
+
+    /// Sessions of the ctx user, the most recently used first.
+    #[instrument]
+    pub async fn list(ctx: &Ctx, mm: &ModelManager) -> Result<Vec<Session>> {
+        let db = mm.db();
+
+        let columns = Session::field_names()
+            .iter()
+            .map(|column| quote_ident(column))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut qb = QueryBuilder::new(format!(
+            "SELECT {columns} FROM {} WHERE \"user_id\" = ",
+            quote_ident(Self::TABLE)
+        ));
+        qb.push_bind(ctx.user_id());
+        qb.push(" AND \"expires_at\" > ").push_bind(now_utc());
+        qb.push(" ORDER BY \"last_seen_at\" DESC, \"id\" DESC");
+
+        let sessions = qb.build_query_as::<Session>().fetch_all(db).await?;
+
+        Ok(sessions)
+    }
+
+    /// Replace the refresh hash, only if it is still `refresh_hash`,
+    /// so a refresh token can not be used twice by concurrent requests.
+    ///
+    /// Returns `false` when the session has been refreshed or revoked in the meantime.
+    #[instrument(skip(refresh_hash, new_refresh_hash))]
+    pub async fn rotate_refresh(
+        ctx: &Ctx,
+        mm: &ModelManager,
+        id: i64,
+        refresh_hash: &str,
+        new_refresh_hash: &str,
+    ) -> Result<bool> {
+        let now = UtcTime(now_utc());
+
+        let count = sqlb::update()
+            .table(Self::TABLE)
+            .and_where("id", "=", id)
+            .and_where("refresh_hash", "=", refresh_hash)
+            .data(vec![
+                ("prev_refresh_hash", refresh_hash.to_string()).into(),
+                ("refresh_hash", new_refresh_hash.to_string()).into(),
+                ("last_seen_at", now.clone()).into(),
+                ("mid", ctx.user_id()).into(),
+                ("mtime", now).into(),
+            ])
+            .exec(mm.db())
+            .await?;
+
+        Ok(count > 0)
+    }
+
+    /// Revoke the session, its refresh token can no longer be used.
+    #[instrument]
+    pub async fn delete(ctx: &Ctx, mm: &ModelManager, id: i64) -> Result<()> {
+        base::delete::<Self>(ctx, mm, id).await
+    }
+
+    /// Revoke all the sessions of a user, returns the number of revoked sessions.
+    #[instrument]
+    pub async fn delete_all_for_user(mm: &ModelManager, user_id: i64) -> Result<u64> {
+        let count = sqlb::delete()
+            .table(Self::TABLE)
+            .and_where("user_id", "=", user_id)
+            .exec(mm.db())
+            .await?;
+
+        Ok(count)
+    }
+}
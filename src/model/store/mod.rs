@@ -1,19 +1,20 @@
 mod error;
 pub use self::error::{Error, Result};
-use crate::config::config;
+use crate::config::Postgres;
 use secrecy::ExposeSecret;
 use sqlx::postgres::PgPoolOptions;
 use sqlx::{Pool, Postgres};
 pub type Db = Pool<Postgres>;
 
-pub async fn new_db_pool() -> Result<Db> {
-    let user = config().postgres.db_user.expose_secret();
-    let password = config().postgres.db_password.expose_secret();
-    let host = config().postgres.db_host.expose_secret();
-    let db = config().postgres.db_name.expose_secret();
+pub async fn new_db_pool(postgres: &Postgres) -> Result<Db> {
+    let user = postgres.db_user.expose_secret();
+    let password = postgres.db_password.expose_secret();
+    let host = postgres.db_host.expose_secret();
+    let port = postgres.db_port;
+    let db = postgres.db_name.expose_secret();
     PgPoolOptions::new()
         .max_connections(5)
-        .connect(&format!("postgres://{user}:{password}@{host}/{db}"))
+        .connect(&format!("postgres://{user}:{password}@{host}:{port}/{db}"))
         .await
         .map_err(|ex| Error::FailToCreatePool(ex.to_string()))
 }
@@ -2,16 +2,36 @@ use crate::ctx::Ctx;
 use crate::model::ModelManager;
 use crate::model::Result;
 use crate::model::base::{self, DbBmc};
+use crate::model::list::{
+    FilterNode, ListFilter, ListOptions, ListPage, OpValsInt64, OpValsString,
+};
 use serde::{Deserialize, Serialize};
 use sqlb::Fields;
 use sqlx::FromRow;
+use time::OffsetDateTime;
 use tracing::instrument;
+use utoipa::ToSchema;
+use validator_derive::Validate;
 
 // region:    --- Task Types
-#[derive(Debug, Clone, Fields, FromRow, Serialize)]
+#[derive(Debug, Clone, Fields, FromRow, Serialize, ToSchema)]
 pub struct Task {
     pub id: i64,
     pub title: String,
+    /// Incremented on each update, to detect concurrent modifications.
+    pub version: i64,
+
+    // -- Timestamps
+    pub cid: i64,
+    #[serde(with = "time::serde::rfc3339")]
+    pub ctime: OffsetDateTime,
+    pub mid: i64,
+    #[serde(with = "time::serde::rfc3339")]
+    pub mtime: OffsetDateTime,
+
+    /// Set when the task is in the trash.
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub deleted_at: Option<OffsetDateTime>,
     // exemple of using sqlb
     // #[field(name = "description")] --> for the fields() methode use when querying the database (Fields trait)
     // #[field(name = "description")]  --> for the FromRow trait of sqlx
@@ -19,19 +39,44 @@ pub struct Task {
 }
 
 // Struct are views of the sql tables
-#[derive(Deserialize, Fields, Debug)]
+#[derive(Deserialize, Fields, Debug, Validate, ToSchema)]
 pub struct TaskForCreate {
+    #[validate(length(min = 1, message = "Can not be empty"))]
     pub title: String,
 }
 
-#[derive(Deserialize, Fields, Debug)]
+#[derive(Deserialize, Fields, Debug, Validate, ToSchema)]
 pub struct TaskForUpdate {
+    #[validate(length(min = 1, message = "Can not be empty"))]
     pub title: Option<String>,
 }
+
+/// Columns a task list can be filtered on.
+#[derive(Deserialize, Debug, Default, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TaskFilter {
+    pub id: Option<OpValsInt64>,
+    pub title: Option<OpValsString>,
+}
+
+impl ListFilter for TaskFilter {
+    fn into_nodes(self) -> Vec<FilterNode> {
+        let mut nodes = Vec::new();
+        if let Some(id) = self.id {
+            nodes.extend(id.into_nodes("id"));
+        }
+        if let Some(title) = self.title {
+            nodes.extend(title.into_nodes("title"));
+        }
+        nodes
+    }
+}
 // endregion: --- Task Types
 
 impl DbBmc for TaskBmc {
     const TABLE: &'static str = "task";
+    const OWNER_COLUMN: Option<&'static str> = Some("owner_id");
+    const SOFT_DELETE: bool = true;
 }
 
 // region:    --- TaskBmc
@@ -49,8 +94,23 @@ impl TaskBmc {
     }
 
     #[instrument]
-    pub async fn list(ctx: &Ctx, mm: &ModelManager) -> Result<Vec<Task>> {
-        base::list::<Self, _>(ctx, mm).await
+    pub async fn list(
+        ctx: &Ctx,
+        mm: &ModelManager,
+        filter: Option<TaskFilter>,
+        list_options: Option<ListOptions>,
+    ) -> Result<Vec<Task>> {
+        base::list::<Self, _, _>(ctx, mm, filter, list_options).await
+    }
+
+    #[instrument]
+    pub async fn list_page(
+        ctx: &Ctx,
+        mm: &ModelManager,
+        filter: Option<TaskFilter>,
+        list_options: Option<ListOptions>,
+    ) -> Result<ListPage<Task>> {
+        base::list_page::<Self, _, _>(ctx, mm, filter, list_options).await
     }
 
     #[instrument]
@@ -63,10 +123,51 @@ impl TaskBmc {
         base::update::<Self, _>(ctx, mm, id, task_u).await
     }
 
+    /// Same as [TaskBmc::update] but fails with `ConcurrentModification`
+    /// if the task is no longer at `version`.
+    #[instrument]
+    pub async fn update_if_version(
+        ctx: &Ctx,
+        mm: &ModelManager,
+        id: i64,
+        version: i64,
+        task_u: TaskForUpdate,
+    ) -> Result<()> {
+        base::update_if_version::<Self, _>(ctx, mm, id, version, task_u).await
+    }
+
+    /// Move the task to the trash, it can be restored until it is purged.
     #[instrument]
     pub async fn delete(ctx: &Ctx, mm: &ModelManager, id: i64) -> Result<()> {
         base::delete::<Self>(ctx, mm, id).await
     }
+
+    /// Task in the trash.
+    #[instrument]
+    pub async fn get_deleted(ctx: &Ctx, mm: &ModelManager, id: i64) -> Result<Task> {
+        base::get_deleted::<Self, _>(ctx, mm, id).await
+    }
+
+    #[instrument]
+    pub async fn restore(ctx: &Ctx, mm: &ModelManager, id: i64) -> Result<()> {
+        base::restore::<Self>(ctx, mm, id).await
+    }
+
+    #[instrument]
+    pub async fn list_deleted_page(
+        ctx: &Ctx,
+        mm: &ModelManager,
+        filter: Option<TaskFilter>,
+        list_options: Option<ListOptions>,
+    ) -> Result<ListPage<Task>> {
+        base::list_deleted_page::<Self, _, _>(ctx, mm, filter, list_options).await
+    }
+
+    /// Hard delete the tasks moved to the trash before `deleted_before`.
+    #[instrument]
+    pub async fn purge_deleted(mm: &ModelManager, deleted_before: OffsetDateTime) -> Result<u64> {
+        base::purge_deleted::<Self>(mm, deleted_before).await
+    }
 }
 // endregion: --- TaskBmc
 
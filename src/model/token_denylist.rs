@@ -0,0 +1,172 @@
+use crate::crypt::now_utc;
+use crate::model::base::DbBmc;
+use crate::model::list::quote_ident;
+use crate::model::{ModelManager, Result};
+use sqlx::QueryBuilder;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use time::OffsetDateTime;
+use tokio::sync::OnceCell;
+use tracing::instrument;
+
+// region:    --- Denylist Cache
+
+/// In memory copy of the denylist, so checking a token costs no db round trip.
+///
+/// It is loaded on first use and kept up to date by [TokenDenylistBmc::deny].
+/// With several instances, the entries added by the others are only seen after
+/// the next [TokenDenylistBmc::sync].
+#[derive(Debug, Default)]
+pub struct DenylistCache {
+    // token_id -> expires_at
+    entries: OnceCell<RwLock<HashMap<String, OffsetDateTime>>>,
+}
+
+impl DenylistCache {
+    async fn entries(&self, mm: &ModelManager) -> Result<&RwLock<HashMap<String, OffsetDateTime>>> {
+        self.entries
+            .get_or_try_init(|| async { TokenDenylistBmc::load(mm).await.map(RwLock::new) })
+            .await
+    }
+}
+
+/// Merge the entries loaded by a sync into the cache, without the expired ones.
+///
+/// The entries denied since the load are not in `loaded`, so the cache is never replaced.
+fn merge_loaded(
+    entries: &mut HashMap<String, OffsetDateTime>,
+    loaded: HashMap<String, OffsetDateTime>,
+    now: OffsetDateTime,
+) {
+    entries.retain(|_, expires_at| *expires_at >= now);
+    entries.extend(loaded);
+}
+
+// endregion: --- Denylist Cache
+
+pub struct TokenDenylistBmc;
+
+impl DbBmc for TokenDenylistBmc {
+    const TABLE: &'static str = "token_denylist";
+}
+
+impl TokenDenylistBmc {
+    /// Revoke a web token until its expiration.
+    #[instrument(skip(mm))]
+    pub async fn deny(
+        mm: &ModelManager,
+        user_id: i64,
+        token_id: &str,
+        expires_at: OffsetDateTime,
+    ) -> Result<()> {
+        let db = mm.db();
+
+        let mut qb = QueryBuilder::new(format!(
+            "INSERT INTO {} (\"token_id\", \"user_id\", \"expires_at\") VALUES (",
+            quote_ident(Self::TABLE)
+        ));
+        qb.push_bind(token_id);
+        qb.push(", ").push_bind(user_id);
+        qb.push(", ").push_bind(expires_at);
+        qb.push(") ON CONFLICT (\"token_id\") DO NOTHING");
+        qb.build().execute(db).await?;
+
+        let entries = mm.denylist().entries(mm).await?;
+        entries
+            .write()
+            .expect("denylist lock poisoned")
+            .insert(token_id.to_string(), expires_at);
+
+        Ok(())
+    }
+
+    /// Whether the web token has been revoked, answered from the cache.
+    pub async fn is_denied(mm: &ModelManager, token_id: &str) -> Result<bool> {
+        let entries = mm.denylist().entries(mm).await?;
+        let is_denied = entries
+            .read()
+            .expect("denylist lock poisoned")
+            .contains_key(token_id);
+
+        Ok(is_denied)
+    }
+
+    /// Purge the expired entries and merge the db entries into the cache,
+    /// returns the number of purged entries.
+    #[instrument(skip(mm))]
+    pub async fn sync(mm: &ModelManager) -> Result<u64> {
+        let db = mm.db();
+
+        let mut qb = QueryBuilder::new(format!(
+            "DELETE FROM {} WHERE \"expires_at\" < ",
+            quote_ident(Self::TABLE)
+        ));
+        qb.push_bind(now_utc());
+        let count = qb.build().execute(db).await?.rows_affected();
+
+        let loaded = Self::load(mm).await?;
+        let entries = mm.denylist().entries(mm).await?;
+        merge_loaded(
+            &mut entries.write().expect("denylist lock poisoned"),
+            loaded,
+            now_utc(),
+        );
+
+        Ok(count)
+    }
+
+    async fn load(mm: &ModelManager) -> Result<HashMap<String, OffsetDateTime>> {
+        let db = mm.db();
+
+        let mut qb = QueryBuilder::new(format!(
+            "SELECT \"token_id\", \"expires_at\" FROM {} WHERE \"expires_at\" >= ",
+            quote_ident(Self::TABLE)
+        ));
+        qb.push_bind(now_utc());
+        let rows = qb
+            .build_query_as::<(String, OffsetDateTime)>()
+            .fetch_all(db)
+            .await?;
+
+        Ok(rows.into_iter().collect())
+    }
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::Duration;
+
+    #[test]
+    fn test_merge_loaded_ok_keeps_the_entry_denied_during_the_sync() {
+        // -- Setup & Fixtures
+        let now = now_utc();
+        let fx_expires_at = now + Duration::minutes(5);
+        let mut entries = HashMap::from([("fx-synced".to_string(), fx_expires_at)]);
+        // Loaded by the sync, before the deny.
+        let loaded = entries.clone();
+        entries.insert("fx-denied".to_string(), fx_expires_at);
+
+        // -- Exec
+        merge_loaded(&mut entries, loaded, now);
+
+        // -- Check
+        assert!(entries.contains_key("fx-synced"));
+        assert!(entries.contains_key("fx-denied"));
+    }
+
+    #[test]
+    fn test_merge_loaded_ok_drops_the_expired() {
+        // -- Setup & Fixtures
+        let now = now_utc();
+        let mut entries = HashMap::from([("fx-expired".to_string(), now - Duration::minutes(5))]);
+
+        // -- Exec
+        merge_loaded(&mut entries, HashMap::new(), now);
+
+        // -- Check
+        assert!(entries.is_empty());
+    }
+}
+// endregion: --- Tests
@@ -1,12 +1,16 @@
+use crate::crypt::now_utc;
 use crate::crypt::{EncryptContent, pwd};
 use crate::ctx::Ctx;
-use crate::model::ModelManager;
-use crate::model::Result;
-use crate::model::base::{self, DbBmc};
+use crate::model::base::{self, DbBmc, UtcTime};
+use crate::model::list::quote_ident;
+use crate::model::role::{DEFAULT_ROLE, RoleBmc};
+use crate::model::user_identity::{UserIdentityBmc, UserIdentityForCreate};
+use crate::model::{Error, ModelManager, Result};
 use serde::{Deserialize, Serialize};
 use sqlb::{Fields, HasFields};
-use sqlx::FromRow;
 use sqlx::postgres::PgRow;
+use sqlx::{FromRow, QueryBuilder};
+use time::OffsetDateTime;
 use tracing::instrument;
 use uuid::Uuid;
 
@@ -15,18 +19,22 @@ use uuid::Uuid;
 pub struct User {
     pub id: i64,
     pub username: String,
+    pub email: Option<String>,
+
+    // -- Timestamps
+    pub cid: i64,
+    #[serde(with = "time::serde::rfc3339")]
+    pub ctime: OffsetDateTime,
+    pub mid: i64,
+    #[serde(with = "time::serde::rfc3339")]
+    pub mtime: OffsetDateTime,
 }
 
 #[derive(Deserialize)]
 pub struct UserForCreate {
-    pub _username: String,
-    pub _pwd_clear: String,
-}
-
-#[derive(Fields)]
-#[allow(dead_code)]
-struct UserForInsert {
-    username: String,
+    pub username: String,
+    pub email: Option<String>,
+    pub pwd_clear: String,
 }
 
 #[derive(Clone, FromRow, Fields, Debug)]
@@ -38,6 +46,9 @@ pub struct UserForLogin {
     pub pwd: Option<String>, // encrypted, #_scheme_id_#....
     pub pwd_salt: Uuid,
     pub token_salt: Uuid,
+
+    // -- 2fa
+    pub totp_enabled: bool,
 }
 
 #[derive(Clone, FromRow, Fields, Debug)]
@@ -49,15 +60,39 @@ pub struct UserForAuth {
     pub token_salt: Uuid,
 }
 
+#[derive(Clone, FromRow, Fields, Debug)]
+pub struct UserForTotp {
+    pub id: i64,
+    pub username: String,
+    pub token_salt: Uuid,
+
+    // -- 2fa info
+    pub totp_secret: Option<String>,
+    pub totp_enabled: bool,
+    pub totp_last_step: Option<i64>,
+}
+
+/// Number of password hashes made with a pwd key.
+#[derive(Debug, FromRow)]
+pub struct PwdKeyUsage {
+    /// `None` for the hashes made before the key ids were introduced.
+    pub kid: Option<String>,
+    pub count: i64,
+}
+
 /// Marker trait
 pub trait UserBy: HasFields + for<'r> FromRow<'r, PgRow> + Unpin + Send {}
 
 impl UserBy for User {}
 impl UserBy for UserForLogin {}
 impl UserBy for UserForAuth {}
+impl UserBy for UserForTotp {}
 
 // endregion: --- User Types
 
+/// Unique constraint of the usernames, as named by Postgres.
+pub const USERNAME_CONSTRAINT: &str = "user_username_key";
+
 pub struct UserBmc;
 
 impl DbBmc for UserBmc {
@@ -65,8 +100,94 @@ impl DbBmc for UserBmc {
 }
 
 impl UserBmc {
+    /// Insert the user and set its password.
+    /// Fails with `UniqueViolation` if the username is already taken.
+    #[instrument(skip(user_c))]
+    pub async fn create(ctx: &Ctx, mm: &ModelManager, user_c: UserForCreate) -> Result<i64> {
+        let UserForCreate {
+            username,
+            email,
+            pwd_clear,
+        } = user_c;
+
+        // -- Hashed before the transaction, so it is not held during the hashing.
+        let pwd_salt = Uuid::new_v4();
+        let pwd = pwd::hash_pwd(EncryptContent {
+            content: pwd_clear,
+            salt: pwd_salt.to_string(),
+        })
+        .await?;
+
+        Self::insert(ctx, mm, username, email, pwd_salt, Some(pwd), None).await
+    }
+
+    /// Insert a user who can only log in through its identity, until a password is set,
+    /// and link the identity in the same transaction.
+    /// Fails with `UniqueViolation` if the username, the email or the identity is already taken.
+    #[instrument(skip(mm, email, identity))]
+    pub async fn create_with_identity(
+        ctx: &Ctx,
+        mm: &ModelManager,
+        username: String,
+        email: Option<String>,
+        identity: &UserIdentityForCreate<'_>,
+    ) -> Result<i64> {
+        Self::insert(
+            ctx,
+            mm,
+            username,
+            email,
+            Uuid::new_v4(),
+            None,
+            Some(identity),
+        )
+        .await
+    }
+
+    /// Insert the user with the default role, and its identity if any, in one transaction.
+    async fn insert(
+        ctx: &Ctx,
+        mm: &ModelManager,
+        username: String,
+        email: Option<String>,
+        pwd_salt: Uuid,
+        pwd: Option<String>,
+        identity: Option<&UserIdentityForCreate<'_>>,
+    ) -> Result<i64> {
+        let mut tx = mm.db().begin().await?;
+
+        let now = now_utc();
+        let mut qb = QueryBuilder::new(format!(
+            "INSERT INTO {} (\"username\", \"email\", \"pwd\", \"pwd_salt\", \"cid\", \"ctime\", \"mid\", \"mtime\") VALUES (",
+            quote_ident(Self::TABLE)
+        ));
+        qb.separated(", ")
+            .push_bind(username)
+            .push_bind(email)
+            .push_bind(pwd)
+            .push_bind(pwd_salt)
+            .push_bind(ctx.user_id())
+            .push_bind(now)
+            .push_bind(ctx.user_id())
+            .push_bind(now);
+        qb.push(") RETURNING \"id\"");
+        let (user_id,) = qb
+            .build_query_as::<(i64,)>()
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|ex| base::insert_error(Self::TABLE, ex))?;
+        RoleBmc::assign(&mut *tx, user_id, DEFAULT_ROLE).await?;
+        if let Some(identity) = identity {
+            UserIdentityBmc::insert(&mut *tx, user_id, identity).await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(user_id)
+    }
+
     #[instrument]
-    pub async fn _get<E>(ctx: &Ctx, mm: &ModelManager, id: i64) -> Result<E>
+    pub async fn get<E>(ctx: &Ctx, mm: &ModelManager, id: i64) -> Result<E>
     where
         E: UserBy,
     {
@@ -93,25 +214,209 @@ impl UserBmc {
         Ok(user)
     }
 
-    #[instrument]
-    pub async fn _update_pwd(ctx: &Ctx, mm: &ModelManager, id: i64, pwd_clear: &str) -> Result<()> {
+    /// The emails are compared case insensitively, as by their unique index.
+    #[instrument(name = "DB first_by_email", skip(email))]
+    pub async fn first_by_email<E>(_ctx: &Ctx, mm: &ModelManager, email: &str) -> Result<Option<E>>
+    where
+        E: UserBy,
+    {
+        let db = mm.db();
+
+        let columns = E::field_names()
+            .iter()
+            .map(|column| quote_ident(column))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut qb = QueryBuilder::new(format!(
+            "SELECT {columns} FROM {} WHERE lower(\"email\") = lower(",
+            quote_ident(Self::TABLE)
+        ));
+        qb.push_bind(email);
+        qb.push(")");
+        let user = qb.build_query_as::<E>().fetch_optional(db).await?;
+
+        Ok(user)
+    }
+
+    #[instrument(skip(pwd_clear))]
+    pub async fn update_pwd(ctx: &Ctx, mm: &ModelManager, id: i64, pwd_clear: &str) -> Result<()> {
         let db = mm.db();
 
-        let user: UserForLogin = Self::_get(ctx, mm, id).await?;
-        let pwd = pwd::encrypt_pwd(&EncryptContent {
+        let user: UserForLogin = Self::get(ctx, mm, id).await?;
+        let pwd = pwd::hash_pwd(EncryptContent {
             content: pwd_clear.to_string(),
             salt: user.pwd_salt.to_string(),
-        })?;
+        })
+        .await?;
 
         sqlb::update()
             .table(Self::TABLE)
             .and_where("id", "=", id)
-            .data(vec![("pwd", pwd.to_string()).into()])
+            .data(vec![
+                ("pwd", pwd.to_string()).into(),
+                ("mid", ctx.user_id()).into(),
+                ("mtime", UtcTime(now_utc())).into(),
+            ])
             .exec(db)
             .await?;
 
         Ok(())
     }
+
+    /// Count the password hashes per key id, to know when a retired pwd key can be removed.
+    /// The hashes are only rewrapped with the active key on login.
+    #[instrument(skip(mm))]
+    pub async fn pwd_key_usage(mm: &ModelManager) -> Result<Vec<PwdKeyUsage>> {
+        let db = mm.db();
+
+        // Hashes are stored as `#scheme#kid#hashed`, or `#scheme#hashed` without kid.
+        let usages = sqlx::query_as::<_, PwdKeyUsage>(
+            r#"SELECT
+                CASE WHEN pwd ~ '^#[^#]+#[^#]+#' THEN split_part(pwd, '#', 3) END AS kid,
+                count(*) AS count
+            FROM "user"
+            WHERE pwd IS NOT NULL
+            GROUP BY 1
+            ORDER BY 1"#,
+        )
+        .fetch_all(db)
+        .await?;
+
+        Ok(usages)
+    }
+
+    /// Replace the token salt by a new random one,
+    /// every token signed with the previous salt becomes invalid.
+    #[instrument]
+    pub async fn rotate_token_salt(ctx: &Ctx, mm: &ModelManager, id: i64) -> Result<()> {
+        let db = mm.db();
+
+        // The uuid column can not be bound through sqlb.
+        let mut qb = QueryBuilder::new(format!(
+            "UPDATE {} SET \"token_salt\" = ",
+            quote_ident(Self::TABLE)
+        ));
+        qb.push_bind(Uuid::new_v4());
+        qb.push(", \"mid\" = ").push_bind(ctx.user_id());
+        qb.push(", \"mtime\" = ").push_bind(now_utc());
+        qb.push(" WHERE \"id\" = ").push_bind(id);
+
+        let count = qb.build().execute(db).await?.rows_affected();
+
+        if count == 0 {
+            Err(Error::EntityNotFound {
+                entity: Self::TABLE,
+                id,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Set a new totp secret, 2fa stays disabled until a first code is verified.
+    #[instrument(skip(totp_secret))]
+    pub async fn set_totp_secret(
+        ctx: &Ctx,
+        mm: &ModelManager,
+        id: i64,
+        totp_secret: &str,
+    ) -> Result<()> {
+        let db = mm.db();
+
+        let mut qb = QueryBuilder::new(format!(
+            "UPDATE {} SET \"totp_secret\" = ",
+            quote_ident(Self::TABLE)
+        ));
+        qb.push_bind(totp_secret);
+        qb.push(", \"totp_enabled\" = false, \"totp_last_step\" = NULL");
+        qb.push(", \"mid\" = ").push_bind(ctx.user_id());
+        qb.push(", \"mtime\" = ").push_bind(now_utc());
+        qb.push(" WHERE \"id\" = ").push_bind(id);
+
+        let count = qb.build().execute(db).await?.rows_affected();
+
+        if count == 0 {
+            Err(Error::EntityNotFound {
+                entity: Self::TABLE,
+                id,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Enable 2fa, the step of the verified code is consumed.
+    #[instrument]
+    pub async fn enable_totp(ctx: &Ctx, mm: &ModelManager, id: i64, step: i64) -> Result<()> {
+        let db = mm.db();
+
+        let mut qb = QueryBuilder::new(format!(
+            "UPDATE {} SET \"totp_enabled\" = true, \"totp_last_step\" = ",
+            quote_ident(Self::TABLE)
+        ));
+        qb.push_bind(step);
+        qb.push(", \"mid\" = ").push_bind(ctx.user_id());
+        qb.push(", \"mtime\" = ").push_bind(now_utc());
+        qb.push(" WHERE \"id\" = ").push_bind(id);
+        qb.push(" AND \"totp_secret\" IS NOT NULL");
+
+        let count = qb.build().execute(db).await?.rows_affected();
+
+        if count == 0 {
+            Err(Error::EntityNotFound {
+                entity: Self::TABLE,
+                id,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Disable 2fa and forget the secret, it must be set up again to be enabled.
+    #[instrument(skip(mm))]
+    pub async fn disable_totp(ctx: &Ctx, mm: &ModelManager, id: i64) -> Result<()> {
+        let db = mm.db();
+
+        let mut qb = QueryBuilder::new(format!(
+            "UPDATE {} SET \"totp_enabled\" = false, \"totp_secret\" = NULL, \"totp_last_step\" = NULL",
+            quote_ident(Self::TABLE)
+        ));
+        qb.push(", \"mid\" = ").push_bind(ctx.user_id());
+        qb.push(", \"mtime\" = ").push_bind(now_utc());
+        qb.push(" WHERE \"id\" = ").push_bind(id);
+
+        let count = qb.build().execute(db).await?.rows_affected();
+
+        if count == 0 {
+            Err(Error::EntityNotFound {
+                entity: Self::TABLE,
+                id,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Record the step of a verified code, returns `false` if a code
+    /// of the same or a later step has been used in the meantime.
+    #[instrument(skip(mm))]
+    pub async fn use_totp_step(mm: &ModelManager, id: i64, step: i64) -> Result<bool> {
+        let db = mm.db();
+
+        let mut qb = QueryBuilder::new(format!(
+            "UPDATE {} SET \"totp_last_step\" = ",
+            quote_ident(Self::TABLE)
+        ));
+        qb.push_bind(step);
+        qb.push(" WHERE \"id\" = ").push_bind(id);
+        qb.push(" AND (\"totp_last_step\" IS NULL OR \"totp_last_step\" < ")
+            .push_bind(step)
+            .push(")");
+
+        let count = qb.build().execute(db).await?.rows_affected();
+
+        Ok(count > 0)
+    }
 }
 
 // // region:    --- Tests
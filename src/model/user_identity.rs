@@ -0,0 +1,82 @@
+use crate::model::base::{self, DbBmc};
+use crate::model::list::quote_ident;
+use crate::model::{ModelManager, Result};
+use sqlx::{PgExecutor, QueryBuilder};
+use tracing::instrument;
+
+/// Identity of a user at an OpenID Connect provider.
+pub struct UserIdentityForCreate<'a> {
+    /// Name of the provider in the config.
+    pub provider: &'a str,
+    /// `sub` claim of the id token.
+    pub subject: &'a str,
+    /// Mail address given by the provider, for information only.
+    pub email: Option<&'a str>,
+}
+
+pub struct UserIdentityBmc;
+
+impl DbBmc for UserIdentityBmc {
+    const TABLE: &'static str = "user_identity";
+    const OWNER_COLUMN: Option<&'static str> = Some("user_id");
+}
+
+impl UserIdentityBmc {
+    /// The user linked to the subject of the provider, if any.
+    #[instrument(name = "DB first_user_id", skip(mm))]
+    pub async fn first_user_id(
+        mm: &ModelManager,
+        provider: &str,
+        subject: &str,
+    ) -> Result<Option<i64>> {
+        let db = mm.db();
+
+        let mut qb = QueryBuilder::new(format!(
+            "SELECT \"user_id\" FROM {} WHERE \"provider\" = ",
+            quote_ident(Self::TABLE)
+        ));
+        qb.push_bind(provider);
+        qb.push(" AND \"subject\" = ").push_bind(subject);
+
+        let user_id = qb.build_query_scalar::<i64>().fetch_optional(db).await?;
+
+        Ok(user_id)
+    }
+
+    /// Link the identity to the user.
+    /// Fails with `UniqueViolation` if it is already linked.
+    #[instrument(skip(mm, identity))]
+    pub async fn create(
+        mm: &ModelManager,
+        user_id: i64,
+        identity: &UserIdentityForCreate<'_>,
+    ) -> Result<i64> {
+        Self::insert(mm.db(), user_id, identity).await
+    }
+
+    /// Same as [UserIdentityBmc::create], but takes the executor,
+    /// so it can run in the transaction creating the user.
+    pub(in crate::model) async fn insert(
+        db: impl PgExecutor<'_>,
+        user_id: i64,
+        identity: &UserIdentityForCreate<'_>,
+    ) -> Result<i64> {
+        let mut qb = QueryBuilder::new(format!(
+            "INSERT INTO {} (\"user_id\", \"provider\", \"subject\", \"email\") VALUES (",
+            quote_ident(Self::TABLE)
+        ));
+        qb.push_bind(user_id);
+        qb.push(", ").push_bind(identity.provider);
+        qb.push(", ").push_bind(identity.subject);
+        qb.push(", ").push_bind(identity.email);
+        qb.push(") RETURNING \"id\"");
+
+        let id = qb
+            .build_query_scalar::<i64>()
+            .fetch_one(db)
+            .await
+            .map_err(|ex| base::insert_error(Self::TABLE, ex))?;
+
+        Ok(id)
+    }
+}
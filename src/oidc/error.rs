@@ -0,0 +1,17 @@
+use thiserror::Error;
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("No oidc provider named {0:?}")]
+    ProviderUnknown(String),
+    #[error("Request to the oidc provider {provider:?} failed : {cause}")]
+    ProviderRequest { provider: String, cause: String },
+    #[error("The issuer discovered for the oidc provider {provider:?} is not the configured one")]
+    IssuerNotMatching { provider: String },
+    #[error("The id token of the oidc provider {provider:?} is not valid : {cause}")]
+    IdTokenInvalid { provider: String, cause: String },
+    #[error("The nonce of the id token of the oidc provider {provider:?} is not matching")]
+    NonceNotMatching { provider: String },
+}
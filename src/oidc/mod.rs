@@ -0,0 +1,296 @@
+// region:    --- Modules
+mod error;
+
+pub use self::error::{Error, Result};
+
+use crate::config::{OidcProvider, OidcSettings};
+use crate::crypt::{ct_eq, sha256_into_b64u};
+use jsonwebtoken::jwk::{AlgorithmParameters, JwkSet};
+use jsonwebtoken::{DecodingKey, Validation, decode, decode_header};
+use rand::RngCore;
+use reqwest::Url;
+use secrecy::ExposeSecret;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tokio::sync::OnceCell;
+// endregion: --- Modules
+
+// region:    --- Auth Flow
+
+/// State of a login, kept by the client between the redirection to the provider and the callback.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuthFlow {
+    pub provider: String,
+    /// Returned as is by the provider, binds the callback to the client which started the login.
+    pub state: String,
+    /// Copied by the provider in the id token, so an id token can not be replayed.
+    pub nonce: String,
+    /// PKCE (RFC 7636) secret, only its challenge is sent with the authorization request.
+    pub code_verifier: String,
+    /// User of the session linking the identity, `None` for a login.
+    pub link_user_id: Option<i64>,
+}
+
+impl AuthFlow {
+    pub fn new(provider: &str, link_user_id: Option<i64>) -> Self {
+        Self {
+            provider: provider.to_string(),
+            state: new_random_b64u(),
+            nonce: new_random_b64u(),
+            code_verifier: new_random_b64u(),
+            link_user_id,
+        }
+    }
+
+    /// `S256` challenge of the code verifier.
+    pub fn code_challenge(&self) -> String {
+        sha256_into_b64u(&self.code_verifier)
+    }
+}
+
+/// 32 random bytes, base64url encoded.
+fn new_random_b64u() -> String {
+    let mut bytes = [0u8; 32];
+    rand::rng().fill_bytes(&mut bytes);
+
+    base64_url::encode(&bytes)
+}
+
+// endregion: --- Auth Flow
+
+// region:    --- Provider Types
+
+/// Subset of the discovery document (OpenID Connect Discovery 1.0).
+#[derive(Debug, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// Claims of a validated id token.
+#[derive(Debug, Deserialize)]
+pub struct IdTokenClaims {
+    /// Id of the user at the provider, stable and unique per provider.
+    pub sub: String,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    pub preferred_username: Option<String>,
+    nonce: Option<String>,
+}
+
+// endregion: --- Provider Types
+
+/// Client of the configured OpenID Connect providers, for the authorization code flow with PKCE.
+#[derive(Debug)]
+pub struct OidcClient {
+    http: reqwest::Client,
+    redirect_base_url: String,
+    providers: Vec<OidcProvider>,
+    /// Discovery documents, fetched on first use.
+    metadata: HashMap<String, OnceCell<ProviderMetadata>>,
+}
+
+impl OidcClient {
+    pub fn new(settings: OidcSettings) -> Self {
+        let metadata = settings
+            .providers
+            .iter()
+            .map(|provider| (provider.name.clone(), OnceCell::new()))
+            .collect();
+
+        Self {
+            http: reqwest::Client::new(),
+            redirect_base_url: settings.redirect_base_url,
+            providers: settings.providers,
+            metadata,
+        }
+    }
+
+    pub fn provider(&self, name: &str) -> Result<&OidcProvider> {
+        self.providers
+            .iter()
+            .find(|provider| provider.name == name)
+            .ok_or_else(|| Error::ProviderUnknown(name.to_string()))
+    }
+
+    /// Callback url registered at the provider, under the configured redirect base url.
+    pub fn redirect_uri(&self, provider: &OidcProvider) -> String {
+        format!(
+            "{}/account/oidc/{}/callback",
+            self.redirect_base_url, provider.name
+        )
+    }
+
+    /// Url of the provider to redirect the client to.
+    pub async fn authorization_url(
+        &self,
+        provider: &OidcProvider,
+        redirect_uri: &str,
+        flow: &AuthFlow,
+    ) -> Result<String> {
+        let metadata = self.metadata(provider).await?;
+
+        let mut url = Url::parse(&metadata.authorization_endpoint)
+            .map_err(|ex| request_error(provider, ex))?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &provider.client_id)
+            .append_pair("redirect_uri", redirect_uri)
+            .append_pair("scope", &provider.scopes.join(" "))
+            .append_pair("state", &flow.state)
+            .append_pair("nonce", &flow.nonce)
+            .append_pair("code_challenge", &flow.code_challenge())
+            .append_pair("code_challenge_method", "S256");
+
+        Ok(url.into())
+    }
+
+    /// Exchange the authorization code for an id token, and validate it.
+    ///
+    /// The `state` of the callback must be validated by the caller beforehand.
+    pub async fn authenticate(
+        &self,
+        provider: &OidcProvider,
+        redirect_uri: &str,
+        code: &str,
+        flow: &AuthFlow,
+    ) -> Result<IdTokenClaims> {
+        let metadata = self.metadata(provider).await?;
+
+        // -- Exchange the code.
+        let request = self
+            .http
+            .post(&metadata.token_endpoint)
+            .basic_auth(
+                &provider.client_id,
+                Some(provider.client_secret.expose_secret()),
+            )
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", redirect_uri),
+                ("client_id", provider.client_id.as_str()),
+                ("code_verifier", flow.code_verifier.as_str()),
+            ]);
+        let token: TokenResponse = self.send_json(provider, request).await?;
+
+        // -- Validate the id token.
+        let id_token_error = |cause: String| Error::IdTokenInvalid {
+            provider: provider.name.clone(),
+            cause,
+        };
+        let header = decode_header(&token.id_token).map_err(|ex| id_token_error(ex.to_string()))?;
+        let jwks: JwkSet = self
+            .send_json(provider, self.http.get(&metadata.jwks_uri))
+            .await?;
+        let jwk = match &header.kid {
+            Some(kid) => jwks.find(kid),
+            None => jwks.keys.first(),
+        }
+        .ok_or_else(|| id_token_error(format!("no key for the kid {:?}", header.kid)))?;
+        // A symmetric key would be known by the client, which could then sign its own id tokens.
+        if let AlgorithmParameters::OctetKey(_) = jwk.algorithm {
+            return Err(id_token_error(
+                "symmetric keys are not accepted".to_string(),
+            ));
+        }
+        let key = DecodingKey::from_jwk(jwk).map_err(|ex| id_token_error(ex.to_string()))?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&metadata.issuer]);
+        validation.set_audience(&[&provider.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        let claims = decode::<IdTokenClaims>(&token.id_token, &key, &validation)
+            .map_err(|ex| id_token_error(ex.to_string()))?
+            .claims;
+
+        let nonce_matches = claims
+            .nonce
+            .as_deref()
+            .is_some_and(|nonce| ct_eq(nonce, &flow.nonce));
+        if !nonce_matches {
+            return Err(Error::NonceNotMatching {
+                provider: provider.name.clone(),
+            });
+        }
+
+        Ok(claims)
+    }
+
+    async fn metadata(&self, provider: &OidcProvider) -> Result<&ProviderMetadata> {
+        let cell = self
+            .metadata
+            .get(&provider.name)
+            .ok_or_else(|| Error::ProviderUnknown(provider.name.clone()))?;
+
+        cell.get_or_try_init(|| async {
+            let url = format!(
+                "{}/.well-known/openid-configuration",
+                provider.issuer.trim_end_matches('/')
+            );
+            let metadata: ProviderMetadata = self.send_json(provider, self.http.get(url)).await?;
+
+            // The issuer of the document must be the one it was fetched from (Discovery 1.0 §4.3).
+            if metadata.issuer.trim_end_matches('/') != provider.issuer.trim_end_matches('/') {
+                return Err(Error::IssuerNotMatching {
+                    provider: provider.name.clone(),
+                });
+            }
+
+            Ok(metadata)
+        })
+        .await
+    }
+
+    async fn send_json<T: DeserializeOwned>(
+        &self,
+        provider: &OidcProvider,
+        request: reqwest::RequestBuilder,
+    ) -> Result<T> {
+        request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|ex| request_error(provider, ex))?
+            .json()
+            .await
+            .map_err(|ex| request_error(provider, ex))
+    }
+}
+
+fn request_error(provider: &OidcProvider, cause: impl ToString) -> Error {
+    Error::ProviderRequest {
+        provider: provider.name.clone(),
+        cause: cause.to_string(),
+    }
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auth_flow_code_challenge_ok_rfc_vector() {
+        // -- Setup & Fixtures
+        // Example of RFC 7636 Appendix B.
+        let mut fx_flow = AuthFlow::new("fx-provider", None);
+        fx_flow.code_verifier = "dBjftJeZ4CVP-mJ92IZF7HjyW7MPZ6iGx-sWDMs0ylGs".to_string();
+
+        // -- Exec & Check
+        assert_eq!(
+            fx_flow.code_challenge(),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+}
+// endregion: --- Tests
@@ -1,14 +1,24 @@
-use crate::config::Config;
+use crate::config::{AuthSettings, Config, Postgres, Trash};
+use crate::crypt::now_utc;
 pub use crate::error::{Error, Result};
+use crate::mailer::{LocalMailer, Mailer};
 use crate::model::ModelManager;
+use crate::model::login_throttle::LoginThrottleBmc;
+use crate::model::task::TaskBmc;
+use crate::model::token_denylist::TokenDenylistBmc;
+use crate::oidc::OidcClient;
 use crate::web;
 use crate::web::Error as ErrorWeb;
+use crate::web::mw_auth::mw_ctx_require;
 use crate::web::mw_res_map::mw_res_map;
 use crate::web::rest::routes_health::routes as routes_health;
 use crate::web::rest::routes_hello::routes as routes_hello;
 use crate::web::rest::routes_login::routes as routes_login;
 use crate::web::rest::routes_static::routes as routes_static;
+use crate::web::rest::routes_task::routes as routes_task;
+use crate::web::rest::routes_well_known::routes as routes_well_known;
 use crate::web::routes_docs::routes as routes_docs;
+use crate::web::rpc::routes as routes_rpc;
 use axum::BoxError;
 use axum::Router;
 use axum::error_handling::HandleErrorLayer;
@@ -17,7 +27,7 @@ use axum::extract::connect_info::IntoMakeServiceWithConnectInfo;
 use axum::http::HeaderValue;
 use axum::http::Method;
 use axum::middleware::AddExtension;
-use axum::middleware::{from_fn_with_state, map_response};
+use axum::middleware::{from_fn, from_fn_with_state, map_response};
 use axum::serve::Serve;
 use http::request::Parts;
 use opentelemetry::metrics::Counter;
@@ -34,8 +44,8 @@ use tower_governor::{GovernorLayer, governor::GovernorConfigBuilder};
 use tower_http::cors::CorsLayer;
 use tower_otel::axum::metrics::OtelMetricsLayer;
 use tower_otel::axum::traces::OtelLoggerLayer;
-use tracing::info;
 use tracing::instrument;
+use tracing::{error, info};
 
 /// Type to hold the newly built server and his listening port
 pub struct Application {
@@ -51,9 +61,20 @@ impl Application {
     /// build the axum server with the provided configuration without lunch it
     #[instrument(skip_all)]
     pub async fn build(config: Config, meter: Meter) -> Result<Self> {
-        let mm = setup_db_migrations().await;
+        let mm = setup_db_migrations(&config.postgres).await;
 
-        let routes = routes(mm, meter);
+        spawn_trash_purge(mm.clone(), &config.trash);
+        spawn_denylist_sync(mm.clone(), &config.auth);
+        spawn_login_throttle_purge(mm.clone(), &config.auth);
+
+        let mailer: Arc<dyn Mailer> = Arc::new(LocalMailer::new(&config.mailer));
+
+        let oidc = Arc::new(OidcClient::new(config.oidc));
+
+        // The handlers read the same settings as the jobs.
+        let auth = Arc::new(config.auth);
+
+        let routes = routes(mm, mailer, oidc, auth, meter);
 
         let addr = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", config.application.port))
             .await
@@ -82,9 +103,9 @@ impl Application {
     }
 }
 
-async fn setup_db_migrations() -> ModelManager {
+async fn setup_db_migrations(postgres: &Postgres) -> ModelManager {
     info!("Create connection to db");
-    let mm = ModelManager::new()
+    let mm = ModelManager::new(postgres)
         .await
         .expect("Failed to create modelManager");
     info!("Creating migrations");
@@ -96,18 +117,103 @@ async fn setup_db_migrations() -> ModelManager {
     mm
 }
 
+/// Hard delete the tasks which have been in the trash for longer than the retention period.
+/// The purge runs in the background, at a fixed interval.
+fn spawn_trash_purge(mm: ModelManager, trash: &Trash) {
+    let retention = i64::try_from(trash.retention_sec)
+        .map(time::Duration::seconds)
+        .unwrap_or(time::Duration::MAX);
+    let mut interval = tokio::time::interval(Duration::from_secs(trash.purge_interval_sec.max(1)));
+
+    tokio::spawn(async move {
+        loop {
+            interval.tick().await;
+            // Nothing can have been deleted before the min date, the run is skipped.
+            let Some(deleted_before) = now_utc().checked_sub(retention) else {
+                error!(
+                    "{:<12} - trash purge - retention of {}s too long, skipped",
+                    "JOB",
+                    retention.whole_seconds()
+                );
+                continue;
+            };
+            match TaskBmc::purge_deleted(&mm, deleted_before).await {
+                Ok(count) => info!("{:<12} - trash purge - {count} task(s) purged", "JOB"),
+                Err(ex) => error!("{:<12} - trash purge - {ex:?}", "JOB"),
+            }
+        }
+    });
+}
+
+/// Reload the revoked tokens cache, for the tokens revoked by the other instances,
+/// and purge the expired entries. The sync runs in the background, at a fixed interval.
+fn spawn_denylist_sync(mm: ModelManager, auth: &AuthSettings) {
+    let mut interval =
+        tokio::time::interval(Duration::from_secs(auth.denylist_sync_interval_sec.max(1)));
+
+    tokio::spawn(async move {
+        loop {
+            interval.tick().await;
+            match TokenDenylistBmc::sync(&mm).await {
+                Ok(count) => info!("{:<12} - denylist sync - {count} token(s) purged", "JOB"),
+                Err(ex) => error!("{:<12} - denylist sync - {ex:?}", "JOB"),
+            }
+        }
+    });
+}
+
+/// Delete the failed logins which are forgotten and no longer locked.
+/// The purge runs in the background, at a fixed interval.
+fn spawn_login_throttle_purge(mm: ModelManager, auth: &AuthSettings) {
+    let window = time::Duration::seconds(auth.lockout_window_sec);
+    let mut interval =
+        tokio::time::interval(Duration::from_secs(auth.lockout_purge_interval_sec.max(1)));
+
+    tokio::spawn(async move {
+        loop {
+            interval.tick().await;
+            let now = now_utc();
+            let Some(window_start) = now.checked_sub(window) else {
+                error!(
+                    "{:<12} - login throttle purge - window of {}s too long, skipped",
+                    "JOB",
+                    window.whole_seconds()
+                );
+                continue;
+            };
+            match LoginThrottleBmc::purge_expired(&mm, now, window_start).await {
+                Ok(count) => info!(
+                    "{:<12} - login throttle purge - {count} key(s) purged",
+                    "JOB"
+                ),
+                Err(ex) => error!("{:<12} - login throttle purge - {ex:?}", "JOB"),
+            }
+        }
+    });
+}
+
 #[derive(Clone, Debug)]
 pub struct SharedState {
     pub metric: OtelMetric,
     pub mm: ModelManager,
+    pub mailer: Arc<dyn Mailer>,
+    pub oidc: Arc<OidcClient>,
+    pub auth: Arc<AuthSettings>,
 }
 
 #[derive(Clone, Debug)]
 pub struct OtelMetric {
     pub app_domain_health_user_count: Counter<u64>,
+    pub app_auth_login_failure_count: Counter<u64>,
 }
 
-fn routes(mm: ModelManager, meter: Meter) -> Router {
+fn routes(
+    mm: ModelManager,
+    mailer: Arc<dyn Mailer>,
+    oidc: Arc<OidcClient>,
+    auth: Arc<AuthSettings>,
+    meter: Meter,
+) -> Router {
     let governor_conf = Arc::new(
         GovernorConfigBuilder::default()
             .per_second(5)
@@ -138,24 +244,41 @@ fn routes(mm: ModelManager, meter: Meter) -> Router {
         .with_unit("users")
         .with_description("The number of users requesting the health info of this service.")
         .build();
+    let app_auth_login_failure_count = meter
+        .u64_counter("app.auth.login.failure.count")
+        .with_unit("logins")
+        .with_description("The number of failed logins, by reason.")
+        .build();
     let otel_metric = OtelMetric {
         app_domain_health_user_count,
+        app_auth_login_failure_count,
     };
 
     let state = SharedState {
         metric: otel_metric,
         mm,
+        mailer,
+        oidc,
+        auth,
     };
 
     let logger = OtelLoggerLayer::default()
         .with_filter(Arc::new(|_req: &Parts| true))
         .with_span_attributes(Arc::new(|_req: &Parts| vec![("MY_APP", "axum")]));
 
+    // Routes only reachable with a valid context
+    let routes_api = Router::new()
+        .merge(routes_rpc(state.mm.clone()))
+        .merge(routes_task().with_state(state.clone()))
+        .route_layer(from_fn(mw_ctx_require));
+
     // Build the main Router
     Router::new()
+        .nest("/api", routes_api)
         .merge(routes_health().with_state(state.clone()))
         .merge(routes_hello())
         .merge(routes_login().with_state(state.clone()))
+        .merge(routes_well_known())
         .merge(routes_static())
         .layer(from_fn_with_state(
             state.mm.clone(),
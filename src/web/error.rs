@@ -1,11 +1,11 @@
-use crate::{crypt, model, web};
+use crate::{crypt, ctx, mailer, model, oidc, web};
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
 use http::{HeaderMap, header};
 use serde::Serialize;
 use std::sync::Arc;
 use thiserror::Error;
-use tracing::error;
+use tracing::{error, info};
 use utoipa::ToSchema;
 
 /// Result type to not have to specified the web::Error type each time for other modules
@@ -23,8 +23,50 @@ pub enum Error {
     LoginFailUserHasNoPwd { user_id: i64 },
     #[error("Password not matching : {user_id:?}")]
     LoginFailPwdNotMatching { user_id: i64 },
+    #[error("Too many failed logins, locked for {retry_after_sec}s")]
+    LoginLocked { retry_after_sec: i64 },
+
+    // -- Password
+    #[error("Current password not matching : {user_id:?}")]
+    PwdChangeFailPwdNotMatching { user_id: i64 },
+    #[error("The password reset token is not valid")]
+    PwdResetTokenInvalid,
+
+    // -- Session
+    #[error("The refresh token is not valid")]
+    RefreshTokenInvalid,
+    #[error("The refresh token has already been used, session {session_id} revoked")]
+    RefreshTokenReused { session_id: i64 },
+
+    // -- 2fa
+    #[error("The mfa pending token is not valid")]
+    MfaTokenInvalid,
+    #[error("The 2fa code or recovery code is not matching : {user_id:?}")]
+    MfaCodeNotMatching { user_id: i64 },
+    #[error("2fa is already enabled : {user_id:?}")]
+    MfaAlreadyEnabled { user_id: i64 },
+    #[error("2fa has not been set up : {user_id:?}")]
+    MfaNotSetUp { user_id: i64 },
+
+    // -- Oidc
+    #[error("The oidc login flow is missing, expired or not matching the callback")]
+    OidcFlowInvalid,
+    #[error("The oidc provider {provider:?} returned an error : {error}")]
+    OidcProviderDenied { provider: String, error: String },
+    #[error("The email of the oidc identity belongs to the user {user_id:?}, who must link it")]
+    OidcIdentityNotLinkable { user_id: i64 },
+    #[error("The oidc identity is already linked to the user {user_id:?}")]
+    OidcIdentityAlreadyLinked { user_id: i64 },
+    #[error("The user {user_id:?} has 2fa, the oidc login would bypass it")]
+    OidcLoginMfaEnabled { user_id: i64 },
 
     // -- RPC
+    #[error("RpcParseFail")]
+    RpcParseFail,
+    #[error("RpcInvalidRequest")]
+    RpcInvalidRequest,
+    #[error("RpcBatchTooLarge : {actual} > {max}")]
+    RpcBatchTooLarge { max: usize, actual: usize },
     #[error("RpcMethodUnknown")]
     RpcMethodUnknown(String),
     #[error("RpcMissingParams")]
@@ -32,6 +74,10 @@ pub enum Error {
     #[error("RpcFailJsonParams")]
     RpcFailJsonParams { rpc_method: String },
 
+    // -- Headers
+    #[error("The If-Match header is not a valid entity tag")]
+    IfMatchInvalid,
+
     // -- Json
     #[error("Wrong json schema provided")]
     JsonSchema,
@@ -53,12 +99,29 @@ pub enum Error {
     /// -- Ctx Error
     #[error("Can't create the context")]
     CtxExt(web::mw_auth::CtxExtError),
+    #[error("The user {user_id} does not have the permission {permission:?}")]
+    PermissionDenied {
+        user_id: i64,
+        permission: &'static str,
+    },
+
+    // -- Api Key
+    #[error("The user {user_id} tried to manage the account with an api key")]
+    ApiKeyCannotManageAccount { user_id: i64 },
+    #[error("The user {user_id} does not have the scope {scope:?} to give to an api key")]
+    ApiKeyScopeNotHeld { user_id: i64, scope: String },
 
     // -- Modules
+    #[error("Ctx error")]
+    Ctx(#[from] ctx::Error),
     #[error("Model layer error")]
     Model(#[from] model::Error),
     #[error("Crypt layer error")]
     Crypt(#[from] crypt::Error),
+    #[error("Mailer error")]
+    Mailer(#[from] mailer::Error),
+    #[error("Oidc error")]
+    Oidc(#[from] oidc::Error),
 
     // -- External Modules
     #[error("SerdeJsonError")]
@@ -89,15 +152,104 @@ impl Error {
             LoginFailUsernameNotFound { .. }
             | LoginFailUserHasNoPwd { .. }
             | LoginFailPwdNotMatching { .. } => (StatusCode::FORBIDDEN, ClientError::LOGIN_FAIL),
+            LoginLocked { retry_after_sec } => (
+                StatusCode::TOO_MANY_REQUESTS,
+                ClientError::LOGIN_LOCKED {
+                    retry_after_sec: *retry_after_sec,
+                },
+            ),
+
+            // -- Password
+            PwdChangeFailPwdNotMatching { .. } => {
+                (StatusCode::FORBIDDEN, ClientError::PWD_CHANGE_FAIL)
+            }
+            PwdResetTokenInvalid => (
+                StatusCode::BAD_REQUEST,
+                ClientError::PWD_RESET_TOKEN_INVALID,
+            ),
+
+            // -- Session
+            RefreshTokenInvalid | RefreshTokenReused { .. } => {
+                (StatusCode::FORBIDDEN, ClientError::REFRESH_TOKEN_INVALID)
+            }
+
+            // -- 2fa
+            MfaTokenInvalid | MfaCodeNotMatching { .. } => {
+                (StatusCode::FORBIDDEN, ClientError::MFA_FAIL)
+            }
+            MfaAlreadyEnabled { .. } => (StatusCode::CONFLICT, ClientError::MFA_ALREADY_ENABLED),
+            MfaNotSetUp { .. } => (StatusCode::CONFLICT, ClientError::MFA_NOT_SET_UP),
+
+            // -- Oidc
+            Oidc(oidc::Error::ProviderUnknown(_)) => {
+                (StatusCode::NOT_FOUND, ClientError::OIDC_PROVIDER_UNKNOWN)
+            }
+            Oidc(oidc::Error::ProviderRequest { .. } | oidc::Error::IssuerNotMatching { .. }) => (
+                StatusCode::BAD_GATEWAY,
+                ClientError::OIDC_PROVIDER_UNAVAILABLE,
+            ),
+            OidcFlowInvalid
+            | OidcProviderDenied { .. }
+            | Oidc(oidc::Error::IdTokenInvalid { .. } | oidc::Error::NonceNotMatching { .. }) => {
+                (StatusCode::FORBIDDEN, ClientError::OIDC_LOGIN_FAIL)
+            }
+            OidcIdentityNotLinkable { .. } => (
+                StatusCode::CONFLICT,
+                ClientError::OIDC_IDENTITY_NOT_LINKABLE,
+            ),
+            OidcIdentityAlreadyLinked { .. } => (
+                StatusCode::CONFLICT,
+                ClientError::OIDC_IDENTITY_ALREADY_LINKED,
+            ),
+            OidcLoginMfaEnabled { .. } => {
+                (StatusCode::FORBIDDEN, ClientError::OIDC_LOGIN_MFA_ENABLED)
+            }
+
+            // -- RPC, answered in the body with the JSON-RPC codes, the status is only logged.
+            RpcParseFail
+            | RpcInvalidRequest
+            | RpcBatchTooLarge { .. }
+            | RpcMethodUnknown(_)
+            | RpcMissingParams { .. }
+            | RpcFailJsonParams { .. } => {
+                (StatusCode::BAD_REQUEST, ClientError::RPC_REQUEST_INVALID)
+            }
 
             // -- Auth
             CtxExt(_) => (StatusCode::FORBIDDEN, ClientError::NO_AUTH),
+            PermissionDenied { .. } => (StatusCode::FORBIDDEN, ClientError::FORBIDDEN),
+
+            // -- Api Key
+            ApiKeyCannotManageAccount { .. } | ApiKeyScopeNotHeld { .. } => {
+                (StatusCode::FORBIDDEN, ClientError::FORBIDDEN)
+            }
 
             // -- Model
             Model(model::Error::EntityNotFound { entity, id }) => (
-                StatusCode::BAD_REQUEST,
+                StatusCode::NOT_FOUND,
                 ClientError::ENTITY_NOT_FOUND { entity, id: *id },
             ),
+            Model(model::Error::UniqueViolation { table, .. }) => (
+                StatusCode::CONFLICT,
+                ClientError::ENTITY_ALREADY_EXISTS { entity: table },
+            ),
+            Model(model::Error::ConcurrentModification { entity, id }) => (
+                StatusCode::CONFLICT,
+                ClientError::CONCURRENT_MODIFICATION { entity, id: *id },
+            ),
+            Model(
+                model_error @ (model::Error::ListLimitOverMax { .. }
+                | model::Error::ListColumnNotAllowed { .. }
+                | model::Error::ListCursorInvalid),
+            ) => (
+                StatusCode::BAD_REQUEST,
+                ClientError::LIST_OPTIONS_INVALID {
+                    detail: model_error.to_string(),
+                },
+            ),
+
+            // -- Headers
+            IfMatchInvalid => (StatusCode::BAD_REQUEST, ClientError::IF_MATCH_INVALID),
 
             // -- Json
             JsonValidation(validation_errors) => (
@@ -127,6 +279,33 @@ impl Error {
             ),
         }
     }
+
+    /// Log the error with the client error it is mapped to,
+    /// as an error for the server errors and the timeouts.
+    pub fn log(&self, status_code: StatusCode, client_error: &ClientError) {
+        let server_error_message = self.as_ref();
+        let server_error_detail = self.to_string();
+        let client_error_message = client_error.as_ref();
+        let client_error_detail = client_error.to_string();
+
+        if status_code.is_server_error() || status_code == StatusCode::REQUEST_TIMEOUT {
+            error!(
+                server_error = "true",
+                server_error_message,
+                server_error_detail,
+                client_error_message,
+                client_error_detail,
+            );
+        } else {
+            info!(
+                server_error = "false",
+                server_error_message,
+                server_error_detail,
+                client_error_message,
+                client_error_detail,
+            );
+        }
+    }
 }
 
 /// Error type that will be sent to the client.
@@ -136,8 +315,40 @@ impl Error {
 pub enum ClientError {
     #[error("The login failed")]
     LOGIN_FAIL,
+    #[error("Too many failed logins, retry in {retry_after_sec} seconds")]
+    LOGIN_LOCKED { retry_after_sec: i64 },
     #[error("The authentication failed")]
     NO_AUTH,
+    #[error("The user is not allowed to perform this action")]
+    FORBIDDEN,
+    #[error("The current password is not matching")]
+    PWD_CHANGE_FAIL,
+    #[error("The password reset token is not valid or has expired")]
+    PWD_RESET_TOKEN_INVALID,
+    #[error("The refresh token is not valid, has expired or has already been used")]
+    REFRESH_TOKEN_INVALID,
+    #[error("The two-factor authentication failed, the code is not valid or the login has expired")]
+    MFA_FAIL,
+    #[error("Two-factor authentication is already enabled")]
+    MFA_ALREADY_ENABLED,
+    #[error("Two-factor authentication must be set up first")]
+    MFA_NOT_SET_UP,
+    #[error("No single sign-on provider with this name")]
+    OIDC_PROVIDER_UNKNOWN,
+    #[error("The single sign-on login failed, please start it again")]
+    OIDC_LOGIN_FAIL,
+    #[error("The single sign-on provider can not be reached, please retry later")]
+    OIDC_PROVIDER_UNAVAILABLE,
+    #[error(
+        "An account already uses this email, log in to it and link the single sign-on from there"
+    )]
+    OIDC_IDENTITY_NOT_LINKABLE,
+    #[error("This single sign-on account is already linked to another account")]
+    OIDC_IDENTITY_ALREADY_LINKED,
+    #[error("The account has two-factor authentication, log in with its password")]
+    OIDC_LOGIN_MFA_ENABLED,
+    #[error("The JSON-RPC request is not valid")]
+    RPC_REQUEST_INVALID,
     #[error("The rate limit has been reached")]
     RATE_LIMIT_EXCEEDED,
     #[error("The request took too long to complete")]
@@ -151,6 +362,16 @@ pub enum ClientError {
     JSON_SCHEMA,
     #[error("The entity {entity} with id {id} does not exist")]
     ENTITY_NOT_FOUND { entity: &'static str, id: i64 },
+    #[error("An entity {entity} with the same values already exists")]
+    ENTITY_ALREADY_EXISTS { entity: &'static str },
+    #[error(
+        "The entity {entity} with id {id} has been modified since it was read, please fetch it again"
+    )]
+    CONCURRENT_MODIFICATION { entity: &'static str, id: i64 },
+    #[error("The If-Match header must be an entity tag returned by the ETag header")]
+    IF_MATCH_INVALID,
+    #[error("The list options are not valid : {detail}")]
+    LIST_OPTIONS_INVALID { detail: String },
     #[error("Service error, please contact the administrator")]
     SERVICE_ERROR,
 }
@@ -9,15 +9,21 @@ pub mod rpc;
 pub use self::error::ClientError;
 pub use self::error::{Error, Result};
 
-use crate::crypt::token::generate_web_token;
+use crate::crypt::token::{RefreshToken, generate_web_token_str};
 use tower_cookies::{Cookie, Cookies};
 
 pub const AUTH_TOKEN: &str = "auth-token";
+pub const REFRESH_TOKEN: &str = "refresh-token";
+/// Header of the api keys, for the machine clients.
+pub const API_KEY_HEADER: &str = "X-API-Key";
+
+/// The refresh cookie is only sent to the account routes, where it is used.
+const REFRESH_TOKEN_PATH: &str = "/account";
 
 fn set_token_cookie(cookies: &Cookies, user: &str, salt: &str) -> Result<()> {
-    let token = generate_web_token(user, salt)?;
+    let token = generate_web_token_str(user, salt)?;
 
-    let mut cookie = Cookie::new(AUTH_TOKEN, token.to_string());
+    let mut cookie = Cookie::new(AUTH_TOKEN, token);
     cookie.set_http_only(true);
     cookie.set_path("/");
 
@@ -34,3 +40,18 @@ fn remove_token_cookie(cookies: &Cookies) -> Result<()> {
 
     Ok(())
 }
+
+fn set_refresh_cookie(cookies: &Cookies, refresh_token: &RefreshToken) {
+    let mut cookie = Cookie::new(REFRESH_TOKEN, refresh_token.to_string());
+    cookie.set_http_only(true);
+    cookie.set_path(REFRESH_TOKEN_PATH);
+
+    cookies.add(cookie);
+}
+
+fn remove_refresh_cookie(cookies: &Cookies) {
+    let mut cookie = Cookie::from(REFRESH_TOKEN);
+    cookie.set_path(REFRESH_TOKEN_PATH);
+
+    cookies.remove(cookie);
+}
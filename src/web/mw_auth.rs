@@ -1,28 +1,30 @@
-use crate::crypt::token::{Token, validate_web_token};
+use crate::config::{TokenSource, config};
+use crate::crypt;
+use crate::crypt::token::{ApiKeyToken, WebToken};
+use crate::crypt::{ct_eq, now_utc};
 use crate::ctx::Ctx;
-use crate::model::ModelManager;
+use crate::model::api_key::{ApiKeyBmc, ApiKeyForAuth};
+use crate::model::role::RoleBmc;
+use crate::model::token_denylist::TokenDenylistBmc;
 use crate::model::user::{UserBmc, UserForAuth};
-use crate::web::AUTH_TOKEN;
+use crate::model::{self, ModelManager};
+use crate::web::{API_KEY_HEADER, AUTH_TOKEN};
 use crate::web::{Error, Result};
 use axum::body::Body;
 use axum::extract::{FromRequestParts, State};
-use axum::http::Request;
 use axum::http::request::Parts;
+use axum::http::{HeaderMap, HeaderValue, Request, header};
 use axum::middleware::Next;
-use axum::response::Response;
+use axum::response::{IntoResponse, Response};
+use futures::future::BoxFuture;
 use serde::Serialize;
+use std::task::{Context, Poll};
 use thiserror::Error;
+use tower::{Layer, Service};
 use tower_cookies::{Cookie, Cookies};
 use tracing::debug;
 
-use super::set_token_cookie;
-
-#[allow(dead_code)]
-pub async fn mw_ctx_require<B>(
-    ctx: Result<Ctx>,
-    req: Request<Body>,
-    next: Next,
-) -> Result<Response> {
+pub async fn mw_ctx_require(ctx: Result<Ctx>, req: Request<Body>, next: Next) -> Result<Response> {
     debug!("{:<12} - mw_ctx_require - {ctx:?}", "MIDDLEWARE");
 
     ctx?;
@@ -30,6 +32,103 @@ pub async fn mw_ctx_require<B>(
     Ok(next.run(req).await)
 }
 
+// region:    --- Require Permission
+
+/// Layer rejecting the requests whose ctx does not hold the permission,
+/// the requests without a valid ctx are rejected like with [mw_ctx_require].
+///
+/// ```ignore
+/// Router::new()
+///     .route("/", post(create_task))
+///     .route_layer(require_permission("task:write"));
+/// ```
+pub fn require_permission(permission: &'static str) -> RequirePermissionLayer {
+    RequirePermissionLayer { permission }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RequirePermissionLayer {
+    permission: &'static str,
+}
+
+impl<S> Layer<S> for RequirePermissionLayer {
+    type Service = RequirePermission<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequirePermission {
+            inner,
+            permission: self.permission,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RequirePermission<S> {
+    inner: S,
+    permission: &'static str,
+}
+
+impl<S> Service<Request<Body>> for RequirePermission<S>
+where
+    S: Service<Request<Body>, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, core::result::Result<Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<core::result::Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        debug!(
+            "{:<12} - require_permission - {}",
+            "MIDDLEWARE", self.permission
+        );
+
+        match check_permission(&req, self.permission) {
+            Ok(()) => Box::pin(self.inner.call(req)),
+            Err(ex) => Box::pin(async move { Ok(ex.into_response()) }),
+        }
+    }
+}
+
+fn check_permission(req: &Request<Body>, permission: &'static str) -> Result<()> {
+    let ctx = req
+        .extensions()
+        .get::<CtxExtResult>()
+        .ok_or(Error::CtxExt(CtxExtError::CtxNotInRequestExt))?
+        .as_ref()
+        .map_err(|ex| Error::CtxExt(ex.clone()))?;
+
+    if ctx.has_permission(permission) {
+        Ok(())
+    } else {
+        Err(Error::PermissionDenied {
+            user_id: ctx.user_id(),
+            permission,
+        })
+    }
+}
+
+// endregion: --- Require Permission
+
+// region:    --- Require User Token
+
+/// The account, and the api keys themselves, are managed with a user token only,
+/// so a leaked key can not be used to create other keys or take over the account.
+pub fn require_user_token(ctx: &Ctx) -> Result<()> {
+    match ctx.api_key_scopes() {
+        Some(_) => Err(Error::ApiKeyCannotManageAccount {
+            user_id: ctx.user_id(),
+        }),
+        None => Ok(()),
+    }
+}
+
+// endregion: --- Require User Token
+
 // Save info in the request extensions
 pub async fn mw_ctx_resolve(
     mm: State<ModelManager>,
@@ -39,12 +138,22 @@ pub async fn mw_ctx_resolve(
 ) -> Result<Response> {
     debug!("{:<12} - mw_ctx_resolve", "MIDDLEWARE");
 
-    let ctx_ext_result = _ctx_resolve(mm, &cookies).await;
+    // An api key takes precedence, the machine clients do not have cookies.
+    let api_key = req.headers().get(API_KEY_HEADER).cloned();
+    let ctx_ext_result = match (api_key, get_token(&cookies, req.headers())) {
+        (Some(api_key), _) => _ctx_resolve_api_key(&mm, &api_key).await,
+        (None, Ok((token, source))) => {
+            let ctx_ext_result = _ctx_resolve(mm, &token).await;
 
-    // Remove the cookie if something went wrong other than NoAuthTokenCookie.
-    if ctx_ext_result.is_err() && !matches!(ctx_ext_result, Err(CtxExtError::TokenNotInCookie)) {
-        cookies.remove(Cookie::from(AUTH_TOKEN))
-    }
+            // Remove the cookie if its token is not valid.
+            if ctx_ext_result.is_err() && source == TokenSource::Cookie {
+                cookies.remove(Cookie::from(AUTH_TOKEN))
+            }
+
+            ctx_ext_result
+        }
+        (None, Err(ex)) => Err(ex),
+    };
 
     // Store the ctx_result in the request extension.
     req.extensions_mut().insert(ctx_ext_result);
@@ -52,32 +161,152 @@ pub async fn mw_ctx_resolve(
     Ok(next.run(req).await)
 }
 
-async fn _ctx_resolve(mm: State<ModelManager>, cookies: &Cookies) -> CtxExtResult {
-    // -- Get Token String
-    let token = cookies
-        .get(AUTH_TOKEN)
-        .map(|c| c.value().to_string())
-        .ok_or(CtxExtError::TokenNotInCookie)?;
-
+/// The token is not re-issued here, the clients get a new one from `/account/refresh`.
+async fn _ctx_resolve(mm: State<ModelManager>, token: &str) -> CtxExtResult {
     // -- Parse Token
-    let token: Token = token.parse().map_err(|_| CtxExtError::TokenWrongFormat)?;
+    // The jwt signature is already validated here, it does not depend on the user.
+    let token: WebToken = token.parse().map_err(|ex| match ex {
+        crypt::Error::TokenInvalidFormat
+        | crypt::Error::TokenCannotDecodeIdent
+        | crypt::Error::TokenCannotDecodeExp => CtxExtError::TokenWrongFormat,
+        _ => CtxExtError::FailValidate,
+    })?;
+
+    // -- Check Revocation
+    // Answered from the in memory denylist, without a db round trip.
+    let is_denied = TokenDenylistBmc::is_denied(&mm, &token.id())
+        .await
+        .map_err(|ex| CtxExtError::ModelAccessError(ex.to_string()))?;
+    if is_denied {
+        return Err(CtxExtError::TokenRevoked);
+    }
 
     // -- Get UserForAuth
-    let user: UserForAuth = UserBmc::first_by_username(&Ctx::root_ctx(), &mm, &token.ident)
+    let user: UserForAuth = UserBmc::first_by_username(&Ctx::root_ctx(), &mm, token.ident())
         .await
         .map_err(|ex| CtxExtError::ModelAccessError(ex.to_string()))?
         .ok_or(CtxExtError::UserNotFound)?;
 
     // -- Validate Token
-    validate_web_token(&token, &user.token_salt.to_string())
+    token
+        .validate(&user.token_salt.to_string())
         .map_err(|_| CtxExtError::FailValidate)?;
 
-    // -- Update Token
-    set_token_cookie(cookies, &user.username, &user.token_salt.to_string())
-        .map_err(|_| CtxExtError::CannotSetTokenCookie)?;
+    // -- Load Permissions
+    let permissions = RoleBmc::list_permissions(&mm, user.id)
+        .await
+        .map_err(|ex| CtxExtError::ModelAccessError(ex.to_string()))?;
+
+    // -- Create CtxExtResult
+    Ctx::new(user.id)
+        .map(|ctx| ctx.with_permissions(permissions))
+        .map_err(|ex| CtxExtError::CtxCreateFail(ex.to_string()))
+}
+
+/// The ctx of an api key has the permissions of its user, limited to the key scopes.
+async fn _ctx_resolve_api_key(mm: &ModelManager, api_key: &HeaderValue) -> CtxExtResult {
+    // -- Parse Api Key
+    let api_key: ApiKeyToken = api_key
+        .to_str()
+        .map_err(|_| CtxExtError::ApiKeyWrongFormat)?
+        .trim()
+        .parse()
+        .map_err(|_| CtxExtError::ApiKeyWrongFormat)?;
+
+    // -- Get ApiKeyForAuth
+    let key: ApiKeyForAuth = match ApiKeyBmc::get_for_auth(mm, api_key.key_id).await {
+        Ok(key) => key,
+        Err(model::Error::EntityNotFound { .. }) => return Err(CtxExtError::ApiKeyInvalid),
+        Err(ex) => return Err(CtxExtError::ModelAccessError(ex.to_string())),
+    };
+
+    // -- Validate Api Key
+    if !ct_eq(&api_key.hash(), &key.key_hash) {
+        return Err(CtxExtError::ApiKeyInvalid);
+    }
+    if key
+        .expires_at
+        .is_some_and(|expires_at| expires_at < now_utc())
+    {
+        return Err(CtxExtError::ApiKeyExpired);
+    }
+
+    // -- Load Permissions
+    let permissions = RoleBmc::list_permissions(mm, key.user_id)
+        .await
+        .map_err(|ex| CtxExtError::ModelAccessError(ex.to_string()))?;
 
     // -- Create CtxExtResult
-    Ctx::new(user.id).map_err(|ex| CtxExtError::CtxCreateFail(ex.to_string()))
+    Ctx::new(key.user_id)
+        .map(|ctx| {
+            ctx.with_permissions(permissions)
+                .with_api_key_scopes(key.scopes)
+        })
+        .map_err(|ex| CtxExtError::CtxCreateFail(ex.to_string()))
+}
+
+/// Token of the request and where it was read, following the configured precedence.
+///
+/// A malformed `Authorization` header is always an error, even when a cookie is present,
+/// so a client is told its header is wrong instead of being silently authenticated otherwise.
+fn get_token(
+    cookies: &Cookies,
+    headers: &HeaderMap,
+) -> core::result::Result<(String, TokenSource), CtxExtError> {
+    let sources = match config().auth.token_precedence {
+        TokenSource::Cookie => [TokenSource::Cookie, TokenSource::Bearer],
+        TokenSource::Bearer => [TokenSource::Bearer, TokenSource::Cookie],
+    };
+
+    let mut first_err = None;
+    for source in sources {
+        let token = match source {
+            TokenSource::Cookie => cookies
+                .get(AUTH_TOKEN)
+                .map(|c| c.value().to_string())
+                .ok_or(CtxExtError::TokenNotInCookie),
+            TokenSource::Bearer => bearer_token(headers),
+        };
+
+        match token {
+            Ok(token) => return Ok((token, source)),
+            Err(CtxExtError::AuthHeaderWrongFormat) => {
+                return Err(CtxExtError::AuthHeaderWrongFormat);
+            }
+            Err(ex) => {
+                first_err.get_or_insert(ex);
+            }
+        }
+    }
+
+    Err(first_err.unwrap_or(CtxExtError::TokenNotInCookie))
+}
+
+/// Parsed web token of the request, `None` when absent or not valid.
+pub(in crate::web) fn request_web_token(
+    cookies: &Cookies,
+    headers: &HeaderMap,
+) -> Option<WebToken> {
+    let (token, _) = get_token(cookies, headers).ok()?;
+
+    token.parse().ok()
+}
+
+/// Token of the `Authorization: Bearer <token>` header, the scheme is case insensitive.
+fn bearer_token(headers: &HeaderMap) -> core::result::Result<String, CtxExtError> {
+    let header = headers
+        .get(header::AUTHORIZATION)
+        .ok_or(CtxExtError::TokenNotInAuthHeader)?;
+
+    header
+        .to_str()
+        .ok()
+        .and_then(|value| value.split_once(' '))
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("Bearer"))
+        .map(|(_, token)| token.trim())
+        .filter(|token| !token.is_empty())
+        .map(str::to_string)
+        .ok_or(CtxExtError::AuthHeaderWrongFormat)
 }
 
 // Retreive info from the request extensions
@@ -102,18 +331,85 @@ type CtxExtResult = core::result::Result<Ctx, CtxExtError>;
 pub enum CtxExtError {
     #[error("Token not found in cookie")]
     TokenNotInCookie,
+    #[error("Authorization header not found")]
+    TokenNotInAuthHeader,
+    #[error("Authorization header is not `Bearer <token>`")]
+    AuthHeaderWrongFormat,
     #[error("Context not found in request extension")]
     CtxNotInRequestExt,
     #[error("Failed to create context: {0}")]
     CtxCreateFail(String),
-    #[error("Failed to set token cookie")]
-    CannotSetTokenCookie,
     #[error("Validation failed")]
     FailValidate,
     #[error("User not found")]
     UserNotFound,
     #[error("Token has the wrong format")]
     TokenWrongFormat,
+    #[error("Token has been revoked")]
+    TokenRevoked,
+    #[error("Api key has the wrong format")]
+    ApiKeyWrongFormat,
+    #[error("Api key not found or not matching")]
+    ApiKeyInvalid,
+    #[error("Api key has expired")]
+    ApiKeyExpired,
     #[error("Model access error: {0}")]
     ModelAccessError(String),
 }
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bearer_token_ok() {
+        // -- Setup & Fixtures
+        let fx_cases = [
+            "Bearer some-token",
+            "bearer some-token",
+            "Bearer  some-token ",
+        ];
+
+        // -- Exec & Check
+        for fx_header in fx_cases {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::AUTHORIZATION, HeaderValue::from_static(fx_header));
+            assert_eq!(
+                bearer_token(&headers).ok().as_deref(),
+                Some("some-token"),
+                "Should have read the token of {fx_header:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_bearer_token_err_missing() {
+        // -- Exec
+        let res = bearer_token(&HeaderMap::new());
+
+        // -- Check
+        assert!(
+            matches!(res, Err(CtxExtError::TokenNotInAuthHeader)),
+            "Should have matched `Err(CtxExtError::TokenNotInAuthHeader)` but was `{res:?}`"
+        );
+    }
+
+    #[test]
+    fn test_bearer_token_err_wrong_format() {
+        // -- Setup & Fixtures
+        let fx_cases = ["some-token", "Basic dXNlcjpwd2Q=", "Bearer ", "Bearer"];
+
+        // -- Exec & Check
+        for fx_header in fx_cases {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::AUTHORIZATION, HeaderValue::from_static(fx_header));
+            let res = bearer_token(&headers);
+            assert!(
+                matches!(res, Err(CtxExtError::AuthHeaderWrongFormat)),
+                "Should have matched `Err(CtxExtError::AuthHeaderWrongFormat)` for {fx_header:?}"
+            );
+        }
+    }
+}
+// endregion: --- Tests
@@ -1,12 +1,11 @@
 use crate::web::error::{ClientError, Error, ProblemDetailsBuilder};
 use axum::body::Body;
-use axum::http::Uri;
+use axum::http::{HeaderValue, Uri, header};
 use axum::response::{IntoResponse, Response};
 use axum_extra::extract::Host;
 use regex::Regex;
 use std::sync::Arc;
 use tower_otel::get_current_otel_trace_id;
-use tracing::{error, info};
 
 /// Map all web:Error to web:ClientError
 pub async fn mw_res_map(host: Host, uri: Uri, res: Response<Body>) -> impl IntoResponse {
@@ -25,37 +24,16 @@ pub async fn mw_res_map(host: Host, uri: Uri, res: Response<Body>) -> impl IntoR
             // Retreive the current opentelemetry trace id
             let trace_id = get_current_otel_trace_id().unwrap_or("unknown".to_string());
 
-            // Set the message(enum name) and detail(Display implementation)
-            let client_error_message = client_error.as_ref();
             let client_error_detail = client_error.to_string();
 
             let serveur_err = web_error.expect("Failed to retreive error");
-            let server_error_message = serveur_err.as_ref();
-            let server_error_detail = serveur_err.to_string();
+            serveur_err.log(*status_code, client_error);
 
             // TODO fix deeplinking
             let uri_api_doc = to_open_api_deeplink(&uri.to_string());
             // TODO add env variable to know if we are running over HTTP or HTTPS
             let type_url = format!("http://{}/swagger-ui/#{}", host.0, uri_api_doc);
 
-            if status_code.is_server_error() || status_code.as_u16() == 408 {
-                error!(
-                    server_error = "true",
-                    server_error_message,
-                    server_error_detail,
-                    client_error_message,
-                    client_error_detail,
-                );
-            } else {
-                info!(
-                    server_error = "false",
-                    server_error_message,
-                    server_error_detail,
-                    client_error_message,
-                    client_error_detail,
-                );
-            }
-
             match client_error {
                 ClientError::JSON_VALDIDATION { errors } => ProblemDetailsBuilder::new()
                     .type_url(type_url)
@@ -67,15 +45,23 @@ pub async fn mw_res_map(host: Host, uri: Uri, res: Response<Body>) -> impl IntoR
                     .extension("detail_validation", errors.to_string())
                     .build()
                     .into_response(),
-                _ => ProblemDetailsBuilder::new()
-                    .type_url(type_url)
-                    .title("title")
-                    .status(status_code)
-                    .detail(client_error_detail)
-                    .instance(uri.to_string())
-                    .trace_id(trace_id)
-                    .build()
-                    .into_response(),
+                _ => {
+                    let mut response = ProblemDetailsBuilder::new()
+                        .type_url(type_url)
+                        .title("title")
+                        .status(status_code)
+                        .detail(client_error_detail)
+                        .instance(uri.to_string())
+                        .trace_id(trace_id)
+                        .build()
+                        .into_response();
+                    if let ClientError::LOGIN_LOCKED { retry_after_sec } = client_error {
+                        response
+                            .headers_mut()
+                            .insert(header::RETRY_AFTER, HeaderValue::from(*retry_after_sec));
+                    }
+                    response
+                }
             }
         });
     error_response.unwrap_or(res)
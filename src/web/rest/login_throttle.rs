@@ -0,0 +1,142 @@
+//! Brute-force protection of the logins: the failures are counted per username and per ip,
+//! both are locked out for an exponentially growing duration once over their threshold.
+
+use crate::config::AuthSettings;
+use crate::crypt::now_utc;
+use crate::model::login_throttle::LoginThrottleBmc;
+use crate::startup::SharedState;
+use crate::web::{Error, Result};
+use opentelemetry::KeyValue;
+use time::Duration;
+use tracing::info;
+
+/// Throttle keys of a login attempt, the username and the ip of the client.
+pub(super) struct LoginAttempt {
+    username_key: String,
+    ip_key: Option<String>,
+}
+
+impl LoginAttempt {
+    pub(super) fn new(username: &str, ip: Option<&str>) -> Self {
+        Self {
+            username_key: format!("username:{username}"),
+            ip_key: ip.map(|ip| format!("ip:{ip}")),
+        }
+    }
+
+    fn keys_with_threshold(&self, auth: &AuthSettings) -> Vec<(&str, i32)> {
+        let mut keys = vec![(self.username_key.as_str(), auth.lockout_threshold)];
+        if let Some(ip_key) = &self.ip_key {
+            keys.push((ip_key.as_str(), auth.lockout_ip_threshold));
+        }
+        keys
+    }
+
+    /// Fail with [Error::LoginLocked] while the username or the ip is locked out,
+    /// before any credential is checked.
+    pub(super) async fn ensure_not_locked(&self, state: &SharedState) -> Result<()> {
+        let now = now_utc();
+        let keys: Vec<String> = self
+            .keys_with_threshold(&state.auth)
+            .into_iter()
+            .map(|(key, _)| key.to_string())
+            .collect();
+
+        match LoginThrottleBmc::locked_until(&state.mm, &keys, now).await? {
+            Some(locked_until) => {
+                count_failure(state, "locked");
+                Err(Error::LoginLocked {
+                    // Rounded up, so a retry at the given time is accepted.
+                    retry_after_sec: (locked_until - now).whole_seconds() + 1,
+                })
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Count the failure of the login, if the error is a wrong credential,
+    /// and lock out the keys which are over their threshold.
+    pub(super) async fn record_failure(&self, state: &SharedState, error: &Error) -> Result<()> {
+        let Some(reason) = failure_reason(error) else {
+            return Ok(());
+        };
+        count_failure(state, reason);
+
+        let auth = &state.auth;
+        let now = now_utc();
+        let window_start = now - Duration::seconds(auth.lockout_window_sec);
+
+        for (key, threshold) in self.keys_with_threshold(auth) {
+            let failures =
+                LoginThrottleBmc::record_failure(&state.mm, key, now, window_start).await?;
+            if failures >= threshold {
+                let lockout = lockout_duration(auth, failures - threshold);
+                info!(
+                    "{:<12} - login throttle - {key} locked for {lockout}",
+                    "HANDLER"
+                );
+                LoginThrottleBmc::lock(&state.mm, key, now + lockout).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Forget the failures of the username. Those of the ip are kept,
+    /// an attacker could otherwise reset them with an account of its own.
+    pub(super) async fn record_success(&self, state: &SharedState) -> Result<()> {
+        LoginThrottleBmc::clear(&state.mm, &self.username_key).await?;
+
+        Ok(())
+    }
+}
+
+/// Base duration, doubled for each failure over the threshold, up to the max.
+fn lockout_duration(auth: &AuthSettings, failures_over: i32) -> Duration {
+    let factor = 2i64.saturating_pow(failures_over.clamp(0, 32).unsigned_abs());
+    let lockout_sec = auth
+        .lockout_base_sec
+        .saturating_mul(factor)
+        .min(auth.lockout_max_sec);
+
+    Duration::seconds(lockout_sec)
+}
+
+fn failure_reason(error: &Error) -> Option<&'static str> {
+    match error {
+        Error::LoginFailUsernameNotFound { .. } => Some("username_not_found"),
+        Error::LoginFailUserHasNoPwd { .. } => Some("user_has_no_pwd"),
+        Error::LoginFailPwdNotMatching { .. } => Some("pwd_not_matching"),
+        Error::MfaCodeNotMatching { .. } => Some("mfa_code_not_matching"),
+        _ => None,
+    }
+}
+
+fn count_failure(state: &SharedState, reason: &'static str) {
+    state
+        .metric
+        .app_auth_login_failure_count
+        .add(1, &[KeyValue::new("reason", reason)]);
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::config;
+
+    #[test]
+    fn test_lockout_duration_ok_doubled_up_to_max() {
+        // -- Setup & Fixtures
+        let auth = &config().auth;
+        let fx_base = Duration::seconds(auth.lockout_base_sec);
+        let fx_max = Duration::seconds(auth.lockout_max_sec);
+
+        // -- Exec & Check
+        assert_eq!(lockout_duration(auth, 0), fx_base);
+        assert_eq!(lockout_duration(auth, 1), (fx_base * 2).min(fx_max));
+        assert_eq!(lockout_duration(auth, 3), (fx_base * 8).min(fx_max));
+        assert_eq!(lockout_duration(auth, i32::MAX), fx_max);
+    }
+}
+// endregion: --- Tests
@@ -1,4 +1,12 @@
+mod login_throttle;
+pub mod routes_api_key;
 pub mod routes_health;
 pub mod routes_hello;
 pub mod routes_login;
+pub mod routes_mfa;
+pub mod routes_oidc;
+pub mod routes_pwd;
+pub mod routes_session;
 pub mod routes_static;
+pub mod routes_task;
+pub mod routes_well_known;
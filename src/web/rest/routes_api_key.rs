@@ -0,0 +1,184 @@
+use crate::crypt::now_utc;
+use crate::crypt::token::ApiKeyToken;
+use crate::ctx::Ctx;
+use crate::model::api_key::{ApiKey, ApiKeyBmc, ApiKeyForCreate};
+use crate::startup::SharedState;
+use crate::web::error::ProblemDetails;
+use crate::web::mw_auth::require_user_token;
+use crate::web::mw_validate_json::ValidatedJson;
+use crate::web::{Error, Result};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::{delete, get};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use std::result::Result as Resultstd;
+use time::OffsetDateTime;
+use tracing::debug;
+use utoipa::ToSchema;
+use validator::ValidationError;
+use validator_derive::Validate;
+
+/// Routes merged under `/account` by the login routes.
+pub(super) fn sub_routes() -> Router<SharedState> {
+    Router::new()
+        .route("/api-keys", get(list_api_keys).post(create_api_key))
+        .route("/api-keys/{id}", delete(delete_api_key))
+}
+
+// region:    --- Create
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct ApiKeyCreatePayload {
+    #[validate(length(min = 1, max = 128, message = "Must be between 1 and 128 characters"))]
+    pub name: String,
+    /// Permissions the key is limited to, each must be held by the user (`task:read` for example).
+    #[validate(length(min = 1, message = "Must have at least one scope"))]
+    pub scopes: Vec<String>,
+    /// No expiration when absent.
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    #[validate(custom(function = "validate_expires_at"))]
+    pub expires_at: Option<OffsetDateTime>,
+}
+
+fn validate_expires_at(expires_at: &OffsetDateTime) -> Resultstd<(), ValidationError> {
+    if *expires_at > now_utc() {
+        Ok(())
+    } else {
+        Err(ValidationError::new("expires_at_past").with_message("Must be in the future".into()))
+    }
+}
+
+/// The created key, its `key` is only returned here.
+#[derive(Serialize, ToSchema)]
+pub struct ApiKeyCreated {
+    #[serde(flatten)]
+    api_key: ApiKey,
+    /// To send in the `X-API-Key` header.
+    key: String,
+}
+
+#[utoipa::path(
+    post,
+    context_path = "/account",
+    path = "/api-keys",
+    tag = "Account",
+    request_body = ApiKeyCreatePayload,
+    responses(
+        (status = 201, description = "Api key created", body = ApiKeyCreated),
+        (status = 400, description = "Invalid name, scopes or expiration", body = ProblemDetails),
+        (status = 403, description = "Not authenticated with a user token, or scope not held by the user", body = ProblemDetails),
+        (status = 500, description = "Internal Server Error", body = ProblemDetails)
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+async fn create_api_key(
+    State(state): State<SharedState>,
+    ctx: Ctx,
+    ValidatedJson(payload): ValidatedJson<ApiKeyCreatePayload>,
+) -> Result<(StatusCode, Json<ApiKeyCreated>)> {
+    debug!("{:<12} - create_api_key", "HANDLER");
+
+    require_user_token(&ctx)?;
+
+    let ApiKeyCreatePayload {
+        name,
+        mut scopes,
+        expires_at,
+    } = payload;
+
+    // -- Validate the scopes.
+    scopes.sort();
+    scopes.dedup();
+    if let Some(scope) = scopes.iter().find(|scope| !ctx.has_permission(scope)) {
+        return Err(Error::ApiKeyScopeNotHeld {
+            user_id: ctx.user_id(),
+            scope: scope.to_string(),
+        });
+    }
+
+    // -- Create the api key.
+    let secret_b64u = ApiKeyToken::new_secret();
+    let id = ApiKeyBmc::create(
+        &ctx,
+        &state.mm,
+        ApiKeyForCreate {
+            name,
+            key_hash: ApiKeyToken::hash_secret(&secret_b64u),
+            scopes,
+            expires_at,
+        },
+    )
+    .await?;
+    let api_key = ApiKeyBmc::get(&ctx, &state.mm, id).await?;
+    let key = ApiKeyToken {
+        key_id: id,
+        secret_b64u,
+    };
+
+    Ok((
+        StatusCode::CREATED,
+        Json(ApiKeyCreated {
+            api_key,
+            key: key.to_string(),
+        }),
+    ))
+}
+
+// endregion: --- Create
+
+// region:    --- List & Revoke
+
+#[utoipa::path(
+    get,
+    context_path = "/account",
+    path = "/api-keys",
+    tag = "Account",
+    responses(
+        (status = 200, description = "Api keys of the user, the most recent first", body = Vec<ApiKey>),
+        (status = 403, description = "Not authenticated with a user token", body = ProblemDetails),
+        (status = 500, description = "Internal Server Error", body = ProblemDetails)
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+async fn list_api_keys(State(state): State<SharedState>, ctx: Ctx) -> Result<Json<Vec<ApiKey>>> {
+    debug!("{:<12} - list_api_keys", "HANDLER");
+
+    require_user_token(&ctx)?;
+
+    let api_keys = ApiKeyBmc::list(&ctx, &state.mm).await?;
+
+    Ok(Json(api_keys))
+}
+
+#[utoipa::path(
+    delete,
+    context_path = "/account",
+    path = "/api-keys/{id}",
+    tag = "Account",
+    params(
+        ("id" = i64, Path, description = "Id of the api key")
+    ),
+    responses(
+        (status = 204, description = "Api key revoked, it can no longer be used"),
+        (status = 403, description = "Not authenticated with a user token", body = ProblemDetails),
+        (status = 404, description = "Api key not found", body = ProblemDetails),
+        (status = 500, description = "Internal Server Error", body = ProblemDetails)
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+async fn delete_api_key(
+    State(state): State<SharedState>,
+    ctx: Ctx,
+    Path(id): Path<i64>,
+) -> Result<StatusCode> {
+    debug!("{:<12} - delete_api_key - {id}", "HANDLER");
+
+    require_user_token(&ctx)?;
+
+    ApiKeyBmc::delete(&ctx, &state.mm, id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// endregion: --- List & Revoke
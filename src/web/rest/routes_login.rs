@@ -1,11 +1,27 @@
-use crate::crypt::{EncryptContent, pwd};
+use crate::crypt::EncryptContent;
+use crate::crypt::pwd::{self, SchemeStatus};
+use crate::crypt::token::RefreshToken;
 use crate::ctx::Ctx;
-use crate::model::user::{UserBmc, UserForLogin};
+use crate::model::session::SessionBmc;
+use crate::model::token_denylist::TokenDenylistBmc;
+use crate::model::user::{UserBmc, UserForCreate, UserForLogin};
 use crate::startup::SharedState;
 use crate::web::error::ProblemDetails;
+use crate::web::mw_auth::{request_web_token, require_user_token};
 use crate::web::mw_validate_json::ValidatedJson;
-use crate::web::{self, Error, Result, remove_token_cookie};
+use crate::web::rest::login_throttle::LoginAttempt;
+use crate::web::rest::routes_mfa::{self, MfaLoginFlow, MfaPendingResponse, mfa_pending_response};
+use crate::web::rest::routes_session::{
+    self, SessionClient, TokenResponse, close_session, open_session,
+};
+use crate::web::rest::{routes_api_key, routes_oidc, routes_pwd};
+use crate::web::{
+    self, Error, REFRESH_TOKEN, Result, remove_refresh_cookie, remove_token_cookie,
+    set_refresh_cookie,
+};
 use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
 use axum::routing::post;
 use axum::{Json, Router};
 use redact::Secret;
@@ -14,7 +30,7 @@ use std::result::Result as Resultstd;
 use tower_cookies::Cookies;
 use tracing::debug;
 use utoipa::{IntoParams, ToSchema};
-use validator::{Validate, ValidateLength};
+use validator::{Validate, ValidateLength, ValidationError};
 use validator_derive::Validate;
 
 pub fn routes() -> Router<SharedState> {
@@ -23,8 +39,15 @@ pub fn routes() -> Router<SharedState> {
 
 fn sub_routes() -> Router<SharedState> {
     Router::new()
+        .route("/register", post(register))
         .route("/login", post(login))
+        .route("/token", post(token))
         .route("/logoff", post(logoff))
+        .merge(routes_pwd::sub_routes())
+        .merge(routes_mfa::sub_routes())
+        .merge(routes_oidc::sub_routes())
+        .merge(routes_api_key::sub_routes())
+        .merge(routes_session::sub_routes())
 }
 
 // region:    --- Structs
@@ -37,18 +60,125 @@ pub struct LoginResponse {
 pub struct LoginResponseResult {
     success: bool,
 }
+
+impl LoginResponse {
+    pub(super) fn new(success: bool) -> Self {
+        Self {
+            result: LoginResponseResult { success },
+        }
+    }
+}
 // endregion: --- Structs
 
+// region:    --- Register
+pub const PWD_MIN_LENGTH: usize = 12;
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct RegisterPayload {
+    /// Between 3 and 64 characters, only ascii letters, digits, `_`, `-` and `.`.
+    #[validate(
+        length(min = 3, max = 64, message = "Must be between 3 and 64 characters"),
+        custom(function = "validate_username")
+    )]
+    pub username: String,
+    /// Needed to receive the password reset mails.
+    #[validate(email(message = "Must be a valid email"))]
+    pub email: Option<String>,
+    /// At least 12 characters, with a lowercase, an uppercase and a digit.
+    #[validate(custom(function = "validate_pwd_strength"))]
+    pub pwd: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RegisterResponse {
+    id: i64,
+    username: String,
+}
+
+fn validate_username(username: &str) -> Resultstd<(), ValidationError> {
+    let is_valid = username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+
+    if is_valid {
+        Ok(())
+    } else {
+        Err(ValidationError::new("username_charset")
+            .with_message("Only ascii letters, digits, '_', '-' and '.' are allowed".into()))
+    }
+}
+
+pub(super) fn validate_pwd_strength(pwd: &str) -> Resultstd<(), ValidationError> {
+    let is_strong = pwd.chars().count() >= PWD_MIN_LENGTH
+        && pwd.chars().any(|c| c.is_lowercase())
+        && pwd.chars().any(|c| c.is_uppercase())
+        && pwd.chars().any(|c| c.is_ascii_digit());
+
+    if is_strong {
+        Ok(())
+    } else {
+        Err(ValidationError::new("pwd_strength").with_message(
+            "Must have at least 12 characters, with a lowercase, an uppercase and a digit".into(),
+        ))
+    }
+}
+
+#[utoipa::path(
+    post,
+    context_path = "/account",
+    path = "/register",
+    tag = "Account",
+    request_body = RegisterPayload,
+    responses(
+        (status = 201, description = "Account created", body = RegisterResponse),
+        (status = 400, description = "Invalid username or password too weak", body = ProblemDetails),
+        (status = 409, description = "Username already taken", body = ProblemDetails),
+        (status = 500, description = "Internal Server Error", body = ProblemDetails)
+    )
+)]
+async fn register(
+    State(state): State<SharedState>,
+    ValidatedJson(payload): ValidatedJson<RegisterPayload>,
+) -> Result<(StatusCode, Json<RegisterResponse>)> {
+    debug!("{:<12} - register", "HANDLER");
+
+    let RegisterPayload {
+        username,
+        email,
+        pwd,
+    } = payload;
+    let root_ctx = Ctx::root_ctx();
+
+    let id = UserBmc::create(
+        &root_ctx,
+        &state.mm,
+        UserForCreate {
+            username: username.clone(),
+            email,
+            pwd_clear: pwd,
+        },
+    )
+    .await?;
+
+    Ok((StatusCode::CREATED, Json(RegisterResponse { id, username })))
+}
+
+// endregion: --- Register
+
 // region:    --- Login
 // TODO find a way to store the password with the type Seceret<String> because it need to impl HasLen
 // this is not possible to implemente myself because the type Secret is of an external crate
 #[derive(Debug, Serialize, Deserialize, Validate, ToSchema)]
 pub struct LoginPayload {
-    #[validate(length(min = 1, message = "Can not be empty"))]
+    /// Same max length as the registered usernames, it is also the key of the login throttle.
+    #[validate(length(min = 1, max = 64, message = "Must be between 1 and 64 characters"))]
     pub username: String,
     #[validate(length(min = 1, message = "Can not be empty",))]
     pub pwd: String,
     //pub pwd: SecretStringWrapper,
+    /// Name of the device, shown in the session list.
+    #[validate(length(max = 128, message = "Must be at most 128 characters"))]
+    pub device: Option<String>,
 }
 
 // Move this part of the code in it's own file
@@ -84,54 +214,138 @@ impl ValidateLength<usize> for SecretStringWrapper {
     request_body = LoginPayload,
     responses(
         (status = 200, description = "Login successfully", body = LoginResponse),
+        (status = 202, description = "Password valid, the login must be finished by `/account/2fa/verify`", body = MfaPendingResponse),
         (status = 403, description = "Login Fail", body = ProblemDetails),
+        (status = 429, description = "Too many failed logins for the username or the ip, retry after the `Retry-After` seconds", body = ProblemDetails),
         (status = 500, description = "Internal Server Error", body = ProblemDetails)
-    ),
-    security(
-        ("api_key" = ["aaa","bb"])
     )
 )]
-
 async fn login(
     State(state): State<SharedState>,
+    client: SessionClient,
     cookies: Cookies,
-    ValidatedJson(payload): ValidatedJson<LoginPayload>,
-) -> Result<Json<LoginResponse>> {
+    ValidatedJson(mut payload): ValidatedJson<LoginPayload>,
+) -> Result<Response> {
+    debug!("{:<12} - login", "HANDLER");
+
+    let device = payload.device.take();
+    let user = authenticate(&state, client.ip(), payload).await?;
+    if user.totp_enabled {
+        return mfa_pending_response(MfaLoginFlow::Cookie, &user);
+    }
+    let refresh_token = open_session(&state.mm, user.id, device, client).await?;
+
+    // -- Set web token and refresh token.
+    web::set_token_cookie(&cookies, &user.username, &user.token_salt.to_string())?;
+    set_refresh_cookie(&cookies, &refresh_token);
+
+    let body = Json(LoginResponse::new(true));
+
+    Ok(body.into_response())
+}
+
+#[utoipa::path(
+    post,
+    context_path = "/account",
+    path = "/token",
+    tag = "Account",
+    request_body = LoginPayload,
+    responses(
+        (status = 200, description = "Tokens for the clients not using the cookies", body = TokenResponse),
+        (status = 202, description = "Password valid, the login must be finished by `/account/2fa/verify`", body = MfaPendingResponse),
+        (status = 403, description = "Login Fail", body = ProblemDetails),
+        (status = 429, description = "Too many failed logins for the username or the ip, retry after the `Retry-After` seconds", body = ProblemDetails),
+        (status = 500, description = "Internal Server Error", body = ProblemDetails)
+    )
+)]
+async fn token(
+    State(state): State<SharedState>,
+    client: SessionClient,
+    ValidatedJson(mut payload): ValidatedJson<LoginPayload>,
+) -> Result<Response> {
+    debug!("{:<12} - token", "HANDLER");
+
+    let device = payload.device.take();
+    let user = authenticate(&state, client.ip(), payload).await?;
+    if user.totp_enabled {
+        return mfa_pending_response(MfaLoginFlow::Bearer, &user);
+    }
+    let refresh_token = open_session(&state.mm, user.id, device, client).await?;
+
+    let body = TokenResponse::new(&user.username, &user.token_salt.to_string(), &refresh_token)?;
+
+    Ok(Json(body).into_response())
+}
+
+/// Validate the credentials of the payload, shared by the cookie and the token logins.
+///
+/// The failures are throttled per username and per ip. For a user with 2fa,
+/// the failures are only forgotten once the code is verified.
+async fn authenticate(
+    state: &SharedState,
+    ip: Option<&str>,
+    payload: LoginPayload,
+) -> Result<UserForLogin> {
+    let attempt = LoginAttempt::new(&payload.username, ip);
+    attempt.ensure_not_locked(state).await?;
+
+    match validate_credentials(state, payload).await {
+        Ok(user) => {
+            if !user.totp_enabled {
+                attempt.record_success(state).await?;
+            }
+            Ok(user)
+        }
+        Err(ex) => {
+            attempt.record_failure(state, &ex).await?;
+            Err(ex)
+        }
+    }
+}
+
+async fn validate_credentials(state: &SharedState, payload: LoginPayload) -> Result<UserForLogin> {
     let LoginPayload {
         username,
         pwd: pwd_clear,
+        ..
     } = payload;
     let root_ctx = Ctx::root_ctx();
 
     // -- Get the user.
-    let user: UserForLogin = UserBmc::first_by_username(&root_ctx, &state.mm, &username)
-        .await?
-        .ok_or(Error::LoginFailUsernameNotFound { username })?;
+    // When the login fails before the password validation, the same hashing work is still done,
+    // so the response time does not disclose which usernames exist.
+    let user: Option<UserForLogin> =
+        UserBmc::first_by_username(&root_ctx, &state.mm, &username).await?;
+    let Some(mut user) = user else {
+        let _ = pwd::validate_pwd_dummy(pwd_clear).await;
+        return Err(Error::LoginFailUsernameNotFound { username });
+    };
     let user_id = user.id;
 
     // -- Validate the password.
-    let Some(pwd) = user.pwd else {
+    let Some(pwd) = user.pwd.take() else {
+        let _ = pwd::validate_pwd_dummy(pwd_clear).await;
         return Err(Error::LoginFailUserHasNoPwd { user_id });
     };
 
-    pwd::validate_pwd(
-        &EncryptContent {
+    let scheme_status = pwd::validate_pwd(
+        EncryptContent {
             salt: user.pwd_salt.to_string(),
             //content: pwd_clear.0.expose_secret().clone(),
             content: pwd_clear.clone(),
         },
-        &pwd,
+        pwd,
     )
+    .await
     .map_err(|_| Error::LoginFailPwdNotMatching { user_id })?;
 
-    // -- Set web token.
-    web::set_token_cookie(&cookies, &user.username, &user.token_salt.to_string())?;
-
-    let body = Json(LoginResponse {
-        result: LoginResponseResult { success: true },
-    });
+    // -- Upgrade the password hash to the default scheme.
+    if let SchemeStatus::Outdated = scheme_status {
+        debug!("{:<12} - login - pwd scheme outdated, upgrading", "HANDLER");
+        UserBmc::update_pwd(&root_ctx, &state.mm, user_id, &pwd_clear).await?;
+    }
 
-    Ok(body)
+    Ok(user)
 }
 
 // endregion:    --- Login
@@ -140,6 +354,9 @@ async fn login(
 #[derive(Debug, Serialize, Deserialize, ToSchema, IntoParams)]
 pub struct LogoffPayload {
     pub logoff: bool,
+    /// Also revoke the tokens and sessions of all the other devices.
+    #[serde(default)]
+    pub everywhere: bool,
 }
 
 #[utoipa::path(
@@ -152,27 +369,127 @@ pub struct LogoffPayload {
     ),
     responses(
         (status = 200, description = "Logoff succesful",  body = LoginResponse),
+        (status = 403, description = "Logoff everywhere with an api key", body = ProblemDetails),
         (status = 500, description = "Internal Server Error")
     )
 )]
 async fn logoff(
+    State(state): State<SharedState>,
+    ctx: Result<Ctx>,
     cookies: Cookies,
+    headers: HeaderMap,
     Json(payload): Json<LogoffPayload>,
 ) -> Result<Json<LoginResponse>> {
     debug!("{:<12} - api_logoff_handler", "HANDLER");
     let should_logoff = payload.logoff;
 
     if should_logoff {
+        // -- Revoke the web token, so it can not be replayed.
+        // A request without a valid token has nothing left to revoke.
+        if let Ok(ctx) = ctx {
+            if payload.everywhere {
+                require_user_token(&ctx)?;
+                UserBmc::rotate_token_salt(&Ctx::root_ctx(), &state.mm, ctx.user_id()).await?;
+                SessionBmc::delete_all_for_user(&state.mm, ctx.user_id()).await?;
+            } else if let Some(token) = request_web_token(&cookies, &headers) {
+                TokenDenylistBmc::deny(&state.mm, ctx.user_id(), &token.id(), token.exp()?).await?;
+            }
+        }
+
+        // -- Revoke the session of the refresh cookie.
+        let refresh_token = cookies
+            .get(REFRESH_TOKEN)
+            .and_then(|cookie| cookie.value().parse::<RefreshToken>().ok());
+        if let Some(refresh_token) = refresh_token {
+            close_session(&state.mm, &refresh_token).await?;
+        }
+
         remove_token_cookie(&cookies)?;
+        remove_refresh_cookie(&cookies);
     }
 
     // Create the success body.
-    let body = Json(LoginResponse {
-        result: LoginResponseResult {
-            success: should_logoff,
-        },
-    });
+    let body = Json(LoginResponse::new(should_logoff));
 
     Ok(body)
 }
 // endregion: --- Logoff
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register_payload_validate_ok() {
+        // -- Setup & Fixtures
+        let fx_payload = RegisterPayload {
+            username: "demo.user-1".to_string(),
+            email: Some("demo@example.com".to_string()),
+            pwd: "Welcome2Demo!".to_string(),
+        };
+
+        // -- Exec & Check
+        assert!(fx_payload.validate().is_ok());
+    }
+
+    #[test]
+    fn test_register_payload_validate_err_weak_pwd() {
+        // -- Setup & Fixtures
+        let fx_pwds = [
+            "Short1A",
+            "alllowercase123",
+            "ALLUPPERCASE123",
+            "NoDigitsInHere",
+        ];
+
+        // -- Exec & Check
+        for fx_pwd in fx_pwds {
+            let payload = RegisterPayload {
+                username: "demo".to_string(),
+                email: None,
+                pwd: fx_pwd.to_string(),
+            };
+            let errors = payload.validate().expect_err("Should have failed");
+            assert!(
+                errors.field_errors().contains_key("pwd"),
+                "Should have a pwd error for {fx_pwd}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_register_payload_validate_err_username() {
+        // -- Setup & Fixtures
+        let fx_usernames = ["ab", "demo user", "demo@user"];
+
+        // -- Exec & Check
+        for fx_username in fx_usernames {
+            let payload = RegisterPayload {
+                username: fx_username.to_string(),
+                email: None,
+                pwd: "Welcome2Demo!".to_string(),
+            };
+            let errors = payload.validate().expect_err("Should have failed");
+            assert!(
+                errors.field_errors().contains_key("username"),
+                "Should have a username error for {fx_username}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_login_payload_validate_err_username_too_long() {
+        // -- Setup & Fixtures
+        let fx_payload = LoginPayload {
+            username: "a".repeat(65),
+            pwd: "Welcome2Demo!".to_string(),
+            device: None,
+        };
+
+        // -- Exec & Check
+        let errors = fx_payload.validate().expect_err("Should have failed");
+        assert!(errors.field_errors().contains_key("username"));
+    }
+}
+// endregion: --- Tests
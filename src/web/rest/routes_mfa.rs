@@ -0,0 +1,438 @@
+use crate::config::config;
+use crate::crypt::now_utc;
+use crate::crypt::token::{Token, generate_mfa_pending_token, validate_mfa_pending_token};
+use crate::crypt::totp;
+use crate::ctx::Ctx;
+use crate::model::recovery_code::RecoveryCodeBmc;
+use crate::model::user::{UserBmc, UserForLogin, UserForTotp};
+use crate::startup::SharedState;
+use crate::web::error::ProblemDetails;
+use crate::web::mw_auth::require_user_token;
+use crate::web::mw_validate_json::ValidatedJson;
+use crate::web::rest::login_throttle::LoginAttempt;
+use crate::web::rest::routes_login::LoginResponse;
+use crate::web::rest::routes_pwd::validate_current_pwd;
+use crate::web::rest::routes_session::{SessionClient, TokenResponse, open_session};
+use crate::web::{self, Error, Result, set_refresh_cookie};
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{Json, Router};
+use futures::future::try_join_all;
+use serde::{Deserialize, Serialize};
+use std::result::Result as Resultstd;
+use time::Duration;
+use tower_cookies::Cookies;
+use tracing::debug;
+use utoipa::ToSchema;
+use validator::ValidationError;
+use validator_derive::Validate;
+
+/// Routes merged under `/account` by the login routes.
+pub(super) fn sub_routes() -> Router<SharedState> {
+    Router::new()
+        .route("/2fa/setup", post(setup_2fa))
+        .route("/2fa/enable", post(enable_2fa))
+        .route("/2fa/disable", post(disable_2fa))
+        .route("/2fa/verify", post(verify_2fa))
+}
+
+// region:    --- Mfa Pending
+
+/// Login the mfa pending token comes from, to finish it the same way once verified.
+#[derive(Debug, Clone, Copy)]
+pub(super) enum MfaLoginFlow {
+    /// `/account/login`, the tokens are set in cookies.
+    Cookie,
+    /// `/account/token`, the tokens are returned in the body.
+    Bearer,
+}
+
+impl MfaLoginFlow {
+    fn name(&self) -> &'static str {
+        match self {
+            MfaLoginFlow::Cookie => "cookie",
+            MfaLoginFlow::Bearer => "bearer",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "cookie" => Some(MfaLoginFlow::Cookie),
+            "bearer" => Some(MfaLoginFlow::Bearer),
+            _ => None,
+        }
+    }
+}
+
+/// Returned by the logins of the users with 2fa, instead of the tokens.
+#[derive(Serialize, ToSchema)]
+pub struct MfaPendingResponse {
+    /// To send to `/account/2fa/verify` with the code of the authenticator app.
+    mfa_token: String,
+    /// Lifetime of the mfa token, in seconds.
+    expires_in: i64,
+}
+
+/// The password is validated, the login is finished by `/account/2fa/verify`.
+///
+/// The token is signed with the token salt, so it is revoked by a logoff everywhere
+/// or a password reset.
+pub(super) fn mfa_pending_response(flow: MfaLoginFlow, user: &UserForLogin) -> Result<Response> {
+    let ident = format!("{}:{}", flow.name(), user.username);
+    let token = generate_mfa_pending_token(&ident, &user.token_salt.to_string())?;
+
+    let body = MfaPendingResponse {
+        mfa_token: token.to_string(),
+        expires_in: Duration::seconds_f64(config().crypt.mfa_pending_duration_sec).whole_seconds(),
+    };
+
+    Ok((StatusCode::ACCEPTED, Json(body)).into_response())
+}
+
+// endregion: --- Mfa Pending
+
+// region:    --- Setup & Enable
+
+/// Secret to register in the authenticator app.
+#[derive(Serialize, ToSchema)]
+pub struct MfaSetupResponse {
+    /// Base32 secret, for the apps not able to scan the uri.
+    secret: String,
+    /// `otpauth://` uri, to show as a qr code.
+    otpauth_uri: String,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct MfaEnablePayload {
+    /// Current password of the user, so a stolen token can not enroll another app.
+    pub pwd_current: String,
+    /// Current code of the authenticator app.
+    #[validate(length(min = 1, message = "Can not be empty"))]
+    pub code: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct MfaRecoveryCodesResponse {
+    /// Single use codes to log in without the authenticator app, they are only returned here.
+    recovery_codes: Vec<String>,
+}
+
+#[utoipa::path(
+    post,
+    context_path = "/account",
+    path = "/2fa/setup",
+    tag = "Account",
+    responses(
+        (status = 200, description = "New secret, 2fa is enabled once a first code is verified by `/account/2fa/enable`", body = MfaSetupResponse),
+        (status = 403, description = "Not authenticated with a user token", body = ProblemDetails),
+        (status = 409, description = "2fa already enabled", body = ProblemDetails),
+        (status = 500, description = "Internal Server Error", body = ProblemDetails)
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+async fn setup_2fa(State(state): State<SharedState>, ctx: Ctx) -> Result<Json<MfaSetupResponse>> {
+    debug!("{:<12} - setup_2fa", "HANDLER");
+
+    require_user_token(&ctx)?;
+
+    let user: UserForTotp = UserBmc::get(&ctx, &state.mm, ctx.user_id()).await?;
+    if user.totp_enabled {
+        return Err(Error::MfaAlreadyEnabled { user_id: user.id });
+    }
+
+    let secret = totp::new_secret();
+    UserBmc::set_totp_secret(&ctx, &state.mm, user.id, &secret).await?;
+    let otpauth_uri = totp::otpauth_uri(&secret, &user.username);
+
+    Ok(Json(MfaSetupResponse {
+        secret,
+        otpauth_uri,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    context_path = "/account",
+    path = "/2fa/enable",
+    tag = "Account",
+    request_body = MfaEnablePayload,
+    responses(
+        (status = 200, description = "2fa enabled, the logins now require a code", body = MfaRecoveryCodesResponse),
+        (status = 400, description = "Empty code", body = ProblemDetails),
+        (status = 403, description = "Not authenticated with a user token, current password or code not matching", body = ProblemDetails),
+        (status = 409, description = "2fa already enabled or not set up", body = ProblemDetails),
+        (status = 500, description = "Internal Server Error", body = ProblemDetails)
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+async fn enable_2fa(
+    State(state): State<SharedState>,
+    ctx: Ctx,
+    ValidatedJson(payload): ValidatedJson<MfaEnablePayload>,
+) -> Result<Json<MfaRecoveryCodesResponse>> {
+    debug!("{:<12} - enable_2fa", "HANDLER");
+
+    require_user_token(&ctx)?;
+
+    let MfaEnablePayload { pwd_current, code } = payload;
+
+    validate_current_pwd(&state, &ctx, pwd_current).await?;
+
+    // -- Validate the code, which proves the secret is registered in the app.
+    let user: UserForTotp = UserBmc::get(&ctx, &state.mm, ctx.user_id()).await?;
+    let user_id = user.id;
+    if user.totp_enabled {
+        return Err(Error::MfaAlreadyEnabled { user_id });
+    }
+    let Some(secret) = user.totp_secret else {
+        return Err(Error::MfaNotSetUp { user_id });
+    };
+    let step = totp::validate_code(&secret, &code, now_utc(), user.totp_last_step)
+        .map_err(|_| Error::MfaCodeNotMatching { user_id })?;
+
+    // -- Enable 2fa and replace the recovery codes.
+    UserBmc::enable_totp(&ctx, &state.mm, user_id, step).await?;
+    let recovery_codes = totp::new_recovery_codes();
+    let code_hashes = try_join_all(
+        recovery_codes
+            .iter()
+            .map(|code| totp::hash_recovery_code(code)),
+    )
+    .await?;
+    RecoveryCodeBmc::replace_all(&state.mm, user_id, &code_hashes).await?;
+
+    Ok(Json(MfaRecoveryCodesResponse { recovery_codes }))
+}
+
+// endregion: --- Setup & Enable
+
+// region:    --- Disable
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+#[validate(schema(function = "validate_mfa_disable_payload"))]
+pub struct MfaDisablePayload {
+    /// Current code of the authenticator app.
+    pub code: Option<String>,
+    /// One of the recovery codes, instead of the code.
+    pub recovery_code: Option<String>,
+}
+
+fn validate_mfa_disable_payload(payload: &MfaDisablePayload) -> Resultstd<(), ValidationError> {
+    validate_one_code(&payload.code, &payload.recovery_code)
+}
+
+#[utoipa::path(
+    post,
+    context_path = "/account",
+    path = "/2fa/disable",
+    tag = "Account",
+    request_body = MfaDisablePayload,
+    responses(
+        (status = 204, description = "2fa disabled, the recovery codes are deleted"),
+        (status = 400, description = "Neither or both of the code and the recovery code", body = ProblemDetails),
+        (status = 403, description = "Not authenticated with a user token or code not matching", body = ProblemDetails),
+        (status = 409, description = "2fa not enabled", body = ProblemDetails),
+        (status = 429, description = "Too many failed logins for the username or the ip, retry after the `Retry-After` seconds", body = ProblemDetails),
+        (status = 500, description = "Internal Server Error", body = ProblemDetails)
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+async fn disable_2fa(
+    State(state): State<SharedState>,
+    ctx: Ctx,
+    client: SessionClient,
+    ValidatedJson(payload): ValidatedJson<MfaDisablePayload>,
+) -> Result<StatusCode> {
+    debug!("{:<12} - disable_2fa", "HANDLER");
+
+    require_user_token(&ctx)?;
+
+    let MfaDisablePayload {
+        code,
+        recovery_code,
+    } = payload;
+
+    let user: UserForTotp = UserBmc::get(&ctx, &state.mm, ctx.user_id()).await?;
+    let user_id = user.id;
+    let Some(secret) = user.totp_secret.filter(|_| user.totp_enabled) else {
+        return Err(Error::MfaNotSetUp { user_id });
+    };
+
+    // -- Validate the code, throttled as on the login.
+    let attempt = LoginAttempt::new(&user.username, client.ip());
+    attempt.ensure_not_locked(&state).await?;
+    let is_valid = validate_second_factor(
+        &state,
+        user_id,
+        &secret,
+        user.totp_last_step,
+        code,
+        recovery_code,
+    )
+    .await?;
+    if !is_valid {
+        let ex = Error::MfaCodeNotMatching { user_id };
+        attempt.record_failure(&state, &ex).await?;
+        return Err(ex);
+    }
+    attempt.record_success(&state).await?;
+
+    // -- Disable 2fa, the recovery codes go with it.
+    UserBmc::disable_totp(&ctx, &state.mm, user_id).await?;
+    RecoveryCodeBmc::replace_all(&state.mm, user_id, &[]).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// endregion: --- Disable
+
+// region:    --- Verify
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+#[validate(schema(function = "validate_mfa_verify_payload"))]
+pub struct MfaVerifyPayload {
+    /// Token returned by `/account/login` or `/account/token`.
+    pub mfa_token: String,
+    /// Current code of the authenticator app.
+    pub code: Option<String>,
+    /// One of the recovery codes, instead of the code.
+    pub recovery_code: Option<String>,
+    /// Name of the device, shown in the session list.
+    #[validate(length(max = 128, message = "Must be at most 128 characters"))]
+    pub device: Option<String>,
+}
+
+fn validate_mfa_verify_payload(payload: &MfaVerifyPayload) -> Resultstd<(), ValidationError> {
+    validate_one_code(&payload.code, &payload.recovery_code)
+}
+
+fn validate_one_code(
+    code: &Option<String>,
+    recovery_code: &Option<String>,
+) -> Resultstd<(), ValidationError> {
+    if code.is_some() != recovery_code.is_some() {
+        Ok(())
+    } else {
+        Err(ValidationError::new("mfa_code")
+            .with_message("Must have either a code or a recovery code".into()))
+    }
+}
+
+#[utoipa::path(
+    post,
+    context_path = "/account",
+    path = "/2fa/verify",
+    tag = "Account",
+    request_body = MfaVerifyPayload,
+    responses(
+        (status = 200, description = "Login finished, with the body of `/account/login` or `/account/token`", body = TokenResponse),
+        (status = 400, description = "Neither or both of the code and the recovery code", body = ProblemDetails),
+        (status = 403, description = "Mfa token invalid or expired, or code not matching", body = ProblemDetails),
+        (status = 429, description = "Too many failed logins for the username or the ip, retry after the `Retry-After` seconds", body = ProblemDetails),
+        (status = 500, description = "Internal Server Error", body = ProblemDetails)
+    )
+)]
+async fn verify_2fa(
+    State(state): State<SharedState>,
+    client: SessionClient,
+    cookies: Cookies,
+    ValidatedJson(payload): ValidatedJson<MfaVerifyPayload>,
+) -> Result<Response> {
+    debug!("{:<12} - verify_2fa", "HANDLER");
+
+    let MfaVerifyPayload {
+        mfa_token,
+        code,
+        recovery_code,
+        device,
+    } = payload;
+    let root_ctx = Ctx::root_ctx();
+
+    // -- Validate the mfa token.
+    let token: Token = mfa_token.parse().map_err(|_| Error::MfaTokenInvalid)?;
+    let (flow, username) = token
+        .ident
+        .split_once(':')
+        .and_then(|(flow, username)| Some((MfaLoginFlow::from_name(flow)?, username)))
+        .ok_or(Error::MfaTokenInvalid)?;
+    let user: UserForTotp = UserBmc::first_by_username(&root_ctx, &state.mm, username)
+        .await?
+        .ok_or(Error::MfaTokenInvalid)?;
+    let user_id = user.id;
+    validate_mfa_pending_token(&token, &user.token_salt.to_string())
+        .map_err(|_| Error::MfaTokenInvalid)?;
+    let Some(secret) = user.totp_secret.filter(|_| user.totp_enabled) else {
+        return Err(Error::MfaTokenInvalid);
+    };
+
+    // -- Validate the code, each can only be used once.
+    // The codes are throttled with the passwords, a few digits are quick to brute force.
+    let attempt = LoginAttempt::new(&user.username, client.ip());
+    attempt.ensure_not_locked(&state).await?;
+    let is_valid = validate_second_factor(
+        &state,
+        user_id,
+        &secret,
+        user.totp_last_step,
+        code,
+        recovery_code,
+    )
+    .await?;
+    if !is_valid {
+        let ex = Error::MfaCodeNotMatching { user_id };
+        attempt.record_failure(&state, &ex).await?;
+        return Err(ex);
+    }
+    attempt.record_success(&state).await?;
+
+    // -- Finish the login.
+    let refresh_token = open_session(&state.mm, user_id, device, client).await?;
+    let token_salt = user.token_salt.to_string();
+
+    match flow {
+        MfaLoginFlow::Cookie => {
+            web::set_token_cookie(&cookies, &user.username, &token_salt)?;
+            set_refresh_cookie(&cookies, &refresh_token);
+            Ok(Json(LoginResponse::new(true)).into_response())
+        }
+        MfaLoginFlow::Bearer => {
+            let body = TokenResponse::new(&user.username, &token_salt, &refresh_token)?;
+            Ok(Json(body).into_response())
+        }
+    }
+}
+
+/// Whether the code of the app, or else the recovery code, is valid. Each can only be used once.
+async fn validate_second_factor(
+    state: &SharedState,
+    user_id: i64,
+    secret: &str,
+    totp_last_step: Option<i64>,
+    code: Option<String>,
+    recovery_code: Option<String>,
+) -> Result<bool> {
+    let is_valid = match (code, recovery_code) {
+        (Some(code), _) => match totp::validate_code(secret, &code, now_utc(), totp_last_step) {
+            Ok(step) => UserBmc::use_totp_step(&state.mm, user_id, step).await?,
+            Err(_) => false,
+        },
+        (None, Some(recovery_code)) => {
+            // The hashes are salted, each unused code of the user has to be tried.
+            let mut is_used = false;
+            for (id, code_hash) in RecoveryCodeBmc::list_unused(&state.mm, user_id).await? {
+                if totp::validate_recovery_code(&recovery_code, code_hash).await {
+                    is_used = RecoveryCodeBmc::use_code(&state.mm, user_id, id).await?;
+                    break;
+                }
+            }
+            is_used
+        }
+        (None, None) => false,
+    };
+
+    Ok(is_valid)
+}
+
+// endregion: --- Verify
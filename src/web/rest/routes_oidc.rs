@@ -0,0 +1,422 @@
+use crate::config::OidcProvider;
+use crate::crypt::token::{Token, generate_oidc_flow_token, validate_oidc_flow_token};
+use crate::crypt::{ct_eq, sha256_into_b64u};
+use crate::ctx::Ctx;
+use crate::model;
+use crate::model::user::{USERNAME_CONSTRAINT, UserBmc, UserForAuth, UserForTotp};
+use crate::model::user_identity::{UserIdentityBmc, UserIdentityForCreate};
+use crate::oidc::{AuthFlow, IdTokenClaims};
+use crate::startup::SharedState;
+use crate::web::error::ProblemDetails;
+use crate::web::mw_auth::require_user_token;
+use crate::web::rest::routes_session::{SessionClient, open_session};
+use crate::web::{self, Error, Result, set_refresh_cookie};
+use axum::Router;
+use axum::extract::{Path, Query, State};
+use axum::response::Redirect;
+use axum::routing::get;
+use serde::Deserialize;
+use tower_cookies::cookie::SameSite;
+use tower_cookies::{Cookie, Cookies};
+use tracing::{debug, info};
+use utoipa::IntoParams;
+
+/// Cookie holding the flow token, from the login until the callback.
+const OIDC_FLOW: &str = "oidc-flow";
+/// The flow cookie is only sent back to the oidc routes.
+const OIDC_FLOW_PATH: &str = "/account/oidc";
+/// Where the client is sent once logged in, or once the identity is linked.
+const OIDC_LOGGED_IN_REDIRECT: &str = "/";
+
+/// Routes merged under `/account` by the login routes.
+pub(super) fn sub_routes() -> Router<SharedState> {
+    Router::new()
+        .route("/oidc/{provider}/login", get(oidc_login))
+        .route("/oidc/{provider}/link", get(oidc_link))
+        .route("/oidc/{provider}/callback", get(oidc_callback))
+}
+
+// region:    --- Login
+
+#[utoipa::path(
+    get,
+    context_path = "/account",
+    path = "/oidc/{provider}/login",
+    tag = "Account",
+    params(
+        ("provider" = String, Path, description = "Name of the provider in the config")
+    ),
+    responses(
+        (status = 303, description = "Redirection to the provider, which calls back `/account/oidc/{provider}/callback`"),
+        (status = 404, description = "No provider with this name", body = ProblemDetails),
+        (status = 502, description = "The provider can not be reached", body = ProblemDetails),
+        (status = 500, description = "Internal Server Error", body = ProblemDetails)
+    )
+)]
+async fn oidc_login(
+    State(state): State<SharedState>,
+    cookies: Cookies,
+    Path(provider): Path<String>,
+) -> Result<Redirect> {
+    debug!("{:<12} - oidc_login - {provider}", "HANDLER");
+
+    start_flow(&state, &cookies, &provider, None).await
+}
+
+#[utoipa::path(
+    get,
+    context_path = "/account",
+    path = "/oidc/{provider}/link",
+    tag = "Account",
+    params(
+        ("provider" = String, Path, description = "Name of the provider in the config")
+    ),
+    responses(
+        (status = 303, description = "Redirection to the provider, the identity is linked to the user by the callback"),
+        (status = 403, description = "Not authenticated with a user token", body = ProblemDetails),
+        (status = 404, description = "No provider with this name", body = ProblemDetails),
+        (status = 502, description = "The provider can not be reached", body = ProblemDetails),
+        (status = 500, description = "Internal Server Error", body = ProblemDetails)
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+async fn oidc_link(
+    State(state): State<SharedState>,
+    ctx: Ctx,
+    cookies: Cookies,
+    Path(provider): Path<String>,
+) -> Result<Redirect> {
+    debug!("{:<12} - oidc_link - {provider}", "HANDLER");
+
+    require_user_token(&ctx)?;
+
+    start_flow(&state, &cookies, &provider, Some(ctx.user_id())).await
+}
+
+/// Redirect to the provider, the flow is kept in a cookie until the callback.
+async fn start_flow(
+    state: &SharedState,
+    cookies: &Cookies,
+    provider: &str,
+    link_user_id: Option<i64>,
+) -> Result<Redirect> {
+    let provider = state.oidc.provider(provider)?;
+    let flow = AuthFlow::new(&provider.name, link_user_id);
+
+    let redirect_uri = state.oidc.redirect_uri(provider);
+    let authorization_url = state
+        .oidc
+        .authorization_url(provider, &redirect_uri, &flow)
+        .await?;
+
+    // -- Keep the flow for the callback.
+    // Lax, so the cookie is sent on the redirection back from the provider.
+    let token = generate_oidc_flow_token(&serde_json::to_string(&flow)?)?;
+    let mut cookie = Cookie::new(OIDC_FLOW, token.to_string());
+    cookie.set_http_only(true);
+    cookie.set_same_site(SameSite::Lax);
+    cookie.set_path(OIDC_FLOW_PATH);
+    cookies.add(cookie);
+
+    Ok(Redirect::to(&authorization_url))
+}
+
+// endregion: --- Login
+
+// region:    --- Callback
+
+/// Query string of the redirection back from the provider.
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct OidcCallbackQuery {
+    /// Authorization code, to exchange for the id token.
+    code: Option<String>,
+    /// Must be the one sent by `/account/oidc/{provider}/login` or `/link`.
+    state: Option<String>,
+    /// Set by the provider when the login failed or has been denied.
+    error: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    context_path = "/account",
+    path = "/oidc/{provider}/callback",
+    tag = "Account",
+    params(
+        ("provider" = String, Path, description = "Name of the provider in the config"),
+        OidcCallbackQuery
+    ),
+    responses(
+        (status = 303, description = "Logged in, the tokens are set in cookies, or identity linked, and the client is redirected to `/`"),
+        (status = 403, description = "Login flow missing or expired, denied by the provider, id token not valid, or user with 2fa", body = ProblemDetails),
+        (status = 404, description = "No provider with this name", body = ProblemDetails),
+        (status = 409, description = "The email of the identity belongs to a user, who must link it, or the identity is linked to another user", body = ProblemDetails),
+        (status = 502, description = "The provider can not be reached", body = ProblemDetails),
+        (status = 500, description = "Internal Server Error", body = ProblemDetails)
+    )
+)]
+async fn oidc_callback(
+    State(state): State<SharedState>,
+    client: SessionClient,
+    cookies: Cookies,
+    Path(provider): Path<String>,
+    Query(query): Query<OidcCallbackQuery>,
+) -> Result<Redirect> {
+    debug!("{:<12} - oidc_callback - {provider}", "HANDLER");
+
+    let provider = state.oidc.provider(&provider)?;
+
+    // -- Validate the flow, it can only be used once.
+    let flow = take_flow(&cookies)?;
+    if let Some(error) = query.error {
+        return Err(Error::OidcProviderDenied {
+            provider: provider.name.clone(),
+            error,
+        });
+    }
+    let (Some(code), Some(flow_state)) = (query.code, query.state) else {
+        return Err(Error::OidcFlowInvalid);
+    };
+    if flow.provider != provider.name || !ct_eq(&flow_state, &flow.state) {
+        return Err(Error::OidcFlowInvalid);
+    }
+
+    // -- Validate the identity.
+    let redirect_uri = state.oidc.redirect_uri(provider);
+    let claims = state
+        .oidc
+        .authenticate(provider, &redirect_uri, &code, &flow)
+        .await?;
+
+    // -- Link the identity to the user of the session which started the flow.
+    if let Some(user_id) = flow.link_user_id {
+        link_identity(&state, provider, &claims, user_id).await?;
+        return Ok(Redirect::to(OIDC_LOGGED_IN_REDIRECT));
+    }
+
+    // -- Login the linked user, the provider can not stand for its second factor.
+    let user_id = login_user_id(&state, provider, &claims).await?;
+    let user: UserForTotp = UserBmc::get(&Ctx::root_ctx(), &state.mm, user_id).await?;
+    if user.totp_enabled {
+        return Err(Error::OidcLoginMfaEnabled { user_id: user.id });
+    }
+    let refresh_token = open_session(&state.mm, user.id, None, client).await?;
+
+    web::set_token_cookie(&cookies, &user.username, &user.token_salt.to_string())?;
+    set_refresh_cookie(&cookies, &refresh_token);
+
+    Ok(Redirect::to(OIDC_LOGGED_IN_REDIRECT))
+}
+
+/// Read and remove the flow of the cookie.
+fn take_flow(cookies: &Cookies) -> Result<AuthFlow> {
+    let flow_token = cookies
+        .get(OIDC_FLOW)
+        .map(|cookie| cookie.value().to_string());
+
+    let mut cookie = Cookie::from(OIDC_FLOW);
+    cookie.set_path(OIDC_FLOW_PATH);
+    cookies.remove(cookie);
+
+    let token: Token = flow_token
+        .ok_or(Error::OidcFlowInvalid)?
+        .parse()
+        .map_err(|_| Error::OidcFlowInvalid)?;
+    validate_oidc_flow_token(&token).map_err(|_| Error::OidcFlowInvalid)?;
+
+    serde_json::from_str(&token.ident).map_err(|_| Error::OidcFlowInvalid)
+}
+
+// endregion: --- Callback
+
+// region:    --- User Linking
+
+/// The user of the identity, a new user without password is created on its first login.
+///
+/// The identity is never linked by email, the local emails are not verified so their user may
+/// not own the email at the provider. When the email is taken, its user must log in and link
+/// the identity with `/account/oidc/{provider}/link`.
+async fn login_user_id(
+    state: &SharedState,
+    provider: &OidcProvider,
+    claims: &IdTokenClaims,
+) -> Result<i64> {
+    let root_ctx = Ctx::root_ctx();
+
+    if let Some(user_id) =
+        UserIdentityBmc::first_user_id(&state.mm, &provider.name, &claims.sub).await?
+    {
+        return Ok(user_id);
+    }
+
+    let verified_email = claims.email.as_deref().filter(|_| claims.email_verified);
+    if let Some(email) = verified_email {
+        let user_by_email: Option<UserForAuth> =
+            UserBmc::first_by_email(&root_ctx, &state.mm, email).await?;
+        if let Some(user) = user_by_email {
+            return Err(Error::OidcIdentityNotLinkable { user_id: user.id });
+        }
+    }
+
+    // -- Create the user with its identity, in one transaction.
+    // The username may be taken, also by a concurrent login, it is then suffixed.
+    let identity = identity_for_create(provider, claims);
+    let username = username_from_claims(provider, claims);
+    let email = verified_email.map(String::from);
+    let created = UserBmc::create_with_identity(
+        &root_ctx,
+        &state.mm,
+        username.clone(),
+        email.clone(),
+        &identity,
+    )
+    .await;
+    let user_id = match created {
+        Err(model::Error::UniqueViolation { constraint, .. })
+            if constraint == USERNAME_CONSTRAINT =>
+        {
+            let username = suffixed_username(provider, claims, &username);
+            UserBmc::create_with_identity(&root_ctx, &state.mm, username, email, &identity).await?
+        }
+        created => created?,
+    };
+    info!(
+        "{:<12} - oidc - {} identity linked to the new user {user_id}",
+        "HANDLER", provider.name
+    );
+
+    Ok(user_id)
+}
+
+/// Link the identity to `user_id`, unless it is already linked to another user.
+async fn link_identity(
+    state: &SharedState,
+    provider: &OidcProvider,
+    claims: &IdTokenClaims,
+    user_id: i64,
+) -> Result<()> {
+    match UserIdentityBmc::first_user_id(&state.mm, &provider.name, &claims.sub).await? {
+        Some(linked_user_id) if linked_user_id == user_id => return Ok(()),
+        Some(linked_user_id) => {
+            return Err(Error::OidcIdentityAlreadyLinked {
+                user_id: linked_user_id,
+            });
+        }
+        None => {}
+    }
+
+    UserIdentityBmc::create(&state.mm, user_id, &identity_for_create(provider, claims)).await?;
+    info!(
+        "{:<12} - oidc - {} identity linked to the user {user_id}",
+        "HANDLER", provider.name
+    );
+
+    Ok(())
+}
+
+fn identity_for_create<'a>(
+    provider: &'a OidcProvider,
+    claims: &'a IdTokenClaims,
+) -> UserIdentityForCreate<'a> {
+    UserIdentityForCreate {
+        provider: &provider.name,
+        subject: &claims.sub,
+        email: claims.email.as_deref(),
+    }
+}
+
+/// Username of a new user when the one of its claims is already taken,
+/// suffixed with a hash of the identity.
+fn suffixed_username(provider: &OidcProvider, claims: &IdTokenClaims, username: &str) -> String {
+    let identity_hash = sha256_into_b64u(&format!("{}:{}", provider.name, claims.sub));
+    format!("{username}-{}", &identity_hash[..8])
+}
+
+/// Same charset and max length as the registered usernames, with room for the suffix.
+fn username_from_claims(provider: &OidcProvider, claims: &IdTokenClaims) -> String {
+    let candidate = claims
+        .preferred_username
+        .as_deref()
+        .or_else(|| {
+            claims
+                .email
+                .as_deref()
+                .and_then(|email| email.split('@').next())
+        })
+        .unwrap_or_default();
+
+    let username: String = candidate
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+        .take(55)
+        .collect();
+
+    if username.chars().count() >= 3 {
+        username
+    } else {
+        format!(
+            "{}-user",
+            provider.name.chars().take(50).collect::<String>()
+        )
+    }
+}
+
+// endregion: --- User Linking
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fx_provider() -> OidcProvider {
+        OidcProvider {
+            name: "fx-provider".to_string(),
+            issuer: "http://localhost:9999".to_string(),
+            client_id: "fx-client".to_string(),
+            client_secret: Box::new("fx-secret".to_string()).into(),
+            scopes: vec!["openid".to_string()],
+        }
+    }
+
+    fn fx_claims(preferred_username: Option<&str>, email: Option<&str>) -> IdTokenClaims {
+        serde_json::from_value(serde_json::json!({
+            "sub": "fx-sub",
+            "preferred_username": preferred_username,
+            "email": email,
+        }))
+        .expect("claims should deserialize")
+    }
+
+    #[test]
+    fn test_username_from_claims_ok_sanitized() {
+        // -- Setup & Fixtures
+        let fx_claims = fx_claims(Some("Jane Doe+sso"), Some("jane@example.com"));
+
+        // -- Exec & Check
+        assert_eq!(
+            username_from_claims(&fx_provider(), &fx_claims),
+            "JaneDoesso"
+        );
+    }
+
+    #[test]
+    fn test_username_from_claims_ok_email_local_part() {
+        // -- Setup & Fixtures
+        let fx_claims = fx_claims(None, Some("jane.doe@example.com"));
+
+        // -- Exec & Check
+        assert_eq!(username_from_claims(&fx_provider(), &fx_claims), "jane.doe");
+    }
+
+    #[test]
+    fn test_username_from_claims_ok_fallback() {
+        // -- Setup & Fixtures
+        let fx_claims = fx_claims(Some("é"), None);
+
+        // -- Exec & Check
+        assert_eq!(
+            username_from_claims(&fx_provider(), &fx_claims),
+            "fx-provider-user"
+        );
+    }
+}
+// endregion: --- Tests
@@ -0,0 +1,225 @@
+use crate::crypt::token::{Token, generate_pwd_reset_token, validate_pwd_reset_token};
+use crate::crypt::{EncryptContent, pwd};
+use crate::ctx::Ctx;
+use crate::mailer::{Mail, Mailer};
+use crate::model::ModelManager;
+use crate::model::session::SessionBmc;
+use crate::model::user::{UserBmc, UserForLogin};
+use crate::startup::SharedState;
+use crate::web::error::ProblemDetails;
+use crate::web::mw_auth::require_user_token;
+use crate::web::mw_validate_json::ValidatedJson;
+use crate::web::rest::routes_login::validate_pwd_strength;
+use crate::web::{Error, Result, remove_refresh_cookie, remove_token_cookie};
+use axum::Router;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::post;
+use serde::Deserialize;
+use tower_cookies::Cookies;
+use tracing::{debug, error};
+use utoipa::ToSchema;
+use validator_derive::Validate;
+
+/// Routes merged under `/account` by the login routes.
+pub(super) fn sub_routes() -> Router<SharedState> {
+    Router::new()
+        .route("/password", post(change_pwd))
+        .route("/password/reset", post(request_pwd_reset))
+        .route("/password/reset/confirm", post(confirm_pwd_reset))
+}
+
+// region:    --- Change
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct PwdChangePayload {
+    pub pwd_current: String,
+    /// At least 12 characters, with a lowercase, an uppercase and a digit.
+    #[validate(custom(function = "validate_pwd_strength"))]
+    pub pwd_new: String,
+}
+
+#[utoipa::path(
+    post,
+    context_path = "/account",
+    path = "/password",
+    tag = "Account",
+    request_body = PwdChangePayload,
+    responses(
+        (status = 204, description = "Password changed"),
+        (status = 400, description = "New password too weak", body = ProblemDetails),
+        (status = 403, description = "Not authenticated with a user token or current password not matching", body = ProblemDetails),
+        (status = 500, description = "Internal Server Error", body = ProblemDetails)
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+async fn change_pwd(
+    State(state): State<SharedState>,
+    ctx: Ctx,
+    ValidatedJson(payload): ValidatedJson<PwdChangePayload>,
+) -> Result<StatusCode> {
+    debug!("{:<12} - change_pwd", "HANDLER");
+
+    require_user_token(&ctx)?;
+
+    let PwdChangePayload {
+        pwd_current,
+        pwd_new,
+    } = payload;
+
+    let user_id = validate_current_pwd(&state, &ctx, pwd_current).await?;
+
+    // -- Set the new password.
+    UserBmc::update_pwd(&ctx, &state.mm, user_id, &pwd_new).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Check the password of the user of the ctx, before a change of its credentials.
+/// Returns the id of the user.
+pub(super) async fn validate_current_pwd(
+    state: &SharedState,
+    ctx: &Ctx,
+    pwd_current: String,
+) -> Result<i64> {
+    let user: UserForLogin = UserBmc::get(ctx, &state.mm, ctx.user_id()).await?;
+    let user_id = user.id;
+    let Some(pwd) = user.pwd else {
+        return Err(Error::PwdChangeFailPwdNotMatching { user_id });
+    };
+
+    pwd::validate_pwd(
+        EncryptContent {
+            salt: user.pwd_salt.to_string(),
+            content: pwd_current,
+        },
+        pwd,
+    )
+    .await
+    .map_err(|_| Error::PwdChangeFailPwdNotMatching { user_id })?;
+
+    Ok(user_id)
+}
+
+// endregion: --- Change
+
+// region:    --- Reset
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct PwdResetRequestPayload {
+    #[validate(email(message = "Must be a valid email"))]
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct PwdResetConfirmPayload {
+    /// Token received by mail.
+    pub token: String,
+    /// At least 12 characters, with a lowercase, an uppercase and a digit.
+    #[validate(custom(function = "validate_pwd_strength"))]
+    pub pwd_new: String,
+}
+
+/// The reset token is signed with the token salt and the current password,
+/// both change once the reset is done, so a token can only be used once.
+fn pwd_reset_salt(user: &UserForLogin) -> String {
+    format!(
+        "{}.{}",
+        user.token_salt,
+        user.pwd.as_deref().unwrap_or_default()
+    )
+}
+
+#[utoipa::path(
+    post,
+    context_path = "/account",
+    path = "/password/reset",
+    tag = "Account",
+    request_body = PwdResetRequestPayload,
+    responses(
+        (status = 202, description = "A reset token has been mailed if the email is registered"),
+        (status = 400, description = "Invalid email", body = ProblemDetails),
+        (status = 500, description = "Internal Server Error", body = ProblemDetails)
+    )
+)]
+async fn request_pwd_reset(
+    State(state): State<SharedState>,
+    ValidatedJson(payload): ValidatedJson<PwdResetRequestPayload>,
+) -> Result<StatusCode> {
+    debug!("{:<12} - request_pwd_reset", "HANDLER");
+
+    let PwdResetRequestPayload { email } = payload;
+
+    // The response is the same whether the email is registered or not, and is sent
+    // before the lookup and the mail, so its timing does not disclose which emails have an account.
+    tokio::spawn(async move {
+        if let Err(ex) = send_pwd_reset(&state.mm, state.mailer.as_ref(), email).await {
+            error!("{:<12} - request_pwd_reset - {ex:?}", "HANDLER");
+        }
+    });
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Mail a reset token to the user with this email, nothing is done when there is none.
+async fn send_pwd_reset(mm: &ModelManager, mailer: &dyn Mailer, email: String) -> Result<()> {
+    let user: Option<UserForLogin> = UserBmc::first_by_email(&Ctx::root_ctx(), mm, &email).await?;
+    let Some(user) = user else {
+        return Ok(());
+    };
+
+    let token = generate_pwd_reset_token(&user.username, &pwd_reset_salt(&user))?;
+    let mail = Mail {
+        to: email,
+        subject: "Reset your password".to_string(),
+        body: format!(
+            "Hello {},\n\nUse the following token to reset your password:\n\n{token}\n\nIf you did not ask for a reset, you can ignore this mail.",
+            user.username
+        ),
+    };
+    mailer.send(mail).await?;
+
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    context_path = "/account",
+    path = "/password/reset/confirm",
+    tag = "Account",
+    request_body = PwdResetConfirmPayload,
+    responses(
+        (status = 204, description = "Password reset, all the sessions have been closed"),
+        (status = 400, description = "Invalid or expired token, or new password too weak", body = ProblemDetails),
+        (status = 500, description = "Internal Server Error", body = ProblemDetails)
+    )
+)]
+async fn confirm_pwd_reset(
+    State(state): State<SharedState>,
+    cookies: Cookies,
+    ValidatedJson(payload): ValidatedJson<PwdResetConfirmPayload>,
+) -> Result<StatusCode> {
+    debug!("{:<12} - confirm_pwd_reset", "HANDLER");
+
+    let PwdResetConfirmPayload { token, pwd_new } = payload;
+    let root_ctx = Ctx::root_ctx();
+
+    // -- Validate the token.
+    let token: Token = token.parse().map_err(|_| Error::PwdResetTokenInvalid)?;
+    let user: UserForLogin = UserBmc::first_by_username(&root_ctx, &state.mm, &token.ident)
+        .await?
+        .ok_or(Error::PwdResetTokenInvalid)?;
+    validate_pwd_reset_token(&token, &pwd_reset_salt(&user))
+        .map_err(|_| Error::PwdResetTokenInvalid)?;
+
+    // -- Set the new password and close all the sessions.
+    UserBmc::update_pwd(&root_ctx, &state.mm, user.id, &pwd_new).await?;
+    UserBmc::rotate_token_salt(&root_ctx, &state.mm, user.id).await?;
+    SessionBmc::delete_all_for_user(&state.mm, user.id).await?;
+    remove_token_cookie(&cookies)?;
+    remove_refresh_cookie(&cookies);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// endregion: --- Reset
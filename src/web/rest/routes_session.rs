@@ -0,0 +1,326 @@
+use crate::config::config;
+use crate::crypt::ct_eq;
+use crate::crypt::now_utc;
+use crate::crypt::token::{RefreshToken, generate_web_token_str};
+use crate::ctx::Ctx;
+use crate::model::session::{Session, SessionBmc, SessionForCreate, SessionForRefresh};
+use crate::model::user::{UserBmc, UserForAuth};
+use crate::model::{self, ModelManager};
+use crate::startup::SharedState;
+use crate::web::error::ProblemDetails;
+use crate::web::mw_auth::require_user_token;
+use crate::web::{self, Error, REFRESH_TOKEN, Result, remove_refresh_cookie, set_refresh_cookie};
+use axum::extract::{ConnectInfo, FromRequestParts, Path, State};
+use axum::http::request::Parts;
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use time::Duration;
+use tower_cookies::Cookies;
+use tracing::debug;
+use utoipa::ToSchema;
+
+/// Routes merged under `/account` by the login routes.
+pub(super) fn sub_routes() -> Router<SharedState> {
+    Router::new()
+        .route("/refresh", post(refresh))
+        .route("/sessions", get(list_sessions))
+        .route("/sessions/{id}", delete(delete_session))
+}
+
+// region:    --- Session Lifecycle
+
+/// Client opening a session, as shown in the session list.
+pub(super) struct SessionClient {
+    ip: Option<String>,
+    user_agent: Option<String>,
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for SessionClient {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        _state: &S,
+    ) -> core::result::Result<Self, Self::Rejection> {
+        let ip = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip().to_string());
+        let user_agent = parts
+            .headers
+            .get(header::USER_AGENT)
+            .and_then(|user_agent| user_agent.to_str().ok())
+            .map(|user_agent| user_agent.chars().take(512).collect());
+
+        Ok(Self { ip, user_agent })
+    }
+}
+
+impl SessionClient {
+    pub(super) fn ip(&self) -> Option<&str> {
+        self.ip.as_deref()
+    }
+}
+
+/// Open a new session for the user, the returned refresh token is the only copy of its secret.
+pub(super) async fn open_session(
+    mm: &ModelManager,
+    user_id: i64,
+    device: Option<String>,
+    client: SessionClient,
+) -> Result<RefreshToken> {
+    let user_ctx = Ctx::new(user_id)?;
+    let secret_b64u = RefreshToken::new_secret();
+    let expires_at = now_utc() + Duration::seconds_f64(config().crypt.refresh_duration_sec);
+
+    let session_id = SessionBmc::create(
+        &user_ctx,
+        mm,
+        SessionForCreate {
+            refresh_hash: RefreshToken::hash_secret(&secret_b64u),
+            device,
+            ip: client.ip,
+            user_agent: client.user_agent,
+            expires_at,
+        },
+    )
+    .await?;
+
+    Ok(RefreshToken {
+        session_id,
+        secret_b64u,
+    })
+}
+
+/// Revoke the session of the refresh token, if the token is still its current one.
+pub(super) async fn close_session(mm: &ModelManager, refresh_token: &RefreshToken) -> Result<()> {
+    let root_ctx = Ctx::root_ctx();
+
+    match SessionBmc::get_for_refresh(&root_ctx, mm, refresh_token.session_id).await {
+        Ok(session) if ct_eq(&refresh_token.hash(), &session.refresh_hash) => {
+            SessionBmc::delete(&root_ctx, mm, session.id).await?;
+            Ok(())
+        }
+        Ok(_) | Err(model::Error::EntityNotFound { .. }) => Ok(()),
+        Err(ex) => Err(ex.into()),
+    }
+}
+
+/// Validate the refresh token and replace it by a new one.
+///
+/// Presenting the previous token of a session again means that it has been copied,
+/// the session is then revoked and the legit client will have to log in again.
+async fn rotate_session(
+    mm: &ModelManager,
+    refresh_token: &str,
+) -> Result<(UserForAuth, RefreshToken)> {
+    let root_ctx = Ctx::root_ctx();
+
+    // -- Get the session.
+    let refresh_token: RefreshToken = refresh_token
+        .parse()
+        .map_err(|_| Error::RefreshTokenInvalid)?;
+    let session: SessionForRefresh =
+        match SessionBmc::get_for_refresh(&root_ctx, mm, refresh_token.session_id).await {
+            Ok(session) => session,
+            Err(model::Error::EntityNotFound { .. }) => return Err(Error::RefreshTokenInvalid),
+            Err(ex) => return Err(ex.into()),
+        };
+
+    // -- Validate the refresh token.
+    let refresh_hash = refresh_token.hash();
+    if !ct_eq(&refresh_hash, &session.refresh_hash) {
+        let is_reused = session
+            .prev_refresh_hash
+            .as_deref()
+            .is_some_and(|prev_refresh_hash| ct_eq(&refresh_hash, prev_refresh_hash));
+        if is_reused {
+            SessionBmc::delete(&root_ctx, mm, session.id).await?;
+            return Err(Error::RefreshTokenReused {
+                session_id: session.id,
+            });
+        }
+        return Err(Error::RefreshTokenInvalid);
+    }
+    if session.expires_at < now_utc() {
+        SessionBmc::delete(&root_ctx, mm, session.id).await?;
+        return Err(Error::RefreshTokenInvalid);
+    }
+
+    // -- Rotate the refresh token.
+    let secret_b64u = RefreshToken::new_secret();
+    let rotated = SessionBmc::rotate_refresh(
+        &root_ctx,
+        mm,
+        session.id,
+        &session.refresh_hash,
+        &RefreshToken::hash_secret(&secret_b64u),
+    )
+    .await?;
+    if !rotated {
+        return Err(Error::RefreshTokenInvalid);
+    }
+
+    let user: UserForAuth = UserBmc::get(&root_ctx, mm, session.user_id).await?;
+
+    Ok((
+        user,
+        RefreshToken {
+            session_id: session.id,
+            secret_b64u,
+        },
+    ))
+}
+
+// endregion: --- Session Lifecycle
+
+// region:    --- Refresh
+
+/// Tokens returned in the body, the access token is sent back in an `Authorization: Bearer <token>` header.
+#[derive(Serialize, ToSchema)]
+pub struct TokenResponse {
+    access_token: String,
+    /// Always `Bearer`.
+    token_type: &'static str,
+    /// Lifetime of the access token, in seconds.
+    expires_in: i64,
+    /// Single use token to get new tokens from `/account/refresh`.
+    refresh_token: String,
+}
+
+impl TokenResponse {
+    pub(super) fn new(
+        username: &str,
+        token_salt: &str,
+        refresh_token: &RefreshToken,
+    ) -> Result<Self> {
+        Ok(Self {
+            access_token: generate_web_token_str(username, token_salt)?,
+            token_type: "Bearer",
+            expires_in: Duration::seconds_f64(config().crypt.token_duration_sec).whole_seconds(),
+            refresh_token: refresh_token.to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RefreshPayload {
+    /// Refresh token of `/account/token`, the `refresh-token` cookie is used when absent.
+    pub refresh_token: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    context_path = "/account",
+    path = "/refresh",
+    tag = "Account",
+    request_body(content = Option<RefreshPayload>, description = "Only for the clients not using the cookies"),
+    responses(
+        (status = 200, description = "New tokens, when the refresh token is sent in the body", body = TokenResponse),
+        (status = 204, description = "New token cookies, when the refresh token is sent in the cookie"),
+        (status = 403, description = "Refresh token invalid, expired or already used", body = ProblemDetails),
+        (status = 500, description = "Internal Server Error", body = ProblemDetails)
+    )
+)]
+async fn refresh(
+    State(state): State<SharedState>,
+    cookies: Cookies,
+    payload: Option<Json<RefreshPayload>>,
+) -> Result<Response> {
+    debug!("{:<12} - refresh", "HANDLER");
+
+    // -- Get the refresh token, from the body first.
+    let body_token = payload.and_then(|Json(payload)| payload.refresh_token);
+    let from_cookie = body_token.is_none();
+    let refresh_token = match body_token {
+        Some(refresh_token) => refresh_token,
+        None => cookies
+            .get(REFRESH_TOKEN)
+            .map(|cookie| cookie.value().to_string())
+            .ok_or(Error::RefreshTokenInvalid)?,
+    };
+
+    // -- Rotate the session.
+    let (user, refresh_token) = match rotate_session(&state.mm, &refresh_token).await {
+        Ok(rotated) => rotated,
+        Err(ex) => {
+            if from_cookie {
+                remove_refresh_cookie(&cookies);
+            }
+            return Err(ex);
+        }
+    };
+    let token_salt = user.token_salt.to_string();
+
+    if from_cookie {
+        web::set_token_cookie(&cookies, &user.username, &token_salt)?;
+        set_refresh_cookie(&cookies, &refresh_token);
+        Ok(StatusCode::NO_CONTENT.into_response())
+    } else {
+        let body = TokenResponse::new(&user.username, &token_salt, &refresh_token)?;
+        Ok(Json(body).into_response())
+    }
+}
+
+// endregion: --- Refresh
+
+// region:    --- Sessions
+
+#[utoipa::path(
+    get,
+    context_path = "/account",
+    path = "/sessions",
+    tag = "Account",
+    responses(
+        (status = 200, description = "Open sessions of the user, the most recently used first", body = Vec<Session>),
+        (status = 403, description = "Not authenticated with a user token", body = ProblemDetails),
+        (status = 500, description = "Internal Server Error", body = ProblemDetails)
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+async fn list_sessions(State(state): State<SharedState>, ctx: Ctx) -> Result<Json<Vec<Session>>> {
+    debug!("{:<12} - list_sessions", "HANDLER");
+
+    require_user_token(&ctx)?;
+
+    let sessions = SessionBmc::list(&ctx, &state.mm).await?;
+
+    Ok(Json(sessions))
+}
+
+#[utoipa::path(
+    delete,
+    context_path = "/account",
+    path = "/sessions/{id}",
+    tag = "Account",
+    params(
+        ("id" = i64, Path, description = "Id of the session")
+    ),
+    responses(
+        (status = 204, description = "Session revoked, its refresh token can no longer be used"),
+        (status = 403, description = "Not authenticated with a user token", body = ProblemDetails),
+        (status = 404, description = "Session not found", body = ProblemDetails),
+        (status = 500, description = "Internal Server Error", body = ProblemDetails)
+    ),
+    security(("bearer" = []), ("cookie" = []))
+)]
+async fn delete_session(
+    State(state): State<SharedState>,
+    ctx: Ctx,
+    Path(id): Path<i64>,
+) -> Result<StatusCode> {
+    debug!("{:<12} - delete_session - {id}", "HANDLER");
+
+    require_user_token(&ctx)?;
+
+    SessionBmc::delete(&ctx, &state.mm, id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// endregion: --- Sessions
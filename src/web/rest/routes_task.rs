@@ -0,0 +1,326 @@
+use crate::ctx::Ctx;
+use crate::model::list::{ListOptions, ListPage};
+use crate::model::task::{Task, TaskBmc, TaskForCreate, TaskForUpdate};
+use crate::startup::SharedState;
+use crate::web::error::ProblemDetails;
+use crate::web::mw_auth::require_permission;
+use crate::web::mw_validate_json::ValidatedJson;
+use crate::web::{Error, Result};
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, patch, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use tracing::debug;
+use utoipa::IntoParams;
+
+pub fn routes() -> Router<SharedState> {
+    Router::new().nest("/tasks", sub_routes())
+}
+
+fn sub_routes() -> Router<SharedState> {
+    let routes_read = Router::new()
+        .route("/", get(list_tasks))
+        .route("/{id}", get(get_task))
+        .route_layer(require_permission("task:read"));
+
+    let routes_write = Router::new()
+        .route("/", post(create_task))
+        .route("/{id}", patch(update_task).delete(delete_task))
+        .route_layer(require_permission("task:write"));
+
+    routes_read.merge(routes_write)
+}
+
+/// Pagination of the task list, passed in the query string.
+#[derive(Debug, Clone, Deserialize, IntoParams)]
+pub struct ListTasksQuery {
+    /// Max number of tasks to return (default 100, max 1000).
+    limit: Option<u32>,
+    offset: Option<u32>,
+    /// Comma separated columns, prefixed with `!` for a descending order (ex: `!ctime,title`).
+    order_by: Option<String>,
+    /// Cursor of the next page, taken from the `Link` header of the previous response.
+    cursor: Option<String>,
+}
+
+impl ListTasksQuery {
+    /// Uri of the page starting at `next_cursor`, with the same limit and ordering.
+    fn next_page_uri(&self, next_cursor: &str) -> String {
+        let mut query = form_urlencoded::Serializer::new(String::new());
+        query.append_pair("cursor", next_cursor);
+        if let Some(limit) = self.limit {
+            query.append_pair("limit", &limit.to_string());
+        }
+        if let Some(order_by) = &self.order_by {
+            query.append_pair("order_by", order_by);
+        }
+        format!("/api/tasks?{}", query.finish())
+    }
+}
+
+impl From<ListTasksQuery> for ListOptions {
+    fn from(query: ListTasksQuery) -> Self {
+        ListOptions {
+            limit: query.limit,
+            offset: query.offset,
+            order_bys: query
+                .order_by
+                .map(|order_by| order_by.split(',').map(str::to_string).collect()),
+            cursor: query.cursor,
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    context_path = "/api",
+    path = "/tasks",
+    tag = "Task",
+    params(ListTasksQuery),
+    responses(
+        (status = 200, description = "A page of tasks", body = Vec<Task>,
+            headers(("Link" = String, description = "Uri of the next page, with `rel=\"next\"`"))),
+        (status = 400, description = "Invalid list options", body = ProblemDetails),
+        (status = 403, description = "Not authenticated or missing the `task:read` permission", body = ProblemDetails),
+        (status = 500, description = "Internal Server Error", body = ProblemDetails)
+    ),
+    security(("bearer" = []), ("cookie" = []), ("api_key" = ["task:read"]))
+)]
+async fn list_tasks(
+    State(state): State<SharedState>,
+    ctx: Ctx,
+    Query(query): Query<ListTasksQuery>,
+) -> Result<Response> {
+    debug!("{:<12} - list_tasks - {query:?}", "HANDLER");
+
+    let ListPage { items, next_cursor } =
+        TaskBmc::list_page(&ctx, &state.mm, None, Some(query.clone().into())).await?;
+
+    let mut res = Json(items).into_response();
+    if let Some(next_cursor) = next_cursor {
+        let link = format!("<{}>; rel=\"next\"", query.next_page_uri(&next_cursor));
+        if let Ok(link) = HeaderValue::from_str(&link) {
+            res.headers_mut().insert(header::LINK, link);
+        }
+    }
+
+    Ok(res)
+}
+
+#[utoipa::path(
+    post,
+    context_path = "/api",
+    path = "/tasks",
+    tag = "Task",
+    request_body = TaskForCreate,
+    responses(
+        (status = 201, description = "Task created", body = Task,
+            headers(
+                ("Location" = String, description = "Uri of the created task"),
+                ("ETag" = String, description = "Current version of the task")
+            )),
+        (status = 400, description = "Invalid body", body = ProblemDetails),
+        (status = 403, description = "Not authenticated or missing the `task:write` permission", body = ProblemDetails),
+        (status = 500, description = "Internal Server Error", body = ProblemDetails)
+    ),
+    security(("bearer" = []), ("cookie" = []), ("api_key" = ["task:write"]))
+)]
+async fn create_task(
+    State(state): State<SharedState>,
+    ctx: Ctx,
+    ValidatedJson(task_c): ValidatedJson<TaskForCreate>,
+) -> Result<impl IntoResponse> {
+    debug!("{:<12} - create_task", "HANDLER");
+
+    let id = TaskBmc::create(&ctx, &state.mm, task_c).await?;
+    let task = TaskBmc::get(&ctx, &state.mm, id).await?;
+
+    Ok((
+        StatusCode::CREATED,
+        [
+            (header::LOCATION, format!("/api/tasks/{id}")),
+            (header::ETAG, etag(&task)),
+        ],
+        Json(task),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    context_path = "/api",
+    path = "/tasks/{id}",
+    tag = "Task",
+    params(
+        ("id" = i64, Path, description = "Id of the task")
+    ),
+    responses(
+        (status = 200, description = "The task", body = Task,
+            headers(("ETag" = String, description = "Current version of the task"))),
+        (status = 403, description = "Not authenticated or missing the `task:read` permission", body = ProblemDetails),
+        (status = 404, description = "Task not found", body = ProblemDetails),
+        (status = 500, description = "Internal Server Error", body = ProblemDetails)
+    ),
+    security(("bearer" = []), ("cookie" = []), ("api_key" = ["task:read"]))
+)]
+async fn get_task(
+    State(state): State<SharedState>,
+    ctx: Ctx,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse> {
+    debug!("{:<12} - get_task - {id}", "HANDLER");
+
+    let task = TaskBmc::get(&ctx, &state.mm, id).await?;
+
+    Ok(([(header::ETAG, etag(&task))], Json(task)))
+}
+
+#[utoipa::path(
+    patch,
+    context_path = "/api",
+    path = "/tasks/{id}",
+    tag = "Task",
+    params(
+        ("id" = i64, Path, description = "Id of the task"),
+        ("If-Match" = Option<String>, Header, description = "ETag of the task, the update fails if it has been modified since")
+    ),
+    request_body = TaskForUpdate,
+    responses(
+        (status = 200, description = "The updated task", body = Task,
+            headers(("ETag" = String, description = "New version of the task"))),
+        (status = 400, description = "Invalid body or If-Match header", body = ProblemDetails),
+        (status = 403, description = "Not authenticated or missing the `task:write` permission", body = ProblemDetails),
+        (status = 404, description = "Task not found", body = ProblemDetails),
+        (status = 409, description = "Task modified since the If-Match version", body = ProblemDetails),
+        (status = 500, description = "Internal Server Error", body = ProblemDetails)
+    ),
+    security(("bearer" = []), ("cookie" = []), ("api_key" = ["task:write"]))
+)]
+async fn update_task(
+    State(state): State<SharedState>,
+    ctx: Ctx,
+    Path(id): Path<i64>,
+    headers: HeaderMap,
+    ValidatedJson(task_u): ValidatedJson<TaskForUpdate>,
+) -> Result<impl IntoResponse> {
+    debug!("{:<12} - update_task - {id}", "HANDLER");
+
+    match if_match_version(&headers)? {
+        Some(version) => TaskBmc::update_if_version(&ctx, &state.mm, id, version, task_u).await?,
+        None => TaskBmc::update(&ctx, &state.mm, id, task_u).await?,
+    }
+    let task = TaskBmc::get(&ctx, &state.mm, id).await?;
+
+    Ok(([(header::ETAG, etag(&task))], Json(task)))
+}
+
+#[utoipa::path(
+    delete,
+    context_path = "/api",
+    path = "/tasks/{id}",
+    tag = "Task",
+    params(
+        ("id" = i64, Path, description = "Id of the task")
+    ),
+    responses(
+        (status = 204, description = "Task deleted"),
+        (status = 403, description = "Not authenticated or missing the `task:write` permission", body = ProblemDetails),
+        (status = 404, description = "Task not found", body = ProblemDetails),
+        (status = 500, description = "Internal Server Error", body = ProblemDetails)
+    ),
+    security(("bearer" = []), ("cookie" = []), ("api_key" = ["task:write"]))
+)]
+async fn delete_task(
+    State(state): State<SharedState>,
+    ctx: Ctx,
+    Path(id): Path<i64>,
+) -> Result<StatusCode> {
+    debug!("{:<12} - delete_task - {id}", "HANDLER");
+
+    TaskBmc::delete(&ctx, &state.mm, id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// region:    --- ETag
+
+/// Strong entity tag of the task, derived from its version.
+fn etag(task: &Task) -> String {
+    format!("\"{}\"", task.version)
+}
+
+/// Version expected by the `If-Match` header, `None` when absent or `*`.
+fn if_match_version(headers: &HeaderMap) -> Result<Option<i64>> {
+    let Some(if_match) = headers.get(header::IF_MATCH) else {
+        return Ok(None);
+    };
+    let if_match = if_match.to_str().map_err(|_| Error::IfMatchInvalid)?.trim();
+    if if_match == "*" {
+        return Ok(None);
+    }
+
+    if_match
+        .strip_prefix('"')
+        .and_then(|etag| etag.strip_suffix('"'))
+        .and_then(|version| version.parse::<i64>().ok())
+        .map(Some)
+        .ok_or(Error::IfMatchInvalid)
+}
+
+// endregion: --- ETag
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_if_match_version_ok() {
+        // -- Fixtures
+        let fx_cases = [(None, None), (Some("*"), None), (Some("\"3\""), Some(3))];
+
+        // -- Exec & Check
+        for (if_match, version) in fx_cases {
+            let mut headers = HeaderMap::new();
+            if let Some(if_match) = if_match {
+                headers.insert(header::IF_MATCH, HeaderValue::from_static(if_match));
+            }
+            assert_eq!(if_match_version(&headers).ok(), Some(version));
+        }
+    }
+
+    #[test]
+    fn test_if_match_version_err_invalid() {
+        // -- Fixtures
+        let fx_cases = ["3", "W/\"3\"", "\"3\", \"4\"", "\"abc\""];
+
+        // -- Exec & Check
+        for if_match in fx_cases {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::IF_MATCH, HeaderValue::from_static(if_match));
+            assert!(
+                matches!(if_match_version(&headers), Err(Error::IfMatchInvalid)),
+                "Should have matched `Err(Error::IfMatchInvalid)` for {if_match}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_next_page_uri_ok_encoded() {
+        // -- Fixtures
+        let fx_query = ListTasksQuery {
+            limit: Some(10),
+            offset: None,
+            order_by: Some("!title&x=1".to_string()),
+            cursor: None,
+        };
+
+        // -- Exec & Check
+        assert_eq!(
+            fx_query.next_page_uri("a+b/c.d"),
+            "/api/tasks?cursor=a%2Bb%2Fc.d&limit=10&order_by=%21title%26x%3D1"
+        );
+    }
+}
+// endregion: --- Tests
@@ -0,0 +1,36 @@
+use crate::crypt::jwt::jwks;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::{Json, Router};
+use tracing::debug;
+
+pub fn routes() -> Router {
+    Router::new().nest("/.well-known", sub_routes())
+}
+
+fn sub_routes() -> Router {
+    Router::new().route("/jwks.json", get(get_jwks))
+}
+
+#[utoipa::path(
+    get,
+    context_path = "/.well-known",
+    path = "/jwks.json",
+    tag = "Account",
+    responses(
+        (status = 200, description = "Public keys verifying the jwt, empty when they are signed with HS512", body = Object,
+            content_type = "application/jwk-set+json"),
+    )
+)]
+async fn get_jwks() -> impl IntoResponse {
+    debug!("{:<12} - get_jwks", "HANDLER");
+
+    (
+        [
+            (header::CONTENT_TYPE, "application/jwk-set+json"),
+            (header::CACHE_CONTROL, "public, max-age=300"),
+        ],
+        Json(jwks()),
+    )
+}
@@ -1,5 +1,9 @@
-use axum::Router;
-use utoipa::OpenApi;
+use crate::web::rpc::rpc_router;
+use crate::web::{API_KEY_HEADER, AUTH_TOKEN};
+use axum::routing::get;
+use axum::{Json, Router};
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
 use utoipa_rapidoc::RapiDoc;
 use utoipa_redoc::{Redoc, Servable};
 use utoipa_scalar::{Scalar, Servable as ScalarServable};
@@ -9,34 +13,78 @@ use utoipauto::utoipauto;
 pub const ACCOUNT_TAG: &str = "Account";
 pub const HEALTH_TAG: &str = "Health";
 pub const HELLO_TAG: &str = "Hello";
+pub const TASK_TAG: &str = "Task";
 
 //#[openapi(paths(test_collect_schemas))]
 #[utoipauto]
 #[derive(OpenApi)]
 #[openapi(
     info(description = "Simple API to demonstrate axum framework capabilites"),
+    external_docs(
+        url = "/api-docs/openrpc.json",
+        description = "OpenRPC document of the JSON-RPC methods served on /api/rpc"
+    ),
     tags(
         (name = ACCOUNT_TAG, description = "All related user endpoints"),
         (name = HEALTH_TAG, description = "Retreive the current status of the service"),
         (name = HELLO_TAG, description = "Basic routes for testing"),
+        (name = TASK_TAG, description = "CRUD operations on the tasks"),
     ),
-    security(
-        (),
-        ("my_auth" = ["read:items", "edit:items"]),
-        ("token_jwt" = [])
-    ),
+    modifiers(&SecurityAddon),
 )]
 struct ApiDoc;
 
+/// Security schemes referenced by the `security` of the paths.
+struct SecurityAddon;
+
+impl Modify for SecurityAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some(
+                        "Access token of `/account/token` or `/account/refresh`",
+                    ))
+                    .build(),
+            ),
+        );
+        components.add_security_scheme(
+            "cookie",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::with_description(
+                AUTH_TOKEN,
+                "Access token set by `/account/login`",
+            ))),
+        );
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::with_description(
+                API_KEY_HEADER,
+                "Api key of `/account/api-keys`, the required permission must be one of its scopes",
+            ))),
+        );
+    }
+}
+
 /// Serving multiples format of API documentation :
 /// - /swagger-ui
 /// - /rapidoc
 /// - /redoc
 /// - /scalar
+///
+/// The JSON-RPC methods are described by an OpenRPC document on /api-docs/openrpc.json.
 pub fn routes() -> Router {
     let api_doc = ApiDoc::openapi();
+    let openrpc_doc = rpc_router().openrpc();
 
     Router::new()
+        .route(
+            "/api-docs/openrpc.json",
+            get(|| async move { Json(openrpc_doc) }),
+        )
         .merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", api_doc.clone()))
         .merge(RapiDoc::new("/api-docs/openapi.json").path("/rapidoc"))
         .merge(Redoc::with_url("/redoc", api_doc.clone()))
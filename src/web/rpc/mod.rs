@@ -1,71 +1,250 @@
 // region:    --- Modules
 
+mod openrpc;
+mod router;
+mod rpc_error;
 mod task_rpc;
 
+pub use self::openrpc::OpenRpcDoc;
+pub use self::router::{RpcHandler, RpcMethod, RpcMethodMeta, RpcMethodSchemas, RpcRouter};
+pub use self::rpc_error::RpcError;
+
+use crate::config::config;
 use crate::ctx::Ctx;
 use crate::model::ModelManager;
-use crate::web::rpc::task_rpc::{create_task, delete_task, list_tasks, update_task};
+use crate::model::list::ListOptions;
 use crate::web::{Error, Result};
+use axum::Json;
+use axum::Router;
+use axum::body::Bytes;
 use axum::extract::State;
+use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
 use axum::routing::post;
-use axum::{Json, Router};
-use serde::Deserialize;
-use serde_json::{Value, from_value, json, to_value};
+use axum_macros::FromRef;
+use futures::future::join_all;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Value, from_slice, from_value};
+use std::sync::Arc;
 use tracing::debug;
+use utoipa::ToSchema;
 
 // endregion: --- Modules
 
 // region:    --- RPC Types
 
+pub const JSONRPC_VERSION: &str = "2.0";
+
 /// JSON-RPC Request Body.
 #[derive(Deserialize)]
 struct RpcRequest {
+    jsonrpc: String,
+    /// `None` when the member is absent, which makes the request a notification.
+    #[serde(default, deserialize_with = "deserialize_id")]
     id: Option<Value>,
     method: String,
     params: Option<Value>,
 }
 
-#[derive(Deserialize)]
+/// Keep an explicit `"id": null` as `Some(Value::Null)` to distinguish it from a notification.
+fn deserialize_id<'de, D>(deserializer: D) -> core::result::Result<Option<Value>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Value::deserialize(deserializer).map(Some)
+}
+
+/// JSON-RPC Response Body.
+#[derive(Serialize)]
+struct RpcResponse {
+    jsonrpc: &'static str,
+    id: Value,
+    #[serde(flatten)]
+    payload: RpcResponsePayload,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum RpcResponsePayload {
+    Result(Value),
+    Error(RpcError),
+}
+
+impl RpcResponse {
+    fn new(id: Value, result: Result<Value>) -> Self {
+        let payload = match result {
+            Ok(value) => RpcResponsePayload::Result(value),
+            Err(error) => {
+                log_rpc_error(&error);
+                RpcResponsePayload::Error(RpcError::from(&error))
+            }
+        };
+
+        Self {
+            jsonrpc: JSONRPC_VERSION,
+            id,
+            payload,
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
 pub struct ParamsForCreate<D> {
     data: D,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct ParamsForUpdate<D> {
     id: i64,
+    /// When provided, the update fails if the entity has been modified since this version.
+    version: Option<i64>,
     data: D,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct ParamsIded {
     id: i64,
 }
 
+#[derive(Deserialize, ToSchema)]
+pub struct ParamsList<F> {
+    filters: Option<F>,
+    list_options: Option<ListOptions>,
+}
+
 // endregion: --- RPC Types
 
+/// All the rpc methods of the application, each domain module registers its own methods.
+pub fn rpc_router() -> RpcRouter {
+    RpcRouter::new().merge(task_rpc::rpc_router())
+}
+
+#[derive(Clone, FromRef)]
+struct RpcState {
+    mm: ModelManager,
+    rpc_router: Arc<RpcRouter>,
+}
+
 pub fn routes(mm: ModelManager) -> Router {
+    let state = RpcState {
+        mm,
+        rpc_router: Arc::new(rpc_router()),
+    };
+
     Router::new()
         .route("/rpc", post(rpc_handler))
-        .with_state(mm)
+        .with_state(state)
 }
 
+/// Entry point of the JSON-RPC 2.0 endpoint.
+///
+/// The body is parsed by hand, a malformed payload must still be answered
+/// with a JSON-RPC error object and not with the generic json rejection.
 async fn rpc_handler(
     State(mm): State<ModelManager>,
+    State(rpc_router): State<Arc<RpcRouter>>,
     ctx: Ctx,
-    Json(rpc_req): Json<RpcRequest>,
+    body: Bytes,
 ) -> Response {
-    // -- Create the RPC Info to be set to the response.extensions.
-    let rpc_info = RpcInfo {
-        id: rpc_req.id.clone(),
-        method: rpc_req.method.clone(),
+    // -- Parse the body.
+    let Ok(value) = from_slice::<Value>(&body) else {
+        return rpc_error_response(Value::Null, Error::RpcParseFail);
     };
 
-    // -- Exec & Store RpcInfo in response.
-    let mut res = _rpc_handler(ctx, mm, rpc_req).await.into_response();
-    res.extensions_mut().insert(rpc_info);
+    match value {
+        Value::Array(values) => rpc_batch_handler(&rpc_router, ctx, mm, values).await,
+        value => {
+            let rpc_info = RpcInfo {
+                id: value.get("id").cloned(),
+                method: value
+                    .get("method")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string(),
+            };
+
+            // -- Exec & Store RpcInfo in response.
+            let mut res = match exec_rpc_call(&rpc_router, ctx, mm, value).await {
+                Some(rpc_res) => Json(rpc_res).into_response(),
+                // Notification, the server must not reply.
+                None => StatusCode::NO_CONTENT.into_response(),
+            };
+            res.extensions_mut().insert(rpc_info);
+
+            res
+        }
+    }
+}
+
+/// Execute all the calls of a batch concurrently.
+///
+/// Responses keep the order of the requests, notifications are left out of the array.
+async fn rpc_batch_handler(
+    rpc_router: &RpcRouter,
+    ctx: Ctx,
+    mm: ModelManager,
+    values: Vec<Value>,
+) -> Response {
+    // -- Validate the batch.
+    if values.is_empty() {
+        return rpc_error_response(Value::Null, Error::RpcInvalidRequest);
+    }
+    let max = config().rpc.max_batch_size;
+    if values.len() > max {
+        return rpc_error_response(
+            Value::Null,
+            Error::RpcBatchTooLarge {
+                max,
+                actual: values.len(),
+            },
+        );
+    }
+
+    debug!(
+        "{:<12} - rpc_batch_handler - size: {}",
+        "HANDLER",
+        values.len()
+    );
+
+    // -- Exec all the calls.
+    let rpc_calls = values
+        .into_iter()
+        .map(|value| exec_rpc_call(rpc_router, ctx.clone(), mm.clone(), value));
+    let rpc_responses: Vec<RpcResponse> = join_all(rpc_calls).await.into_iter().flatten().collect();
 
-    res
+    // A batch made only of notifications must not be answered.
+    if rpc_responses.is_empty() {
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        Json(rpc_responses).into_response()
+    }
+}
+
+/// Validate and execute one call, `None` is returned for notifications.
+async fn exec_rpc_call(
+    rpc_router: &RpcRouter,
+    ctx: Ctx,
+    mm: ModelManager,
+    value: Value,
+) -> Option<RpcResponse> {
+    // -- Validate the request object.
+    let rpc_req = match parse_rpc_request(value) {
+        Ok(rpc_req) => rpc_req,
+        Err((id, error)) => return Some(RpcResponse::new(id, Err(error))),
+    };
+
+    match rpc_req.id.clone() {
+        Some(id) => {
+            let result = _rpc_handler(rpc_router, ctx, mm, rpc_req).await;
+            Some(RpcResponse::new(id, result))
+        }
+        None => {
+            if let Err(error) = _rpc_handler(rpc_router, ctx, mm, rpc_req).await {
+                log_rpc_error(&error);
+            }
+            None
+        }
+    }
 }
 
 /// RPC basic information holding the id and method for further logging.
@@ -75,49 +254,45 @@ pub struct RpcInfo {
     pub method: String,
 }
 
-macro_rules! exec_rpc_fn {
-    // With Params
-    ($rpc_fn:expr, $ctx:expr, $mm:expr, $rpc_params:expr) => {{
-        let rpc_fn_name = stringify!($rpc_fn);
-        let params = $rpc_params.ok_or(Error::RpcMissingParams {
-            rpc_method: rpc_fn_name.to_string(),
-        })?;
-        let params = from_value(params).map_err(|_| Error::RpcFailJsonParams {
-            rpc_method: rpc_fn_name.to_string(),
-        })?;
-        $rpc_fn($ctx, $mm, params).await.map(to_value)??
-    }};
-
-    // Without Params
-    ($rpc_fn:expr, $ctx:expr, $mm:expr) => {
-        $rpc_fn($ctx, $mm).await.map(to_value)??
-    };
+/// Validate a json value against the JSON-RPC 2.0 request object.
+/// On failure, the id is still returned (when readable) to be echoed in the error response.
+fn parse_rpc_request(value: Value) -> core::result::Result<RpcRequest, (Value, Error)> {
+    let id = value.get("id").cloned().unwrap_or(Value::Null);
+
+    let rpc_req: RpcRequest =
+        from_value(value).map_err(|_| (id.clone(), Error::RpcInvalidRequest))?;
+    if rpc_req.jsonrpc != JSONRPC_VERSION {
+        return Err((id, Error::RpcInvalidRequest));
+    }
+
+    Ok(rpc_req)
+}
+
+/// The RPC errors are answered in the body and never reach `mw_res_map`, so they are logged here.
+fn log_rpc_error(error: &Error) {
+    let (status_code, client_error) = error.client_status_and_error();
+    error.log(status_code, &client_error);
+}
+
+fn rpc_error_response(id: Value, error: Error) -> Response {
+    debug!("{:<12} - rpc_error_response - {error:?}", "HANDLER");
+
+    Json(RpcResponse::new(id, Err(error))).into_response()
 }
 
-async fn _rpc_handler(ctx: Ctx, mm: ModelManager, rpc_req: RpcRequest) -> Result<Json<Value>> {
+async fn _rpc_handler(
+    rpc_router: &RpcRouter,
+    ctx: Ctx,
+    mm: ModelManager,
+    rpc_req: RpcRequest,
+) -> Result<Value> {
     let RpcRequest {
-        id: rpc_id,
         method: rpc_method,
         params: rpc_params,
+        ..
     } = rpc_req;
 
     debug!("{:<12} - _rpc_handler - method: {rpc_method}", "HANDLER");
 
-    let result_json: Value = match rpc_method.as_str() {
-        // -- Task RPC methods.
-        "create_task" => exec_rpc_fn!(create_task, ctx, mm, rpc_params),
-        "list_tasks" => exec_rpc_fn!(list_tasks, ctx, mm),
-        "update_task" => exec_rpc_fn!(update_task, ctx, mm, rpc_params),
-        "delete_task" => exec_rpc_fn!(delete_task, ctx, mm, rpc_params),
-
-        // -- Fallback as Err.
-        _ => return Err(Error::RpcMethodUnknown(rpc_method)),
-    };
-
-    let body_response = json!({
-        "id": rpc_id,
-        "result": result_json
-    });
-
-    Ok(Json(body_response))
+    rpc_router.call(ctx, mm, &rpc_method, rpc_params).await
 }
@@ -0,0 +1,138 @@
+//! OpenRPC 1.x document generated from the methods registered in the [RpcRouter].
+//!
+//! Specification: <https://spec.open-rpc.org>
+
+use crate::web::rpc::RpcRouter;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use utoipa::openapi::RefOr;
+use utoipa::openapi::schema::Schema;
+
+pub const OPENRPC_VERSION: &str = "1.3.2";
+
+// region:    --- OpenRPC Types
+
+#[derive(Clone, Serialize)]
+pub struct OpenRpcDoc {
+    openrpc: &'static str,
+    info: OpenRpcInfo,
+    servers: Vec<OpenRpcServer>,
+    methods: Vec<OpenRpcMethod>,
+    components: OpenRpcComponents,
+}
+
+#[derive(Clone, Serialize)]
+struct OpenRpcInfo {
+    title: &'static str,
+    version: &'static str,
+}
+
+#[derive(Clone, Serialize)]
+struct OpenRpcServer {
+    name: &'static str,
+    url: &'static str,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OpenRpcMethod {
+    name: &'static str,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    description: &'static str,
+    params: Vec<ContentDescriptor>,
+    result: ContentDescriptor,
+    param_structure: &'static str,
+    /// Extension field holding the permission needed to call the method.
+    #[serde(rename = "x-permission", skip_serializing_if = "Option::is_none")]
+    permission: Option<&'static str>,
+}
+
+#[derive(Clone, Serialize)]
+struct ContentDescriptor {
+    name: String,
+    required: bool,
+    schema: RefOr<Schema>,
+}
+
+#[derive(Clone, Serialize)]
+struct OpenRpcComponents {
+    schemas: BTreeMap<String, RefOr<Schema>>,
+}
+
+// endregion: --- OpenRPC Types
+
+impl OpenRpcDoc {
+    pub fn from_router(rpc_router: &RpcRouter) -> Self {
+        let mut components = Vec::new();
+
+        let methods = rpc_router
+            .methods_with_schemas()
+            .map(|(meta, schemas)| {
+                schemas.collect_components(&mut components);
+
+                OpenRpcMethod {
+                    name: meta.name,
+                    description: meta.description,
+                    params: schemas
+                        .params
+                        .as_ref()
+                        .map(params_descriptors)
+                        .unwrap_or_default(),
+                    result: ContentDescriptor {
+                        name: "result".to_string(),
+                        required: true,
+                        schema: schemas.result.clone(),
+                    },
+                    param_structure: "by-name",
+                    permission: meta.permission,
+                }
+            })
+            .collect();
+
+        Self {
+            openrpc: OPENRPC_VERSION,
+            info: OpenRpcInfo {
+                title: env!("CARGO_PKG_NAME"),
+                version: env!("CARGO_PKG_VERSION"),
+            },
+            servers: vec![OpenRpcServer {
+                name: "JSON-RPC 2.0",
+                url: "/api/rpc",
+            }],
+            methods,
+            components: OpenRpcComponents {
+                schemas: components.into_iter().collect(),
+            },
+        }
+    }
+}
+
+/// Params are sent by name, so each property of the params object is one descriptor.
+fn params_descriptors(schema: &RefOr<Schema>) -> Vec<ContentDescriptor> {
+    match schema {
+        RefOr::T(Schema::Object(object)) => object
+            .properties
+            .iter()
+            .map(|(name, schema)| ContentDescriptor {
+                name: name.clone(),
+                required: object.required.contains(name),
+                schema: schema.clone(),
+            })
+            .collect(),
+        // Optional params object (`Option<P>`), every param becomes optional.
+        RefOr::T(Schema::OneOf(one_of)) => one_of
+            .items
+            .iter()
+            .flat_map(params_descriptors)
+            .map(|descriptor| ContentDescriptor {
+                required: false,
+                ..descriptor
+            })
+            .collect(),
+        _ => vec![ContentDescriptor {
+            name: "params".to_string(),
+            required: true,
+            schema: schema.clone(),
+        }],
+    }
+}
@@ -0,0 +1,371 @@
+use crate::ctx::Ctx;
+use crate::model::ModelManager;
+use crate::web::rpc::openrpc::OpenRpcDoc;
+use crate::web::{Error, Result};
+use futures::future::BoxFuture;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Value, from_value, to_value};
+use std::collections::BTreeMap;
+use std::future::Future;
+use utoipa::ToSchema;
+use utoipa::openapi::RefOr;
+use utoipa::openapi::schema::Schema;
+
+/// Reserved method returning the OpenRPC document of all the registered methods.
+pub const RPC_DISCOVER: &str = "rpc.discover";
+
+// region:    --- RpcHandler
+
+/// Handler once the params and result types have been erased.
+type RpcHandlerFn = Box<
+    dyn Fn(Ctx, ModelManager, Option<Value>) -> BoxFuture<'static, Result<Value>> + Send + Sync,
+>;
+
+/// Implemented for every `async fn(Ctx, ModelManager, P) -> Result<R>`
+/// and `async fn(Ctx, ModelManager) -> Result<R>`, so they can be registered as [RpcMethod].
+///
+/// `T` is only a marker to allow both signatures to coexist.
+pub trait RpcHandler<T>: Clone + Send + Sync + 'static {
+    fn call(
+        self,
+        rpc_method: &'static str,
+        ctx: Ctx,
+        mm: ModelManager,
+        params: Option<Value>,
+    ) -> BoxFuture<'static, Result<Value>>;
+
+    /// Schema of the params, `None` when the handler does not take any.
+    fn params_schema() -> Option<RefOr<Schema>>;
+
+    fn result_schema() -> RefOr<Schema>;
+
+    /// Collect the schemas referenced by the params and result types.
+    fn schemas(schemas: &mut Vec<(String, RefOr<Schema>)>);
+}
+
+impl<F, Fut, R> RpcHandler<()> for F
+where
+    F: FnOnce(Ctx, ModelManager) -> Fut + Clone + Send + Sync + 'static,
+    Fut: Future<Output = Result<R>> + Send + 'static,
+    R: Serialize + ToSchema,
+{
+    fn call(
+        self,
+        _rpc_method: &'static str,
+        ctx: Ctx,
+        mm: ModelManager,
+        _params: Option<Value>,
+    ) -> BoxFuture<'static, Result<Value>> {
+        Box::pin(async move {
+            let result = self(ctx, mm).await?;
+            Ok::<_, Error>(to_value(result)?)
+        })
+    }
+
+    fn params_schema() -> Option<RefOr<Schema>> {
+        None
+    }
+
+    fn result_schema() -> RefOr<Schema> {
+        R::schema()
+    }
+
+    fn schemas(schemas: &mut Vec<(String, RefOr<Schema>)>) {
+        R::schemas(schemas);
+    }
+}
+
+impl<F, Fut, P, R> RpcHandler<(P,)> for F
+where
+    F: FnOnce(Ctx, ModelManager, P) -> Fut + Clone + Send + Sync + 'static,
+    Fut: Future<Output = Result<R>> + Send + 'static,
+    P: DeserializeOwned + ToSchema + Send + 'static,
+    R: Serialize + ToSchema,
+{
+    fn call(
+        self,
+        rpc_method: &'static str,
+        ctx: Ctx,
+        mm: ModelManager,
+        params: Option<Value>,
+    ) -> BoxFuture<'static, Result<Value>> {
+        Box::pin(async move {
+            let params = deserialize_params::<P>(rpc_method, params)?;
+            let result = self(ctx, mm, params).await?;
+            Ok::<_, Error>(to_value(result)?)
+        })
+    }
+
+    fn params_schema() -> Option<RefOr<Schema>> {
+        Some(P::schema())
+    }
+
+    fn result_schema() -> RefOr<Schema> {
+        R::schema()
+    }
+
+    fn schemas(schemas: &mut Vec<(String, RefOr<Schema>)>) {
+        P::schemas(schemas);
+        R::schemas(schemas);
+    }
+}
+
+/// Params can only be omitted when `P` accepts a null value (`Option<_>` for example).
+fn deserialize_params<P>(rpc_method: &str, params: Option<Value>) -> Result<P>
+where
+    P: DeserializeOwned,
+{
+    match params {
+        Some(params) => from_value(params).map_err(|_| Error::RpcFailJsonParams {
+            rpc_method: rpc_method.to_string(),
+        }),
+        None => from_value(Value::Null).map_err(|_| Error::RpcMissingParams {
+            rpc_method: rpc_method.to_string(),
+        }),
+    }
+}
+
+// endregion: --- RpcHandler
+
+// region:    --- RpcMethod
+
+/// Metadata of a registered method, exposed through `rpc.discover`.
+#[derive(Debug, Clone, Serialize)]
+pub struct RpcMethodMeta {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Permission the caller must hold to call the method.
+    pub permission: Option<&'static str>,
+}
+
+/// Schemas of a registered method, used to generate the OpenRPC document.
+pub struct RpcMethodSchemas {
+    pub params: Option<RefOr<Schema>>,
+    pub result: RefOr<Schema>,
+    components: fn(&mut Vec<(String, RefOr<Schema>)>),
+}
+
+impl RpcMethodSchemas {
+    /// Push all the referenced schemas in `components`.
+    pub fn collect_components(&self, components: &mut Vec<(String, RefOr<Schema>)>) {
+        (self.components)(components)
+    }
+}
+
+/// A named handler with its metadata, built with the builder methods.
+pub struct RpcMethod {
+    meta: RpcMethodMeta,
+    schemas: RpcMethodSchemas,
+    handler: RpcHandlerFn,
+}
+
+impl RpcMethod {
+    pub fn new<H, T>(name: &'static str, handler: H) -> Self
+    where
+        H: RpcHandler<T>,
+    {
+        let handler: RpcHandlerFn =
+            Box::new(move |ctx: Ctx, mm: ModelManager, params: Option<Value>| {
+                handler.clone().call(name, ctx, mm, params)
+            });
+
+        Self {
+            meta: RpcMethodMeta {
+                name,
+                description: "",
+                permission: None,
+            },
+            schemas: RpcMethodSchemas {
+                params: H::params_schema(),
+                result: H::result_schema(),
+                components: H::schemas,
+            },
+            handler,
+        }
+    }
+
+    /// Sets the human readable description of the method.
+    pub fn description(mut self, description: &'static str) -> Self {
+        self.meta.description = description;
+        self
+    }
+
+    /// Sets the permission required to call the method.
+    pub fn permission(mut self, permission: &'static str) -> Self {
+        self.meta.permission = Some(permission);
+        self
+    }
+}
+
+// endregion: --- RpcMethod
+
+// region:    --- RpcRouter
+
+/// Registry of all the rpc methods, each domain module exposes its own router
+/// and they are merged together with [RpcRouter::merge].
+#[derive(Default)]
+pub struct RpcRouter {
+    methods: BTreeMap<&'static str, RpcMethod>,
+}
+
+impl RpcRouter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a method.
+    ///
+    /// # Panics
+    /// Panics if a method with the same name is already registered.
+    pub fn add(mut self, method: RpcMethod) -> Self {
+        let name = method.meta.name;
+        if self.methods.insert(name, method).is_some() {
+            panic!("RpcRouter - method `{name}` registered twice");
+        }
+        self
+    }
+
+    /// Register all the methods of another router.
+    pub fn merge(self, other: RpcRouter) -> Self {
+        other.methods.into_values().fold(self, RpcRouter::add)
+    }
+
+    /// Metadata of all the registered methods, sorted by name.
+    pub fn methods(&self) -> impl Iterator<Item = &RpcMethodMeta> {
+        self.methods.values().map(|method| &method.meta)
+    }
+
+    /// Metadata and schemas of all the registered methods, sorted by name.
+    pub fn methods_with_schemas(
+        &self,
+    ) -> impl Iterator<Item = (&RpcMethodMeta, &RpcMethodSchemas)> {
+        self.methods
+            .values()
+            .map(|method| (&method.meta, &method.schemas))
+    }
+
+    /// OpenRPC document describing all the registered methods.
+    pub fn openrpc(&self) -> OpenRpcDoc {
+        OpenRpcDoc::from_router(self)
+    }
+
+    /// Dispatch the call to the registered method.
+    pub async fn call(
+        &self,
+        ctx: Ctx,
+        mm: ModelManager,
+        rpc_method: &str,
+        params: Option<Value>,
+    ) -> Result<Value> {
+        if rpc_method == RPC_DISCOVER {
+            return Ok(to_value(self.openrpc())?);
+        }
+
+        let method = self
+            .methods
+            .get(rpc_method)
+            .ok_or_else(|| Error::RpcMethodUnknown(rpc_method.to_string()))?;
+
+        // -- Check the permission declared by the method.
+        if let Some(permission) = method.meta.permission {
+            if !ctx.has_permission(permission) {
+                return Err(Error::PermissionDenied {
+                    user_id: ctx.user_id(),
+                    permission,
+                });
+            }
+        }
+
+        (method.handler)(ctx, mm, params).await
+    }
+}
+
+// endregion: --- RpcRouter
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Deserialize, ToSchema)]
+    struct ParamsEcho {
+        value: i64,
+    }
+
+    async fn echo(_ctx: Ctx, _mm: ModelManager, params: ParamsEcho) -> Result<i64> {
+        Ok(params.value)
+    }
+
+    async fn ping(_ctx: Ctx, _mm: ModelManager) -> Result<&'static str> {
+        Ok("pong")
+    }
+
+    #[test]
+    fn test_rpc_router_methods_ok() {
+        // -- Setup & Fixtures
+        let router = RpcRouter::new()
+            .add(RpcMethod::new("ping", ping).description("Ping"))
+            .merge(RpcRouter::new().add(RpcMethod::new("echo", echo).permission("echo:read")));
+
+        // -- Exec
+        let names: Vec<&str> = router.methods().map(|meta| meta.name).collect();
+
+        // -- Check
+        assert_eq!(names, vec!["echo", "ping"]);
+    }
+
+    #[test]
+    fn test_rpc_router_openrpc_ok() -> anyhow::Result<()> {
+        // -- Setup & Fixtures
+        let router = RpcRouter::new()
+            .add(RpcMethod::new("ping", ping))
+            .add(RpcMethod::new("echo", echo).description("Echo the value"));
+
+        // -- Exec
+        let doc = to_value(router.openrpc())?;
+
+        // -- Check
+        let methods = doc["methods"]
+            .as_array()
+            .expect("methods should be an array");
+        assert_eq!(methods.len(), 2);
+        assert_eq!(methods[0]["name"], "echo");
+        assert_eq!(methods[0]["params"][0]["name"], "value");
+        assert_eq!(methods[0]["params"][0]["required"], true);
+        assert_eq!(methods[1]["params"].as_array().map(Vec::len), Some(0));
+
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_rpc_router_add_err_duplicate() {
+        let _ = RpcRouter::new()
+            .add(RpcMethod::new("ping", ping))
+            .add(RpcMethod::new("ping", ping));
+    }
+
+    #[test]
+    fn test_deserialize_params_err_missing() {
+        // -- Exec
+        let res = deserialize_params::<ParamsEcho>("echo", None);
+
+        // -- Check
+        assert!(
+            matches!(res, Err(Error::RpcMissingParams { .. })),
+            "Should have matched `Err(Error::RpcMissingParams)`"
+        );
+    }
+
+    #[test]
+    fn test_deserialize_params_ok_optional() {
+        // -- Exec
+        let res = deserialize_params::<Option<i64>>("echo", None);
+
+        // -- Check
+        assert!(matches!(res, Ok(None)));
+    }
+}
+// endregion: --- Tests
@@ -0,0 +1,116 @@
+use crate::web::{ClientError, Error};
+use serde::Serialize;
+use serde_json::{Value, json, to_value};
+
+// region:    --- Error Codes
+// Pre-defined error codes from the JSON-RPC 2.0 specification.
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+
+// Implementation-defined server errors (reserved range -32000 to -32099).
+pub const SERVER_ERROR: i64 = -32000;
+// endregion: --- Error Codes
+
+/// JSON-RPC 2.0 error object, sent back in the `error` member of the response.
+#[derive(Debug, Serialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl From<&Error> for RpcError {
+    fn from(error: &Error) -> Self {
+        match error {
+            Error::RpcParseFail => RpcError {
+                code: PARSE_ERROR,
+                message: "Parse error".to_string(),
+                data: None,
+            },
+            Error::RpcInvalidRequest => RpcError {
+                code: INVALID_REQUEST,
+                message: "Invalid Request".to_string(),
+                data: None,
+            },
+            Error::RpcBatchTooLarge { max, .. } => RpcError {
+                code: INVALID_REQUEST,
+                message: "Invalid Request".to_string(),
+                data: Some(json!({ "max_batch_size": max })),
+            },
+            Error::RpcMethodUnknown(rpc_method) => RpcError {
+                code: METHOD_NOT_FOUND,
+                message: "Method not found".to_string(),
+                data: Some(json!({ "method": rpc_method })),
+            },
+            Error::RpcMissingParams { rpc_method } | Error::RpcFailJsonParams { rpc_method } => {
+                RpcError {
+                    code: INVALID_PARAMS,
+                    message: "Invalid params".to_string(),
+                    data: Some(json!({ "method": rpc_method })),
+                }
+            }
+            // -- Application errors, go through the same client error mapping as the rest api.
+            _ => {
+                let (_, client_error) = error.client_status_and_error();
+                let code = match client_error {
+                    ClientError::SERVICE_ERROR => INTERNAL_ERROR,
+                    _ => SERVER_ERROR,
+                };
+                RpcError {
+                    code,
+                    message: client_error.to_string(),
+                    data: to_value(&client_error).ok(),
+                }
+            }
+        }
+    }
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rpc_error_from_ok_spec_codes() {
+        // -- Fixtures
+        let fx_cases = [
+            (Error::RpcParseFail, PARSE_ERROR),
+            (Error::RpcInvalidRequest, INVALID_REQUEST),
+            (
+                Error::RpcBatchTooLarge { max: 1, actual: 2 },
+                INVALID_REQUEST,
+            ),
+            (
+                Error::RpcMethodUnknown("unknown".to_string()),
+                METHOD_NOT_FOUND,
+            ),
+            (
+                Error::RpcMissingParams {
+                    rpc_method: "create_task".to_string(),
+                },
+                INVALID_PARAMS,
+            ),
+            (
+                Error::RpcFailJsonParams {
+                    rpc_method: "create_task".to_string(),
+                },
+                INVALID_PARAMS,
+            ),
+        ];
+
+        // -- Exec & Check
+        for (error, code) in fx_cases {
+            assert_eq!(
+                RpcError::from(&error).code,
+                code,
+                "wrong code for {error:?}"
+            );
+        }
+    }
+}
+// endregion: --- Tests
@@ -1,8 +1,50 @@
 use crate::ctx::Ctx;
 use crate::model::ModelManager;
-use crate::model::task::{Task, TaskBmc, TaskForCreate, TaskForUpdate};
+use crate::model::list::ListPage;
+use crate::model::task::{Task, TaskBmc, TaskFilter, TaskForCreate, TaskForUpdate};
 use crate::web::Result;
-use crate::web::rpc::{ParamsForCreate, ParamsForUpdate, ParamsIded};
+use crate::web::rpc::{
+    ParamsForCreate, ParamsForUpdate, ParamsIded, ParamsList, RpcMethod, RpcRouter,
+};
+
+pub fn rpc_router() -> RpcRouter {
+    RpcRouter::new()
+        .add(
+            RpcMethod::new("create_task", create_task)
+                .description("Create a new task")
+                .permission("task:write"),
+        )
+        .add(
+            RpcMethod::new("list_tasks", list_tasks)
+                .description("List the tasks matching the filters")
+                .permission("task:read"),
+        )
+        .add(
+            RpcMethod::new("list_tasks_page", list_tasks_page)
+                .description("List a page of the tasks matching the filters, with the cursor of the next page")
+                .permission("task:read"),
+        )
+        .add(
+            RpcMethod::new("update_task", update_task)
+                .description("Update the given fields of a task")
+                .permission("task:write"),
+        )
+        .add(
+            RpcMethod::new("delete_task", delete_task)
+                .description("Move a task to the trash and return it")
+                .permission("task:write"),
+        )
+        .add(
+            RpcMethod::new("restore_task", restore_task)
+                .description("Restore a task from the trash and return it")
+                .permission("task:write"),
+        )
+        .add(
+            RpcMethod::new("list_deleted_tasks", list_deleted_tasks)
+                .description("List a page of the tasks in the trash, with the cursor of the next page")
+                .permission("task:read"),
+        )
+}
 
 pub async fn create_task(
     ctx: Ctx,
@@ -17,20 +59,45 @@ pub async fn create_task(
     Ok(task)
 }
 
-pub async fn list_tasks(ctx: Ctx, mm: ModelManager) -> Result<Vec<Task>> {
-    let tasks = TaskBmc::list(&ctx, &mm).await?;
+pub async fn list_tasks(
+    ctx: Ctx,
+    mm: ModelManager,
+    params: Option<ParamsList<TaskFilter>>,
+) -> Result<Vec<Task>> {
+    let (filters, list_options) = params
+        .map(|params| (params.filters, params.list_options))
+        .unwrap_or_default();
+
+    let tasks = TaskBmc::list(&ctx, &mm, filters, list_options).await?;
 
     Ok(tasks)
 }
 
+pub async fn list_tasks_page(
+    ctx: Ctx,
+    mm: ModelManager,
+    params: Option<ParamsList<TaskFilter>>,
+) -> Result<ListPage<Task>> {
+    let (filters, list_options) = params
+        .map(|params| (params.filters, params.list_options))
+        .unwrap_or_default();
+
+    let page = TaskBmc::list_page(&ctx, &mm, filters, list_options).await?;
+
+    Ok(page)
+}
+
 pub async fn update_task(
     ctx: Ctx,
     mm: ModelManager,
     params: ParamsForUpdate<TaskForUpdate>,
 ) -> Result<Task> {
-    let ParamsForUpdate { id, data } = params;
+    let ParamsForUpdate { id, version, data } = params;
 
-    TaskBmc::update(&ctx, &mm, id, data).await?;
+    match version {
+        Some(version) => TaskBmc::update_if_version(&ctx, &mm, id, version, data).await?,
+        None => TaskBmc::update(&ctx, &mm, id, data).await?,
+    }
 
     let task = TaskBmc::get(&ctx, &mm, id).await?;
 
@@ -40,8 +107,31 @@ pub async fn update_task(
 pub async fn delete_task(ctx: Ctx, mm: ModelManager, params: ParamsIded) -> Result<Task> {
     let ParamsIded { id } = params;
 
-    let task = TaskBmc::get(&ctx, &mm, id).await?;
     TaskBmc::delete(&ctx, &mm, id).await?;
+    let task = TaskBmc::get_deleted(&ctx, &mm, id).await?;
 
     Ok(task)
 }
+
+pub async fn restore_task(ctx: Ctx, mm: ModelManager, params: ParamsIded) -> Result<Task> {
+    let ParamsIded { id } = params;
+
+    TaskBmc::restore(&ctx, &mm, id).await?;
+    let task = TaskBmc::get(&ctx, &mm, id).await?;
+
+    Ok(task)
+}
+
+pub async fn list_deleted_tasks(
+    ctx: Ctx,
+    mm: ModelManager,
+    params: Option<ParamsList<TaskFilter>>,
+) -> Result<ListPage<Task>> {
+    let (filters, list_options) = params
+        .map(|params| (params.filters, params.list_options))
+        .unwrap_or_default();
+
+    let page = TaskBmc::list_deleted_page(&ctx, &mm, filters, list_options).await?;
+
+    Ok(page)
+}
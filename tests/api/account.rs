@@ -1,6 +1,9 @@
-use crate::helpers::spawn_app;
+use crate::helpers::{TEST_PWD, TestApp, spawn_app, spawn_app_with};
+use axum_demo::crypt::totp;
 use serde::Serialize;
 use serde_json::to_value;
+use time::OffsetDateTime;
+use uuid::Uuid;
 
 // Struct
 #[derive(Serialize)]
@@ -9,6 +12,18 @@ struct LoginPayload {
     pwd: String,
 }
 
+#[derive(Serialize)]
+struct RegisterPayload {
+    username: String,
+    email: Option<String>,
+    pwd: String,
+}
+
+#[derive(Serialize)]
+struct PwdResetRequestPayload {
+    email: String,
+}
+
 #[derive(Serialize)]
 struct LogoffPayload {
     logoff: bool,
@@ -63,3 +78,645 @@ async fn logoff_fails() {
     // Assert
     assert_eq!(response.status(), 404, "Status code should be 404");
 }
+
+#[tokio::test]
+async fn register_works() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = RegisterPayload {
+        username: String::from("new_user"),
+        email: Some(String::from("new_user@example.com")),
+        pwd: String::from("Welcome2Demo!"),
+    };
+    let json = to_value(body).expect("Failed to serialize body");
+
+    // Act
+    let response = app.post_register(json).await;
+
+    // Assert
+    assert_eq!(response.status(), 201, "Status code should be 201");
+}
+
+#[tokio::test]
+async fn register_fails_with_taken_username() {
+    // Arrange
+    let app = spawn_app().await;
+    let username = app.seed_user().await;
+    let body = RegisterPayload {
+        username,
+        email: None,
+        pwd: String::from("Welcome2Demo!"),
+    };
+    let json = to_value(body).expect("Failed to serialize body");
+
+    // Act
+    let response = app.post_register(json).await;
+
+    // Assert
+    assert_eq!(response.status(), 409, "Status code should be 409");
+    assert_eq!(
+        response
+            .headers()
+            .get("Content-Type")
+            .and_then(|value| value.to_str().ok()),
+        Some("application/problem+json")
+    );
+}
+
+#[tokio::test]
+async fn register_fails_with_weak_pwd() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = RegisterPayload {
+        username: String::from("new_user"),
+        email: None,
+        pwd: String::from("weak"),
+    };
+    let json = to_value(body).expect("Failed to serialize body");
+
+    // Act
+    let response = app.post_register(json).await;
+
+    // Assert
+    assert_eq!(response.status(), 400, "Status code should be 400");
+}
+
+#[tokio::test]
+async fn pwd_reset_is_accepted_for_unknown_email() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = PwdResetRequestPayload {
+        email: String::from("unknown@example.com"),
+    };
+    let json = to_value(body).expect("Failed to serialize body");
+
+    // Act
+    let response = app.post_pwd_reset(json).await;
+
+    // Assert
+    assert_eq!(response.status(), 202, "Status code should be 202");
+}
+
+#[tokio::test]
+async fn login_fails_the_same_way_for_unknown_username_and_wrong_pwd() {
+    // Arrange
+    let app = spawn_app().await;
+    let register = RegisterPayload {
+        username: String::from("known_user"),
+        email: None,
+        pwd: String::from("Welcome2Demo!"),
+    };
+    let response = app
+        .post_register(to_value(register).expect("Failed to serialize body"))
+        .await;
+    assert_eq!(response.status(), 201, "Status code should be 201");
+
+    let unknown_username = LoginPayload {
+        username: String::from("unknown_user"),
+        pwd: String::from("Welcome2Demo!"),
+    };
+    let wrong_pwd = LoginPayload {
+        username: String::from("known_user"),
+        pwd: String::from("Welcome2Moon!"),
+    };
+
+    // Act
+    let unknown_username = app
+        .post_account_login(to_value(unknown_username).expect("Failed to serialize body"))
+        .await;
+    let wrong_pwd = app
+        .post_account_login(to_value(wrong_pwd).expect("Failed to serialize body"))
+        .await;
+
+    // Assert
+    assert_eq!(unknown_username.status(), 403, "Status code should be 403");
+    assert_eq!(wrong_pwd.status(), 403, "Status code should be 403");
+    let unknown_username: serde_json::Value =
+        unknown_username.json().await.expect("Failed to read body");
+    let wrong_pwd: serde_json::Value = wrong_pwd.json().await.expect("Failed to read body");
+    assert_eq!(unknown_username["title"], wrong_pwd["title"]);
+    assert_eq!(unknown_username["detail"], wrong_pwd["detail"]);
+}
+
+#[tokio::test]
+async fn jwks_is_published() {
+    // Arrange
+    let app = spawn_app().await;
+    let client = reqwest::Client::new();
+
+    // Act
+    let response = client
+        .get(&format!("{}/.well-known/jwks.json", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status(), 200, "Status code should be 200");
+    let jwks: serde_json::Value = response.json().await.expect("Failed to read body");
+    assert!(jwks["keys"].is_array(), "Should have a `keys` array");
+}
+
+#[tokio::test]
+async fn refresh_rotates_the_refresh_token() {
+    // Arrange
+    let app = spawn_app().await;
+    app.register_user("refresh_user").await;
+    let body = app.token_for("refresh_user").await;
+    let refresh_token = body["refresh_token"]
+        .as_str()
+        .expect("Should have a refresh token")
+        .to_string();
+
+    // Act
+    let response = app
+        .post_account_refresh(serde_json::json!({ "refresh_token": refresh_token }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status(), 200, "Status code should be 200");
+    let body: serde_json::Value = response.json().await.expect("Failed to read body");
+    assert!(
+        body["access_token"].is_string(),
+        "Should have an access token"
+    );
+    let new_refresh_token = body["refresh_token"]
+        .as_str()
+        .expect("Should have a refresh token");
+    assert_ne!(new_refresh_token, refresh_token, "Should have been rotated");
+}
+
+#[tokio::test]
+async fn refresh_reuse_revokes_the_session() {
+    // Arrange
+    let app = spawn_app().await;
+    app.register_user("reuse_user").await;
+    let body = app.token_for("reuse_user").await;
+    let refresh_token = body["refresh_token"].clone();
+    let response = app
+        .post_account_refresh(serde_json::json!({ "refresh_token": refresh_token }))
+        .await;
+    assert_eq!(response.status(), 200, "Status code should be 200");
+    let body: serde_json::Value = response.json().await.expect("Failed to read body");
+    let new_refresh_token = body["refresh_token"].clone();
+
+    // Act
+    let reused = app
+        .post_account_refresh(serde_json::json!({ "refresh_token": refresh_token }))
+        .await;
+    let revoked = app
+        .post_account_refresh(serde_json::json!({ "refresh_token": new_refresh_token }))
+        .await;
+
+    // Assert
+    assert_eq!(reused.status(), 403, "Status code should be 403");
+    assert_eq!(revoked.status(), 403, "Status code should be 403");
+}
+
+#[tokio::test]
+async fn logoff_revokes_the_token() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.register_and_login("logoff_user").await;
+    let client = reqwest::Client::new();
+    let response = client
+        .get(&format!("{}/api/tasks", &app.address))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), 200, "Status code should be 200");
+
+    // Act
+    let response = client
+        .post(&format!("{}/account/logoff", &app.address))
+        .bearer_auth(&token)
+        .json(&LogoffPayload { logoff: true })
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), 200, "Status code should be 200");
+    let replayed = client
+        .get(&format!("{}/api/tasks", &app.address))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(replayed.status(), 403, "Status code should be 403");
+}
+
+#[tokio::test]
+async fn login_requires_the_2fa_code_once_enabled() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.register_and_login("mfa_user").await;
+    let client = reqwest::Client::new();
+    let response = client
+        .post(&format!("{}/account/2fa/setup", &app.address))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), 200, "Status code should be 200");
+    let body: serde_json::Value = response.json().await.expect("Failed to read body");
+    let secret = body["secret"].as_str().expect("Should have a secret");
+    assert!(
+        body["otpauth_uri"]
+            .as_str()
+            .is_some_and(|uri| uri.starts_with("otpauth://totp/")),
+        "Should have an otpauth uri"
+    );
+    let code = totp::generate_code(secret, OffsetDateTime::now_utc()).expect("Failed to gen code");
+    let response = client
+        .post(&format!("{}/account/2fa/enable", &app.address))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "pwd_current": TEST_PWD, "code": code }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), 200, "Status code should be 200");
+    let body: serde_json::Value = response.json().await.expect("Failed to read body");
+    let recovery_code = body["recovery_codes"][0]
+        .as_str()
+        .expect("Should have recovery codes")
+        .to_string();
+
+    // Act
+    let response = app
+        .post_account_token(serde_json::json!({ "username": "mfa_user", "pwd": TEST_PWD }))
+        .await;
+    assert_eq!(response.status(), 202, "Status code should be 202");
+    let body: serde_json::Value = response.json().await.expect("Failed to read body");
+    assert!(
+        body["access_token"].is_null(),
+        "Should not have a token yet"
+    );
+    let verify_payload = serde_json::json!({
+        "mfa_token": body["mfa_token"].as_str().expect("Should have an mfa token"),
+        "recovery_code": recovery_code,
+    });
+    let verified = client
+        .post(&format!("{}/account/2fa/verify", &app.address))
+        .json(&verify_payload)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let replayed = client
+        .post(&format!("{}/account/2fa/verify", &app.address))
+        .json(&verify_payload)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(verified.status(), 200, "Status code should be 200");
+    let body: serde_json::Value = verified.json().await.expect("Failed to read body");
+    assert!(
+        body["access_token"].is_string(),
+        "Should have an access token"
+    );
+    assert_eq!(replayed.status(), 403, "Status code should be 403");
+}
+
+/// Secret of a new 2fa setup of the user of `token`.
+async fn setup_2fa(app: &TestApp, token: &str) -> String {
+    let response = reqwest::Client::new()
+        .post(&format!("{}/account/2fa/setup", &app.address))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), 200, "Status code should be 200");
+    let body: serde_json::Value = response.json().await.expect("Failed to read body");
+    body["secret"]
+        .as_str()
+        .expect("Should have a secret")
+        .to_string()
+}
+
+async fn post_2fa(
+    app: &TestApp,
+    token: &str,
+    action: &str,
+    body: serde_json::Value,
+) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(&format!("{}/account/2fa/{action}", &app.address))
+        .bearer_auth(token)
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn enable_2fa_requires_the_current_pwd() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.register_and_login("mfa_pwd_user").await;
+    let secret = setup_2fa(&app, &token).await;
+    let code = totp::generate_code(&secret, OffsetDateTime::now_utc()).expect("Failed to gen code");
+
+    // Act
+    let response = post_2fa(
+        &app,
+        &token,
+        "enable",
+        serde_json::json!({ "pwd_current": "Welcome2Moon!", "code": code }),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status(), 403, "Status code should be 403");
+    let response = app
+        .post_account_token(serde_json::json!({ "username": "mfa_pwd_user", "pwd": TEST_PWD }))
+        .await;
+    assert_eq!(response.status(), 200, "Should still log in without a code");
+}
+
+#[tokio::test]
+async fn disable_2fa_requires_a_valid_code() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.register_and_login("mfa_off_user").await;
+    let secret = setup_2fa(&app, &token).await;
+    let code = totp::generate_code(&secret, OffsetDateTime::now_utc()).expect("Failed to gen code");
+    let response = post_2fa(
+        &app,
+        &token,
+        "enable",
+        serde_json::json!({ "pwd_current": TEST_PWD, "code": code }),
+    )
+    .await;
+    assert_eq!(response.status(), 200, "Status code should be 200");
+    let body: serde_json::Value = response.json().await.expect("Failed to read body");
+    let recovery_code = body["recovery_codes"][0]
+        .as_str()
+        .expect("Should have recovery codes")
+        .to_string();
+
+    // Act
+    let rejected = post_2fa(
+        &app,
+        &token,
+        "disable",
+        serde_json::json!({ "recovery_code": "0000-0000-0000-0000" }),
+    )
+    .await;
+    let disabled = post_2fa(
+        &app,
+        &token,
+        "disable",
+        serde_json::json!({ "recovery_code": recovery_code }),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(rejected.status(), 403, "Status code should be 403");
+    assert_eq!(disabled.status(), 204, "Status code should be 204");
+    let response = app
+        .post_account_token(serde_json::json!({ "username": "mfa_off_user", "pwd": TEST_PWD }))
+        .await;
+    assert_eq!(response.status(), 200, "Should log in without a code");
+}
+
+#[tokio::test]
+async fn login_is_locked_after_too_many_failures() {
+    // Arrange
+    let app = spawn_app().await;
+    app.register_user("locked_user").await;
+    let wrong_pwd = serde_json::json!({
+        "username": "locked_user",
+        "pwd": "Welcome2Moon!",
+    });
+    // Default lockout threshold.
+    for _ in 0..5 {
+        let response = app.post_account_login(wrong_pwd.clone()).await;
+        assert_eq!(response.status(), 403, "Status code should be 403");
+    }
+
+    // Act
+    let response = app
+        .post_account_login(serde_json::json!({ "username": "locked_user", "pwd": TEST_PWD }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status(), 429, "Status code should be 429");
+    let retry_after: i64 = response
+        .headers()
+        .get("Retry-After")
+        .and_then(|retry_after| retry_after.to_str().ok())
+        .and_then(|retry_after| retry_after.parse().ok())
+        .expect("Should have a Retry-After header");
+    assert!(retry_after > 0, "Should have to wait");
+}
+
+#[tokio::test]
+async fn forgotten_login_failures_are_purged() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.auth.lockout_window_sec = 0;
+        c.auth.lockout_purge_interval_sec = 1;
+    })
+    .await;
+    app.register_user("purged_failure_user").await;
+    let response = app
+        .post_account_login(serde_json::json!({
+            "username": "purged_failure_user",
+            "pwd": "Welcome2Moon!",
+        }))
+        .await;
+    assert_eq!(response.status(), 403, "Status code should be 403");
+
+    // Act
+    tokio::time::sleep(std::time::Duration::from_millis(2500)).await;
+
+    // Assert
+    let remaining: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM login_throttle")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to count the failures.");
+    assert_eq!(remaining, 0, "Should be purged");
+}
+
+#[tokio::test]
+async fn pwd_reset_matches_the_email_case_insensitively() {
+    // Arrange
+    let out_dir = std::env::temp_dir().join(format!("mails-{}", Uuid::new_v4()));
+    let mailer_out_dir = out_dir.display().to_string();
+    let app = spawn_app_with(|c| c.mailer.out_dir = Some(mailer_out_dir)).await;
+    let response = app
+        .post_register(serde_json::json!({
+            "username": "case_user",
+            "email": "Case.User@Example.com",
+            "pwd": TEST_PWD,
+        }))
+        .await;
+    assert_eq!(response.status(), 201, "Status code should be 201");
+
+    // Act
+    let response = app
+        .post_pwd_reset(serde_json::json!({ "email": "case.user@example.com" }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status(), 202, "Status code should be 202");
+    let mail = wait_for_mail(&out_dir).await;
+    assert!(
+        mail.contains("following token"),
+        "Should have sent the reset"
+    );
+}
+
+/// Content of the first mail written in `out_dir`, the reset mails are sent in the background.
+async fn wait_for_mail(out_dir: &std::path::Path) -> String {
+    for _ in 0..50 {
+        let mail = std::fs::read_dir(out_dir)
+            .ok()
+            .and_then(|mut entries| entries.next())
+            .and_then(|entry| std::fs::read_to_string(entry.ok()?.path()).ok());
+        if let Some(mail) = mail {
+            return mail;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    panic!("Should have received a mail in {}", out_dir.display());
+}
+
+#[tokio::test]
+async fn pwd_reset_sets_the_new_pwd_once() {
+    // Arrange
+    let out_dir = std::env::temp_dir().join(format!("mails-{}", Uuid::new_v4()));
+    let mailer_out_dir = out_dir.display().to_string();
+    let app = spawn_app_with(|c| c.mailer.out_dir = Some(mailer_out_dir)).await;
+    let response = app
+        .post_register(serde_json::json!({
+            "username": "reset_user",
+            "email": "reset_user@example.com",
+            "pwd": TEST_PWD,
+        }))
+        .await;
+    assert_eq!(response.status(), 201, "Status code should be 201");
+    let response = app
+        .post_pwd_reset(serde_json::json!({ "email": "reset_user@example.com" }))
+        .await;
+    assert_eq!(response.status(), 202, "Status code should be 202");
+    let mail = wait_for_mail(&out_dir).await;
+    let token = mail
+        .lines()
+        .skip_while(|line| !line.contains("following token"))
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.contains("following token"))
+        .expect("Should have a token in the mail")
+        .to_string();
+    let client = reqwest::Client::new();
+    let confirm_payload = serde_json::json!({ "token": token, "pwd_new": "Welcome2Reset!" });
+
+    // Act
+    let confirmed = client
+        .post(&format!("{}/account/password/reset/confirm", &app.address))
+        .json(&confirm_payload)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let replayed = client
+        .post(&format!("{}/account/password/reset/confirm", &app.address))
+        .json(&confirm_payload)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(confirmed.status(), 204, "Status code should be 204");
+    assert_eq!(replayed.status(), 400, "Status code should be 400");
+    let old_pwd = app
+        .post_account_token(serde_json::json!({ "username": "reset_user", "pwd": TEST_PWD }))
+        .await;
+    assert_eq!(old_pwd.status(), 403, "Status code should be 403");
+    let new_pwd = app
+        .post_account_token(
+            serde_json::json!({ "username": "reset_user", "pwd": "Welcome2Reset!" }),
+        )
+        .await;
+    assert_eq!(new_pwd.status(), 200, "Status code should be 200");
+
+    // Clean
+    std::fs::remove_dir_all(&out_dir).expect("Failed to remove the mails");
+}
+
+#[tokio::test]
+async fn change_pwd_requires_the_current_pwd() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.register_and_login("change_pwd_user").await;
+    let client = reqwest::Client::new();
+
+    // Act
+    let wrong_current = client
+        .post(&format!("{}/account/password", &app.address))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "pwd_current": "Welcome2Moon!", "pwd_new": "Welcome2Change!" }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let changed = client
+        .post(&format!("{}/account/password", &app.address))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "pwd_current": TEST_PWD, "pwd_new": "Welcome2Change!" }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(wrong_current.status(), 403, "Status code should be 403");
+    assert_eq!(changed.status(), 204, "Status code should be 204");
+    let old_pwd = app
+        .post_account_token(serde_json::json!({ "username": "change_pwd_user", "pwd": TEST_PWD }))
+        .await;
+    assert_eq!(old_pwd.status(), 403, "Status code should be 403");
+    let new_pwd = app
+        .post_account_token(
+            serde_json::json!({ "username": "change_pwd_user", "pwd": "Welcome2Change!" }),
+        )
+        .await;
+    assert_eq!(new_pwd.status(), 200, "Status code should be 200");
+}
+
+#[tokio::test]
+async fn logoff_everywhere_fails_with_an_api_key() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.register_and_login("logoff_key_user").await;
+    let client = reqwest::Client::new();
+    let response = client
+        .post(&format!("{}/account/api-keys", &app.address))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "name": "ci", "scopes": ["task:read"] }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), 201, "Status code should be 201");
+    let body: serde_json::Value = response.json().await.expect("Failed to read body");
+    let api_key = body["key"].as_str().expect("Should have a key").to_string();
+
+    // Act
+    let response = client
+        .post(&format!("{}/account/logoff", &app.address))
+        .header("X-API-Key", &api_key)
+        .json(&serde_json::json!({ "logoff": true, "everywhere": true }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status(), 403, "Status code should be 403");
+    let response = client
+        .get(&format!("{}/api/tasks", &app.address))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), 200, "Status code should be 200");
+}
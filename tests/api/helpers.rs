@@ -1,10 +1,13 @@
-use axum_demo::config::Postgres as PostgresConfig;
+use axum_demo::config::{Config, Postgres as PostgresConfig};
 use axum_demo::observability::ObservabilityGuard;
 use axum_demo::{config::get_configuration, startup::Application};
 use secrecy::ExposeSecret;
 use sqlx::{Connection, Executor, PgConnection, PgPool, postgres::PgConnectOptions};
 use uuid::Uuid;
 
+/// Password of the users registered by the helpers.
+pub const TEST_PWD: &str = "Welcome2Demo!";
+
 pub struct TestApp {
     pub address: String,
     pub db_pool: PgPool,
@@ -38,6 +41,56 @@ impl TestApp {
         username
     }
 
+    pub async fn post_register(&self, body: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(&format!("{}/account/register", &self.address))
+            .header("Content-Type", "application/json")
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_pwd_reset(&self, body: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(&format!("{}/account/password/reset", &self.address))
+            .header("Content-Type", "application/json")
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_account_login(&self, body: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(&format!("{}/account/login", &self.address))
+            .header("Content-Type", "application/json")
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_account_token(&self, body: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(&format!("{}/account/token", &self.address))
+            .header("Content-Type", "application/json")
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_account_refresh(&self, body: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(&format!("{}/account/refresh", &self.address))
+            .header("Content-Type", "application/json")
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_login(&self, body: serde_json::Value) -> reqwest::Response {
         reqwest::Client::new()
             .post(&format!("{}/login", &self.address))
@@ -48,6 +101,53 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    /// JSON-RPC call of `method`, authenticated with the bearer `token`.
+    pub async fn post_rpc(
+        &self,
+        token: &str,
+        method: &str,
+        params: serde_json::Value,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(&format!("{}/api/rpc", &self.address))
+            .bearer_auth(token)
+            .json(&serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": method,
+                "params": params,
+            }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Register `username` with [TEST_PWD].
+    pub async fn register_user(&self, username: &str) {
+        let response = self
+            .post_register(serde_json::json!({ "username": username, "pwd": TEST_PWD }))
+            .await;
+        assert_eq!(response.status(), 201, "Status code should be 201");
+    }
+
+    /// Body of `/account/token` for a user registered by the helpers.
+    pub async fn token_for(&self, username: &str) -> serde_json::Value {
+        let response = self
+            .post_account_token(serde_json::json!({ "username": username, "pwd": TEST_PWD }))
+            .await;
+        assert_eq!(response.status(), 200, "Status code should be 200");
+        response.json().await.expect("Failed to read body")
+    }
+
+    /// Register `username`, and return its access token.
+    pub async fn register_and_login(&self, username: &str) -> String {
+        self.register_user(username).await;
+        self.token_for(username).await["access_token"]
+            .as_str()
+            .expect("Should have a token")
+            .to_string()
+    }
+
     pub async fn post_logoff(&self, body: serde_json::Value) -> reqwest::Response {
         reqwest::Client::new()
             .post(&format!("{}/logoff", &self.address))
@@ -60,12 +160,18 @@ impl TestApp {
 }
 
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Same as [spawn_app], with the configuration changed by `configure` before the app is built.
+pub async fn spawn_app_with(configure: impl FnOnce(&mut Config)) -> TestApp {
     let configuration = {
         let mut c = get_configuration().expect("Failed to read configuration");
         c.postgres.db_name = Box::new(Uuid::new_v4().to_string()).into();
         c.application.port = 0;
         c.tracing.file_enabled = false;
         c.tracing.stdout_enabled = false;
+        configure(&mut c);
         c
     };
     //Create and migrate the database
@@ -1,3 +1,5 @@
 mod account;
 mod health_check;
 mod helpers;
+mod oidc;
+mod task;
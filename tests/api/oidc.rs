@@ -0,0 +1,412 @@
+use crate::helpers::{TEST_PWD, TestApp, spawn_app_with};
+use axum::extract::{Form, Query, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::Redirect;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use axum_demo::config::OidcProvider;
+use axum_demo::crypt::sha256_into_b64u;
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, OctetKeyPairParameters,
+    OctetKeyPairType,
+};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use ring::rand::SystemRandom;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+// region:    --- Mock Issuer
+
+const MOCK_PROVIDER: &str = "mock";
+const MOCK_CLIENT_ID: &str = "mock-client";
+const MOCK_CLIENT_SECRET: &str = "mock-secret";
+/// `mock-client:mock-secret`, base64 encoded.
+const MOCK_BASIC_AUTH: &str = "Basic bW9jay1jbGllbnQ6bW9jay1zZWNyZXQ=";
+const MOCK_KID: &str = "mock-key";
+/// Public url of the app, as behind a proxy. The callbacks are sent to the app by hand.
+const PUBLIC_BASE_URL: &str = "https://demo.example.com";
+
+/// Authorization request of a code, checked when the code is exchanged.
+struct MockAuthorization {
+    redirect_uri: String,
+    code_challenge: String,
+    nonce: String,
+}
+
+#[derive(Clone)]
+struct MockIssuer {
+    issuer: String,
+    /// Ed25519 PKCS#8 key signing the id tokens.
+    key_pkcs8: Arc<Vec<u8>>,
+    /// Subject of the user logging in at the issuer.
+    subject: String,
+    codes: Arc<Mutex<HashMap<String, MockAuthorization>>>,
+}
+
+/// In-process OpenID Connect issuer, listening on a random port.
+async fn spawn_mock_issuer(subject: &str) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Failed to bind the mock issuer");
+    let issuer = format!("http://127.0.0.1:{}", listener.local_addr().unwrap().port());
+    let key_pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+        .expect("Failed to generate the mock issuer key");
+
+    let state = MockIssuer {
+        issuer: issuer.clone(),
+        key_pkcs8: Arc::new(key_pkcs8.as_ref().to_vec()),
+        subject: subject.to_string(),
+        codes: Arc::default(),
+    };
+    let routes = Router::new()
+        .route("/.well-known/openid-configuration", get(mock_discovery))
+        .route("/authorize", get(mock_authorize))
+        .route("/token", post(mock_token))
+        .route("/jwks", get(mock_jwks))
+        .with_state(state);
+    let _ = tokio::spawn(async move { axum::serve(listener, routes).await });
+
+    issuer
+}
+
+async fn mock_discovery(State(mock): State<MockIssuer>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "issuer": mock.issuer,
+        "authorization_endpoint": format!("{}/authorize", mock.issuer),
+        "token_endpoint": format!("{}/token", mock.issuer),
+        "jwks_uri": format!("{}/jwks", mock.issuer),
+    }))
+}
+
+/// The user is logged in at once, and sent back with a new code.
+async fn mock_authorize(
+    State(mock): State<MockIssuer>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Redirect, StatusCode> {
+    let param = |name: &str| params.get(name).cloned().ok_or(StatusCode::BAD_REQUEST);
+    if param("client_id")? != MOCK_CLIENT_ID
+        || param("response_type")? != "code"
+        || param("code_challenge_method")? != "S256"
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let code = Uuid::new_v4().to_string();
+    let redirect_uri = param("redirect_uri")?;
+    let location = format!("{redirect_uri}?code={code}&state={}", param("state")?);
+    mock.codes.lock().unwrap().insert(
+        code,
+        MockAuthorization {
+            redirect_uri,
+            code_challenge: param("code_challenge")?,
+            nonce: param("nonce")?,
+        },
+    );
+
+    Ok(Redirect::to(&location))
+}
+
+/// Exchange a code, once, for an id token signed with the key of the jwks.
+async fn mock_token(
+    State(mock): State<MockIssuer>,
+    headers: HeaderMap,
+    Form(params): Form<HashMap<String, String>>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let is_client_authenticated = headers
+        .get(header::AUTHORIZATION)
+        .is_some_and(|value| value == MOCK_BASIC_AUTH);
+    if !is_client_authenticated {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    let param = |name: &str| params.get(name).cloned().unwrap_or_default();
+    let authorization = mock
+        .codes
+        .lock()
+        .unwrap()
+        .remove(&param("code"))
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    if param("grant_type") != "authorization_code"
+        || param("redirect_uri") != authorization.redirect_uri
+        || sha256_into_b64u(&param("code_verifier")) != authorization.code_challenge
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let claims = serde_json::json!({
+        "iss": mock.issuer,
+        "aud": MOCK_CLIENT_ID,
+        "sub": mock.subject,
+        "email": format!("{}@example.com", mock.subject),
+        "email_verified": true,
+        "preferred_username": format!("sso-{}", mock.subject),
+        "nonce": authorization.nonce,
+        "iat": now,
+        "exp": now + 300,
+    });
+    let mut id_token_header = Header::new(Algorithm::EdDSA);
+    id_token_header.kid = Some(MOCK_KID.to_string());
+    let id_token = jsonwebtoken::encode(
+        &id_token_header,
+        &claims,
+        &EncodingKey::from_ed_der(&mock.key_pkcs8),
+    )
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(serde_json::json!({
+        "access_token": Uuid::new_v4().to_string(),
+        "token_type": "Bearer",
+        "id_token": id_token,
+    })))
+}
+
+async fn mock_jwks(State(mock): State<MockIssuer>) -> Json<JwkSet> {
+    let key_pair = Ed25519KeyPair::from_pkcs8(&mock.key_pkcs8).expect("Mock key should be valid");
+
+    Json(JwkSet {
+        keys: vec![Jwk {
+            common: CommonParameters {
+                key_id: Some(MOCK_KID.to_string()),
+                ..Default::default()
+            },
+            algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                key_type: OctetKeyPairType::OctetKeyPair,
+                curve: EllipticCurve::Ed25519,
+                x: base64_url::encode(key_pair.public_key().as_ref()),
+            }),
+        }],
+    })
+}
+
+// endregion: --- Mock Issuer
+
+/// App with the mock issuer as the `mock` provider.
+async fn spawn_app_with_mock_issuer(subject: &str) -> TestApp {
+    let issuer = spawn_mock_issuer(subject).await;
+
+    spawn_app_with(|c| {
+        c.oidc.redirect_base_url = PUBLIC_BASE_URL.to_string();
+        c.oidc.providers = vec![OidcProvider {
+            name: MOCK_PROVIDER.to_string(),
+            issuer,
+            client_id: MOCK_CLIENT_ID.to_string(),
+            client_secret: Box::new(MOCK_CLIENT_SECRET.to_string()).into(),
+            scopes: vec!["openid".to_string(), "email".to_string()],
+        }];
+    })
+    .await
+}
+
+/// The redirections are followed by hand, to carry the cookies.
+fn client_without_redirect() -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .expect("Failed to build the client")
+}
+
+fn location(response: &reqwest::Response) -> String {
+    response
+        .headers()
+        .get(header::LOCATION)
+        .and_then(|location| location.to_str().ok())
+        .expect("Should have a location")
+        .to_string()
+}
+
+/// `name=value` of the cookie set by the response.
+fn set_cookie(response: &reqwest::Response, name: &str) -> Option<String> {
+    response
+        .headers()
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .filter_map(|cookie| cookie.to_str().ok())
+        .filter_map(|cookie| cookie.split(';').next())
+        .find(|cookie| cookie.starts_with(&format!("{name}=")))
+        .map(String::from)
+}
+
+/// Start the flow with `/account/oidc/mock/{action}`, and return the response of the callback.
+async fn run_oidc_flow(app: &TestApp, action: &str, token: Option<&str>) -> reqwest::Response {
+    let client = client_without_redirect();
+
+    let mut request = client.get(&format!(
+        "{}/account/oidc/{MOCK_PROVIDER}/{action}",
+        &app.address
+    ));
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    let response = request.send().await.expect("Failed to execute request.");
+    assert_eq!(response.status(), 303, "Status code should be 303");
+    let flow_cookie = set_cookie(&response, "oidc-flow").expect("Should set the flow cookie");
+    let response = client
+        .get(location(&response))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), 303, "Status code should be 303");
+    let callback = location(&response);
+    assert!(
+        callback.starts_with(PUBLIC_BASE_URL),
+        "Should call back the public url"
+    );
+
+    client
+        .get(callback.replacen(PUBLIC_BASE_URL, &app.address, 1))
+        .header(header::COOKIE, &flow_cookie)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn oidc_login_links_the_identity_and_sets_the_auth_cookie() {
+    // Arrange
+    let subject = Uuid::new_v4().simple().to_string();
+    let app = spawn_app_with_mock_issuer(&subject).await;
+    let client = client_without_redirect();
+
+    // Act
+    let callback = run_oidc_flow(&app, "login", None).await;
+
+    // Assert
+    assert_eq!(callback.status(), 303, "Status code should be 303");
+    assert_eq!(location(&callback), "/", "Should redirect to the home page");
+    let auth_cookie = set_cookie(&callback, "auth-token").expect("Should set the auth cookie");
+    let response = client
+        .get(&format!("{}/account/sessions", &app.address))
+        .header(header::COOKIE, &auth_cookie)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), 200, "Status code should be 200");
+}
+
+#[tokio::test]
+async fn oidc_callback_fails_when_the_state_is_not_matching() {
+    // Arrange
+    let subject = Uuid::new_v4().simple().to_string();
+    let app = spawn_app_with_mock_issuer(&subject).await;
+    let client = client_without_redirect();
+    let response = client
+        .get(&format!(
+            "{}/account/oidc/{MOCK_PROVIDER}/login",
+            &app.address
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let flow_cookie = set_cookie(&response, "oidc-flow").expect("Should set the flow cookie");
+
+    // Act
+    let response = client
+        .get(&format!(
+            "{}/account/oidc/{MOCK_PROVIDER}/callback?code=forged&state=forged",
+            &app.address
+        ))
+        .header(header::COOKIE, &flow_cookie)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status(), 403, "Status code should be 403");
+    assert!(
+        set_cookie(&response, "auth-token").is_none(),
+        "Should not set the auth cookie"
+    );
+}
+
+#[tokio::test]
+async fn oidc_login_fails_for_an_unknown_provider() {
+    // Arrange
+    let app = spawn_app_with_mock_issuer("unused").await;
+
+    // Act
+    let response = client_without_redirect()
+        .get(&format!("{}/account/oidc/unknown/login", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status(), 404, "Status code should be 404");
+}
+
+#[tokio::test]
+async fn oidc_login_fails_when_the_email_belongs_to_a_user() {
+    // Arrange
+    let subject = Uuid::new_v4().simple().to_string();
+    let app = spawn_app_with_mock_issuer(&subject).await;
+    let response = app
+        .post_register(serde_json::json!({
+            "username": "email_owner",
+            "email": format!("{subject}@example.com"),
+            "pwd": TEST_PWD,
+        }))
+        .await;
+    assert_eq!(response.status(), 201, "Status code should be 201");
+
+    // Act
+    let callback = run_oidc_flow(&app, "login", None).await;
+
+    // Assert
+    assert_eq!(callback.status(), 409, "Status code should be 409");
+    assert!(
+        set_cookie(&callback, "auth-token").is_none(),
+        "Should not set the auth cookie"
+    );
+}
+
+#[tokio::test]
+async fn oidc_link_links_the_identity_to_the_logged_in_user() {
+    // Arrange
+    let subject = Uuid::new_v4().simple().to_string();
+    let app = spawn_app_with_mock_issuer(&subject).await;
+    let token = app.register_and_login("link_user").await;
+
+    // Act
+    let linked = run_oidc_flow(&app, "link", Some(&token)).await;
+    let callback = run_oidc_flow(&app, "login", None).await;
+
+    // Assert
+    assert_eq!(linked.status(), 303, "Status code should be 303");
+    assert_eq!(callback.status(), 303, "Status code should be 303");
+    let linked_user: String = sqlx::query_scalar(
+        r#"SELECT u.username FROM "user" u JOIN user_identity i ON i.user_id = u.id WHERE i.subject = $1"#,
+    )
+    .bind(&subject)
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to get the linked user.");
+    assert_eq!(linked_user, "link_user", "Should be linked to the user");
+}
+
+#[tokio::test]
+async fn oidc_login_fails_for_a_user_with_2fa() {
+    // Arrange
+    let subject = Uuid::new_v4().simple().to_string();
+    let app = spawn_app_with_mock_issuer(&subject).await;
+    let token = app.register_and_login("mfa_sso_user").await;
+    let linked = run_oidc_flow(&app, "link", Some(&token)).await;
+    assert_eq!(linked.status(), 303, "Status code should be 303");
+    sqlx::query(r#"UPDATE "user" SET totp_enabled = true WHERE username = 'mfa_sso_user'"#)
+        .execute(&app.db_pool)
+        .await
+        .expect("Failed to enable 2fa.");
+
+    // Act
+    let callback = run_oidc_flow(&app, "login", None).await;
+
+    // Assert
+    assert_eq!(callback.status(), 403, "Status code should be 403");
+    assert!(
+        set_cookie(&callback, "auth-token").is_none(),
+        "Should not set the auth cookie"
+    );
+}
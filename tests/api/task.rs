@@ -0,0 +1,397 @@
+use crate::helpers::{spawn_app, spawn_app_with};
+
+#[tokio::test]
+async fn list_tasks_fails_without_auth() {
+    // Arrange
+    let app = spawn_app().await;
+    let client = reqwest::Client::new();
+
+    // Act
+    let response = client
+        .get(&format!("{}/api/tasks", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status(), 403, "Status code should be 403");
+    assert_eq!(
+        "application/problem+json",
+        response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .unwrap()
+            .to_str()
+            .unwrap()
+    );
+}
+
+#[tokio::test]
+async fn list_tasks_works_with_bearer_token() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.register_and_login("bearer_user").await;
+    let client = reqwest::Client::new();
+
+    // Act
+    let response = client
+        .get(&format!("{}/api/tasks", &app.address))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status(), 200, "Status code should be 200");
+    assert!(
+        response
+            .headers()
+            .get(reqwest::header::SET_COOKIE)
+            .is_none(),
+        "A bearer request should not get a cookie"
+    );
+}
+
+#[tokio::test]
+async fn list_tasks_fails_with_malformed_auth_header() {
+    // Arrange
+    let app = spawn_app().await;
+    let client = reqwest::Client::new();
+
+    // Act
+    let response = client
+        .get(&format!("{}/api/tasks", &app.address))
+        .header(reqwest::header::AUTHORIZATION, "Token some-token")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status(), 403, "Status code should be 403");
+}
+
+#[tokio::test]
+async fn create_task_fails_without_write_permission() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.register_and_login("reader_user").await;
+    sqlx::query(
+        r#"UPDATE user_role SET role_id = (SELECT id FROM role WHERE name = 'reader')
+           WHERE user_id = (SELECT id FROM "user" WHERE username = 'reader_user')"#,
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to change the role.");
+    let client = reqwest::Client::new();
+
+    // Act
+    let list = client
+        .get(&format!("{}/api/tasks", &app.address))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let create = client
+        .post(&format!("{}/api/tasks", &app.address))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "title": "Not allowed" }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let no_auth = client
+        .post(&format!("{}/api/tasks", &app.address))
+        .json(&serde_json::json!({ "title": "Not allowed" }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(list.status(), 200, "Status code should be 200");
+    assert_eq!(create.status(), 403, "Status code should be 403");
+    assert_eq!(no_auth.status(), 403, "Status code should be 403");
+    let create: serde_json::Value = create.json().await.expect("Failed to read body");
+    let no_auth: serde_json::Value = no_auth.json().await.expect("Failed to read body");
+    assert_ne!(
+        create["detail"], no_auth["detail"],
+        "A missing permission should not look like a missing authentication"
+    );
+}
+
+#[tokio::test]
+async fn api_key_is_limited_to_its_scopes() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.register_and_login("api_key_user").await;
+    let client = reqwest::Client::new();
+    let response = client
+        .post(&format!("{}/account/api-keys", &app.address))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "name": "ci", "scopes": ["task:read"] }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), 201, "Status code should be 201");
+    let body: serde_json::Value = response.json().await.expect("Failed to read body");
+    let api_key = body["key"].as_str().expect("Should have a key").to_string();
+    let api_key_id = body["id"].as_i64().expect("Should have an id");
+
+    // Act
+    let list = client
+        .get(&format!("{}/api/tasks", &app.address))
+        .header("X-API-Key", &api_key)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let create = client
+        .post(&format!("{}/api/tasks", &app.address))
+        .header("X-API-Key", &api_key)
+        .json(&serde_json::json!({ "title": "Not in scope" }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let revoke = client
+        .delete(&format!("{}/account/api-keys/{api_key_id}", &app.address))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let list_revoked = client
+        .get(&format!("{}/api/tasks", &app.address))
+        .header("X-API-Key", &api_key)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(list.status(), 200, "Status code should be 200");
+    assert_eq!(create.status(), 403, "Status code should be 403");
+    assert_eq!(revoke.status(), 204, "Status code should be 204");
+    assert_eq!(list_revoked.status(), 403, "Status code should be 403");
+}
+
+#[tokio::test]
+async fn task_of_another_user_is_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+    let owner_token = app.register_and_login("owner_user").await;
+    let other_token = app.register_and_login("other_user").await;
+    let client = reqwest::Client::new();
+    let response = client
+        .post(&format!("{}/api/tasks", &app.address))
+        .bearer_auth(&owner_token)
+        .json(&serde_json::json!({ "title": "Private" }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), 201, "Status code should be 201");
+    let body: serde_json::Value = response.json().await.expect("Failed to read body");
+    let task_uri = format!(
+        "{}/api/tasks/{}",
+        &app.address,
+        body["id"].as_i64().expect("Should have an id")
+    );
+
+    // Act
+    let get = client
+        .get(&task_uri)
+        .bearer_auth(&other_token)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let patch = client
+        .patch(&task_uri)
+        .bearer_auth(&other_token)
+        .json(&serde_json::json!({ "title": "Taken" }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let delete = client
+        .delete(&task_uri)
+        .bearer_auth(&other_token)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let owner_get = client
+        .get(&task_uri)
+        .bearer_auth(&owner_token)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(get.status(), 404, "Status code should be 404");
+    assert_eq!(patch.status(), 404, "Status code should be 404");
+    assert_eq!(delete.status(), 404, "Status code should be 404");
+    assert_eq!(owner_get.status(), 200, "Status code should be 200");
+    let body: serde_json::Value = owner_get.json().await.expect("Failed to read body");
+    assert_eq!(body["title"], "Private", "Should not have been updated");
+}
+
+#[tokio::test]
+async fn update_task_fails_with_a_stale_if_match() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.register_and_login("etag_user").await;
+    let client = reqwest::Client::new();
+    let response = client
+        .post(&format!("{}/api/tasks", &app.address))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "title": "First" }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), 201, "Status code should be 201");
+    let stale_etag = response
+        .headers()
+        .get(reqwest::header::ETAG)
+        .expect("Should have an ETag")
+        .clone();
+    let body: serde_json::Value = response.json().await.expect("Failed to read body");
+    let task_uri = format!(
+        "{}/api/tasks/{}",
+        &app.address,
+        body["id"].as_i64().expect("Should have an id")
+    );
+    let response = client
+        .patch(&task_uri)
+        .bearer_auth(&token)
+        .header(reqwest::header::IF_MATCH, stale_etag.clone())
+        .json(&serde_json::json!({ "title": "Second" }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), 200, "Status code should be 200");
+
+    // Act
+    let response = client
+        .patch(&task_uri)
+        .bearer_auth(&token)
+        .header(reqwest::header::IF_MATCH, stale_etag)
+        .json(&serde_json::json!({ "title": "Lost update" }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status(), 409, "Status code should be 409");
+    let task = client
+        .get(&task_uri)
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let task: serde_json::Value = task.json().await.expect("Failed to read body");
+    assert_eq!(task["title"], "Second", "Should not have been updated");
+}
+
+/// `result` of a successful JSON-RPC response.
+async fn rpc_result(response: reqwest::Response) -> serde_json::Value {
+    let body: serde_json::Value = response.json().await.expect("Failed to read body");
+    assert!(body["error"].is_null(), "Should not fail: {body}");
+    body["result"].clone()
+}
+
+#[tokio::test]
+async fn deleted_task_can_be_listed_and_restored() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.register_and_login("trash_user").await;
+    let task = rpc_result(
+        app.post_rpc(
+            &token,
+            "create_task",
+            serde_json::json!({ "data": { "title": "To trash" } }),
+        )
+        .await,
+    )
+    .await;
+    let id = task["id"].as_i64().expect("Should have an id");
+
+    // Act
+    let deleted = rpc_result(
+        app.post_rpc(&token, "delete_task", serde_json::json!({ "id": id }))
+            .await,
+    )
+    .await;
+    let trash = rpc_result(
+        app.post_rpc(&token, "list_deleted_tasks", serde_json::json!({}))
+            .await,
+    )
+    .await;
+    let tasks = rpc_result(
+        app.post_rpc(&token, "list_tasks", serde_json::json!({}))
+            .await,
+    )
+    .await;
+    let restored = rpc_result(
+        app.post_rpc(&token, "restore_task", serde_json::json!({ "id": id }))
+            .await,
+    )
+    .await;
+    let trash_after_restore = rpc_result(
+        app.post_rpc(&token, "list_deleted_tasks", serde_json::json!({}))
+            .await,
+    )
+    .await;
+
+    // Assert
+    assert!(
+        deleted["deleted_at"].is_string(),
+        "Should return the task as deleted"
+    );
+    assert_eq!(trash["items"][0]["id"], id, "Should be in the trash");
+    assert_eq!(
+        tasks,
+        serde_json::json!([]),
+        "Should be hidden from the list"
+    );
+    assert!(restored["deleted_at"].is_null(), "Should be restored");
+    assert_eq!(
+        trash_after_restore["items"],
+        serde_json::json!([]),
+        "Should have left the trash"
+    );
+}
+
+#[tokio::test]
+async fn deleted_task_is_purged_after_the_retention() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.trash.retention_sec = 0;
+        c.trash.purge_interval_sec = 1;
+    })
+    .await;
+    let token = app.register_and_login("purge_user").await;
+    let task = rpc_result(
+        app.post_rpc(
+            &token,
+            "create_task",
+            serde_json::json!({ "data": { "title": "To purge" } }),
+        )
+        .await,
+    )
+    .await;
+    let id = task["id"].as_i64().expect("Should have an id");
+    rpc_result(
+        app.post_rpc(&token, "delete_task", serde_json::json!({ "id": id }))
+            .await,
+    )
+    .await;
+
+    // Act
+    tokio::time::sleep(std::time::Duration::from_millis(2500)).await;
+
+    // Assert
+    let trash = rpc_result(
+        app.post_rpc(&token, "list_deleted_tasks", serde_json::json!({}))
+            .await,
+    )
+    .await;
+    assert_eq!(trash["items"], serde_json::json!([]), "Should be purged");
+    let purged: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM task WHERE id = $1")
+        .bind(id)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to count the tasks.");
+    assert_eq!(purged, 0, "Should be deleted from the db");
+}